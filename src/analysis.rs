@@ -0,0 +1,341 @@
+use rand::Rng;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::blueprints::Blueprints;
+use crate::chromosome::EgpChromosome;
+use crate::evolution::phenotype_hash;
+use crate::operators::{mutate_with_rng, Mutation};
+use crate::simplify::Expr;
+use crate::{express_with, ComponentIndex, ExpressionConfig, Phenotype};
+
+/// Approximate tree edit distance between two expressions
+///
+/// Arguments are aligned by position, a relabeled node costs one and unaligned subtrees cost
+/// their size. This is an upper bound of the tree edit distance.
+pub fn edit_size(a: &Expr, b: &Expr) -> usize {
+    let relabel = if a.label == b.label { 0 } else { 1 };
+
+    let aligned: usize = a
+        .arguments
+        .iter()
+        .zip(b.arguments.iter())
+        .map(|(a, b)| edit_size(a, b))
+        .sum();
+
+    let n_aligned = a.arguments.len().min(b.arguments.len());
+    let unaligned: usize = a.arguments[n_aligned..]
+        .iter()
+        .chain(b.arguments[n_aligned..].iter())
+        .map(Expr::size)
+        .sum();
+
+    relabel + aligned + unaligned
+}
+
+/// How the phenotype responded to mutations of one gene
+#[derive(Debug, Clone, Default)]
+pub struct GeneSensitivity {
+    pub samples: usize,
+    /// Mutations which changed the phenotype
+    pub changed: usize,
+    pub total_edit_size: usize,
+}
+
+impl GeneSensitivity {
+    /// Fraction of mutations of this gene which changed the phenotype
+    pub fn sensitivity(&self) -> f64 {
+        if self.samples == 0 {
+            0.
+        } else {
+            self.changed as f64 / self.samples as f64
+        }
+    }
+
+    pub fn mean_edit_size(&self) -> f64 {
+        if self.samples == 0 {
+            0.
+        } else {
+            self.total_edit_size as f64 / self.samples as f64
+        }
+    }
+}
+
+/// Response of a chromosome's phenotype to sampled single-point mutations
+#[derive(Debug, Clone, Default)]
+pub struct Robustness {
+    pub samples: usize,
+    /// Mutations which left the phenotype structurally identical
+    pub neutral: usize,
+    /// Number of mutations by phenotype edit size
+    pub edit_sizes: BTreeMap<usize, usize>,
+    /// Sensitivity by mutated gene, `ComponentIndex::Output` for the output component
+    pub genes: HashMap<ComponentIndex, GeneSensitivity>,
+}
+
+impl Robustness {
+    /// Fraction of neutral mutations
+    pub fn neutrality(&self) -> f64 {
+        if self.samples == 0 {
+            0.
+        } else {
+            self.neutral as f64 / self.samples as f64
+        }
+    }
+
+    pub fn mean_edit_size(&self) -> f64 {
+        if self.samples == 0 {
+            return 0.;
+        }
+
+        let total: usize = self
+            .edit_sizes
+            .iter()
+            .map(|(size, count)| size * count)
+            .sum();
+
+        total as f64 / self.samples as f64
+    }
+}
+
+fn mutated_gene(mutation: &Mutation) -> Option<ComponentIndex> {
+    match *mutation {
+        Mutation::Unchanged => None,
+        Mutation::Activity { group, index }
+        | Mutation::BindingSite { group, index, .. }
+        | Mutation::Constant { group, index } => Some(ComponentIndex::Regular(group, index)),
+        Mutation::OutputBindingSite { output, .. } => Some(ComponentIndex::Output(output)),
+    }
+}
+
+/// Samples single-point mutations of a chromosome with `mutate`, measuring their effect
+pub fn robustness(
+    blueprints: &Blueprints,
+    chromosome: &EgpChromosome,
+    samples: usize,
+) -> Robustness {
+    robustness_with(
+        &mut rand::thread_rng(),
+        blueprints,
+        chromosome,
+        samples,
+        &ExpressionConfig::default(),
+    )
+}
+
+/// Measures robustness like `robustness`, expressing with the given parameters and drawing the
+/// mutations from the given random number generator
+pub fn robustness_with<R: Rng + ?Sized>(
+    rng: &mut R,
+    blueprints: &Blueprints,
+    chromosome: &EgpChromosome,
+    samples: usize,
+    config: &ExpressionConfig,
+) -> Robustness {
+    let phenotype = express_with(blueprints, chromosome, config);
+    let hash = phenotype_hash(&phenotype);
+    let expr = Expr::from_phenotype(&phenotype);
+
+    let mut result = Robustness::default();
+
+    for _ in 0..samples {
+        let mut mutant = chromosome.clone();
+        let mutation = mutate_with_rng(rng, blueprints, &mut mutant);
+
+        let mutant_phenotype = express_with(blueprints, &mutant, config);
+        let neutral = phenotype_hash(&mutant_phenotype) == hash;
+        let edit = match (&expr, Expr::from_phenotype(&mutant_phenotype)) {
+            (Some(a), Some(b)) => edit_size(a, &b),
+            _ => 0,
+        };
+
+        result.samples += 1;
+        if neutral {
+            result.neutral += 1;
+        }
+        *result.edit_sizes.entry(edit).or_insert(0) += 1;
+
+        if let Some(gene) = mutated_gene(&mutation) {
+            let sensitivity = result.genes.entry(gene).or_default();
+            sensitivity.samples += 1;
+            if !neutral {
+                sensitivity.changed += 1;
+            }
+            sensitivity.total_edit_size += edit;
+        }
+    }
+
+    result
+}
+
+/// Regular components expressed in a phenotype, as `(group, index)`
+pub fn expressed_components(phenotype: &Phenotype) -> HashSet<(usize, usize)> {
+    phenotype
+        .node_weights()
+        .filter_map(|expressed| match expressed.index {
+            ComponentIndex::Regular(group, index) => Some((group, index)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Expressed and unexpressed regular components of a group
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupBloat {
+    pub total: usize,
+    pub expressed: usize,
+    pub unexpressed: Vec<usize>,
+}
+
+impl GroupBloat {
+    /// Fraction of the group that isn't expressed
+    pub fn ratio(&self) -> f64 {
+        if self.total == 0 {
+            0.
+        } else {
+            self.unexpressed.len() as f64 / self.total as f64
+        }
+    }
+}
+
+/// Bloat statistics for each group of a chromosome, given its phenotype
+pub fn bloat(chromosome: &EgpChromosome, phenotype: &Phenotype) -> Vec<GroupBloat> {
+    let expressed = expressed_components(phenotype);
+
+    chromosome
+        .regular
+        .iter()
+        .enumerate()
+        .map(|(group, components)| {
+            let unexpressed: Vec<usize> = (0..components.len())
+                .filter(|index| !expressed.contains(&(group, *index)))
+                .collect();
+
+            GroupBloat {
+                total: components.len(),
+                expressed: components.len() - unexpressed.len(),
+                unexpressed,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::express;
+    use crate::testing;
+
+    fn parse(source: &str) -> Expr {
+        Expr::parse(source).unwrap()
+    }
+
+    #[test]
+    fn edit_size_aligns_by_position() {
+        assert_eq!(edit_size(&parse("(+ x 1)"), &parse("(+ x 1)")), 0);
+        assert_eq!(edit_size(&parse("(+ x 1)"), &parse("(* x 1)")), 1);
+        assert_eq!(edit_size(&parse("(+ x 1)"), &parse("(+ 1 x)")), 2);
+        // the unaligned (neg x) costs its size
+        assert_eq!(edit_size(&parse("(use x)"), &parse("(use x (neg x))")), 2);
+    }
+
+    #[test]
+    fn robustness_accounts_for_every_sample() {
+        let blueprints = testing::arithmetic();
+
+        for chromosome in testing::ancestors(&blueprints, 1, 5) {
+            let robustness = robustness(&blueprints, &chromosome, 50);
+
+            assert_eq!(robustness.samples, 50);
+            assert_eq!(robustness.edit_sizes.values().sum::<usize>(), 50);
+            // structurally identical phenotypes have no edits
+            assert!(robustness.edit_sizes.get(&0).cloned().unwrap_or(0) >= robustness.neutral);
+
+            let genes: Vec<&GeneSensitivity> = robustness.genes.values().collect();
+            assert!(genes.iter().map(|gene| gene.samples).sum::<usize>() <= 50);
+            assert!(genes.iter().all(|gene| gene.changed <= gene.samples));
+            assert_eq!(
+                robustness.samples - robustness.neutral,
+                robustness
+                    .genes
+                    .values()
+                    .map(|gene| gene.changed)
+                    .sum::<usize>()
+            );
+        }
+    }
+
+    #[test]
+    fn seeded_robustness_is_deterministic() {
+        let blueprints = testing::arithmetic();
+        let config = testing::expression();
+
+        for chromosome in testing::ancestors(&blueprints, 3, 5) {
+            let measure = |seed| {
+                let mut rng = testing::rng(seed);
+                robustness_with(&mut rng, &blueprints, &chromosome, 30, &config)
+            };
+            let (a, b) = (measure(4), measure(4));
+
+            assert_eq!(a.neutral, b.neutral);
+            assert_eq!(a.edit_sizes, b.edit_sizes);
+            for (gene, sensitivity) in a.genes.iter() {
+                assert_eq!(sensitivity.changed, b.genes[gene].changed);
+                assert_eq!(sensitivity.total_edit_size, b.genes[gene].total_edit_size);
+            }
+        }
+    }
+
+    #[test]
+    fn edit_sizes_cover_every_output() {
+        let blueprints = testing::two_outputs();
+        let mut rng = testing::rng(7);
+        let mut second = GeneSensitivity::default();
+
+        // four regulars besides the outputs and terminals, so that the outputs are mutated often
+        for _ in 0..5 {
+            let chromosome =
+                EgpChromosome::ancestor_from_blueprints_with_rng(&mut rng, &blueprints, 8);
+            let robustness = robustness_with(
+                &mut rng,
+                &blueprints,
+                &chromosome,
+                100,
+                &ExpressionConfig::default(),
+            );
+
+            if let Some(gene) = robustness.genes.get(&ComponentIndex::Output(1)) {
+                second.changed += gene.changed;
+                second.total_edit_size += gene.total_edit_size;
+            }
+        }
+
+        // the second output is a root of its own
+        assert!(second.changed > 0);
+        assert!(second.total_edit_size > 0);
+    }
+
+    #[test]
+    fn bloat_partitions_each_group() {
+        let blueprints = testing::arithmetic();
+
+        for chromosome in testing::ancestors(&blueprints, 2, 10) {
+            let phenotype = express(&blueprints, &chromosome);
+            let expressed = expressed_components(&phenotype);
+            let bloat = bloat(&chromosome, &phenotype);
+
+            assert_eq!(bloat.len(), chromosome.regular.len());
+            for (group, stats) in bloat.iter().enumerate() {
+                assert_eq!(stats.total, chromosome.regular[group].len());
+                assert_eq!(stats.expressed + stats.unexpressed.len(), stats.total);
+                assert_eq!(
+                    stats.expressed,
+                    expressed.iter().filter(|(g, _)| *g == group).count()
+                );
+                assert!(stats
+                    .unexpressed
+                    .iter()
+                    .all(|index| !expressed.contains(&(group, *index))));
+            }
+        }
+    }
+}
@@ -1,12 +1,14 @@
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::iter;
 
 // circular imports are for convenience
 use crate::chromosome::EgpChromosome;
 use crate::component::Component;
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Blueprints {
-    pub output: Blueprint, // output always belongs to first group
+    #[serde(alias = "output", deserialize_with = "crate::chromosome::one_or_many")]
+    pub outputs: Vec<Blueprint>, // outputs always belong to first group
     pub regular: Vec<Vec<Blueprint>>,
     pub terminal: Vec<Vec<Component>>,
     pub activities_by_group: Vec<usize>, // number of activities in each group (output not counted)
@@ -36,7 +38,7 @@ impl Blueprints {
         Blueprints::recompute_activities_counter(terminal, &mut activity_n);
     }
 
-    pub fn sum_group_lens<T>(groups: &Vec<Vec<T>>) -> usize {
+    pub fn sum_group_lens<T>(groups: &[Vec<T>]) -> usize {
         groups.iter().map(|group| group.len()).sum()
     }
 
@@ -45,6 +47,16 @@ impl Blueprints {
         regular: Vec<Vec<Blueprint>>,
         terminal: Vec<Vec<Blueprint>>,
         weak_map: HashMap<String, String>,
+    ) -> Blueprints {
+        Blueprints::with_outputs(vec![output], regular, terminal, weak_map)
+    }
+
+    /// Blueprints of chromosomes with one output component per output blueprint
+    pub fn with_outputs(
+        outputs: Vec<Blueprint>,
+        regular: Vec<Vec<Blueprint>>,
+        terminal: Vec<Vec<Blueprint>>,
+        weak_map: HashMap<String, String>,
     ) -> Blueprints {
         let activities_by_group: Vec<usize> = regular
             .iter()
@@ -56,20 +68,14 @@ impl Blueprints {
 
         let terminal: Vec<Vec<Component>> = terminal
             .iter()
-            .map(|bps| {
-                EgpChromosome::make_group(
-                    bps,
-                    &iter::repeat(1).take(bps.len()).collect(),
-                    total_activities,
-                )
-            })
+            .map(|bps| EgpChromosome::make_group(bps, &vec![1; bps.len()], total_activities))
             .collect();
 
         let number_of_regulars = Blueprints::sum_group_lens(&regular);
         let number_of_terminals = Blueprints::sum_group_lens(&terminal);
 
         Blueprints {
-            output,
+            outputs,
             regular,
             terminal,
             activities_by_group,
@@ -83,6 +89,7 @@ impl Blueprints {
 
 // activity is computed from index in group
 // group membership is positional (in Vec<Vec<Blueprint>>)
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Blueprint {
     pub activity: usize,
     pub label: String,
@@ -91,6 +98,38 @@ pub struct Blueprint {
     pub binding_sites: Vec<usize>, // group indices
     /// Weak bindings are left as stubs for post-processing, this will build a tree
     pub weak_binding_sites: Vec<usize>, // group indices
+    /// Ephemeral random constants are regular components without binding sites carrying a value,
+    /// so each gene holds its own constant
+    #[serde(default)]
+    pub ephemeral: Option<Ephemeral>,
+}
+
+/// Range of the value drawn for a new ephemeral random constant and the standard deviation of
+/// its Gaussian perturbation
+///
+/// Perturbed values are reflected back into the range, so constants never leave it.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Ephemeral {
+    pub min: f64,
+    pub max: f64,
+    pub sigma: f64,
+}
+
+impl Ephemeral {
+    /// Folds a value into `min..=max`, as if reflected at the bounds
+    pub fn reflect(&self, value: f64) -> f64 {
+        let width = self.max - self.min;
+        if width <= 0. {
+            return self.min;
+        }
+
+        let offset = (value - self.min).rem_euclid(2. * width);
+        if offset > width {
+            self.max - (offset - width)
+        } else {
+            self.min + offset
+        }
+    }
 }
 
 impl Blueprint {
@@ -100,6 +139,7 @@ impl Blueprint {
             label: String::from(label),
             binding_sites: vec![],
             weak_binding_sites: vec![],
+            ephemeral: None,
         }
     }
 
@@ -109,6 +149,7 @@ impl Blueprint {
             label: String::from(label),
             binding_sites: vec![0],
             weak_binding_sites: vec![],
+            ephemeral: None,
         }
     }
 
@@ -118,6 +159,18 @@ impl Blueprint {
             label: String::from(label),
             binding_sites: vec![0, 0],
             weak_binding_sites: vec![],
+            ephemeral: None,
+        }
+    }
+
+    /// Blueprint of an ephemeral random constant, to be placed among the regular blueprints
+    pub fn ephemeral(label: &str, min: f64, max: f64, sigma: f64) -> Blueprint {
+        Blueprint {
+            activity: 0,
+            label: String::from(label),
+            binding_sites: vec![],
+            weak_binding_sites: vec![],
+            ephemeral: Some(Ephemeral { min, max, sigma }),
         }
     }
 
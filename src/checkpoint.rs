@@ -0,0 +1,273 @@
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::blueprints::Blueprints;
+use crate::evolution::{Config, FitnessCache, Individual};
+use crate::lineage::{IndividualId, Lineage};
+use crate::novelty::BehaviourArchive;
+use crate::speciation::SpeciesSet;
+use crate::statistics::Statistics;
+
+/// State of an evolutionary run, see `Evolution::state` and `Evolution::resume`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunState {
+    pub config: Config,
+    pub population: Vec<Individual>,
+    pub generation: usize,
+    pub cache: FitnessCache,
+    pub lineage: Option<Lineage>,
+    pub rng: Pcg32,
+    pub statistics: Vec<Statistics>,
+    pub evaluations: usize,
+    pub species: Option<SpeciesSet>,
+    pub archive: Option<BehaviourArchive>,
+    pub next_id: IndividualId,
+}
+
+/// Everything needed to continue a run, stored as JSON
+///
+/// JSON has no numbers for infinities and NaN, so fitness values and other floats of the run
+/// that may not be finite are written as the strings `"inf"`, `"-inf"` and `"NaN"`, see `float`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub blueprints: Blueprints,
+    pub state: RunState,
+}
+
+impl Checkpoint {
+    /// Writes the checkpoint, replacing an existing file only once writing has succeeded
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        fs::rename(&temporary, path)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Checkpoint> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+}
+
+/// Serde helpers for floats that may not be finite, written as strings in that case
+pub(crate) mod float {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Encoded {
+        Number(f64),
+        Text(String),
+    }
+
+    struct Float(f64);
+
+    impl Serialize for Float {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serialize(&self.0, serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Float {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Float, D::Error> {
+            deserialize(deserializer).map(Float)
+        }
+    }
+
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if value.is_finite() {
+            serializer.serialize_f64(*value)
+        } else {
+            serializer.collect_str(value)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        match Encoded::deserialize(deserializer)? {
+            Encoded::Number(value) => Ok(value),
+            Encoded::Text(text) => text.parse().map_err(D::Error::custom),
+        }
+    }
+
+    pub mod vec {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(values: &[f64], serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_seq(values.iter().map(|&value| Float(value)))
+        }
+
+        pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<f64>, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            let values = Vec::<Float>::deserialize(deserializer)?;
+            Ok(values.into_iter().map(|value| value.0).collect())
+        }
+    }
+
+    pub mod nested {
+        use super::*;
+
+        pub fn serialize<S>(values: &[Vec<f64>], serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            serializer.collect_seq(
+                values
+                    .iter()
+                    .map(|inner| inner.iter().map(|&value| Float(value)).collect::<Vec<_>>()),
+            )
+        }
+
+        pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<Vec<f64>>, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            let values = Vec::<Vec<Float>>::deserialize(deserializer)?;
+            Ok(values
+                .into_iter()
+                .map(|inner| inner.into_iter().map(|value| value.0).collect())
+                .collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evolution::{Evolution, Fitness, WithBehaviour};
+    use crate::novelty::NoveltyConfig;
+    use crate::testing;
+    use crate::Phenotype;
+
+    // fractional fitness values, which only survive JSON with `float_roundtrip`
+    fn fitness(phenotype: &Phenotype) -> f64 {
+        (testing::size(phenotype) + 0.1).ln()
+    }
+
+    /// Runs `config` both at once and interrupted by a checkpoint, comparing the final states
+    fn assert_resumes_exactly<F, S>(config: Config, fitness: F, name: &str)
+    where
+        F: Fn(&Phenotype) -> S + Copy,
+        S: Fitness,
+    {
+        let blueprints = testing::arithmetic();
+
+        let mut uninterrupted = Evolution::new(&blueprints, config.clone(), fitness);
+        uninterrupted.run();
+
+        let path = std::env::temp_dir().join(format!(
+            "egp-checkpoint-{}-{}.json",
+            name,
+            std::process::id()
+        ));
+        let mut interrupted = Evolution::new(&blueprints, config, fitness);
+        for _ in 0..3 {
+            interrupted.step();
+        }
+        interrupted.checkpoint().save(&path).unwrap();
+        drop(interrupted);
+
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let mut resumed = Evolution::resume(&checkpoint.blueprints, checkpoint.state, fitness);
+        resumed.run();
+
+        // compared as JSON values, the fitness cache is a map without a fixed order
+        assert_eq!(
+            serde_json::to_value(resumed.state()).unwrap(),
+            serde_json::to_value(uninterrupted.state()).unwrap()
+        );
+    }
+
+    #[test]
+    fn resumed_run_matches_uninterrupted_run() {
+        let config = Config {
+            generations: 6,
+            track_lineage: true,
+            cache_capacity: Some(30),
+            ..testing::config(11)
+        };
+
+        assert_resumes_exactly(config, fitness, "plain");
+    }
+
+    #[test]
+    fn infinite_fitness_survives_a_checkpoint() {
+        let blueprints = testing::arithmetic();
+        let mut evolution = Evolution::new(&blueprints, testing::config(13), fitness);
+        evolution.population[0].fitness = f64::NEG_INFINITY;
+        evolution.population[1].fitness = f64::INFINITY;
+
+        let path = std::env::temp_dir().join(format!(
+            "egp-checkpoint-infinite-{}.json",
+            std::process::id()
+        ));
+        evolution.checkpoint().save(&path).unwrap();
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let fitness = |population: &[Individual]| -> Vec<f64> {
+            population
+                .iter()
+                .map(|individual| individual.fitness)
+                .collect()
+        };
+        assert_eq!(
+            fitness(&checkpoint.state.population),
+            fitness(&evolution.population)
+        );
+        assert_eq!(checkpoint.state.population[0].fitness, f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn resumed_run_with_infinite_fitness_matches_uninterrupted_run() {
+        let config = Config {
+            generations: 6,
+            ..testing::config(14)
+        };
+
+        // phenotypes with an odd number of nodes are invalid
+        assert_resumes_exactly(
+            config,
+            |phenotype: &Phenotype| {
+                if phenotype.node_count() % 2 == 1 {
+                    f64::NEG_INFINITY
+                } else {
+                    fitness(phenotype)
+                }
+            },
+            "infinite",
+        );
+    }
+
+    #[test]
+    fn resumed_novelty_search_matches_uninterrupted_run() {
+        let config = Config {
+            generations: 6,
+            novelty: Some(NoveltyConfig {
+                archive_threshold: 0.5,
+                archive_probability: 0.2,
+                ..Default::default()
+            }),
+            ..testing::config(12)
+        };
+
+        assert_resumes_exactly(
+            config,
+            |phenotype: &Phenotype| WithBehaviour {
+                fitness: fitness(phenotype),
+                behaviour: vec![testing::size(phenotype), phenotype.edge_count() as f64],
+            },
+            "novelty",
+        );
+    }
+}
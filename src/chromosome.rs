@@ -1,5 +1,5 @@
 use rand::Rng;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::iter;
 
 use crate::blueprints::{Blueprint, Blueprints};
@@ -7,10 +7,31 @@ use crate::component::Component;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct EgpChromosome {
-    pub output: Component,
+    /// One component per output blueprint, in the same order
+    #[serde(alias = "output", deserialize_with = "one_or_many")]
+    pub outputs: Vec<Component>,
     pub regular: Vec<Vec<Component>>,
 }
 
+/// Reads a list, or the single value written by versions before 0.2 in its place
+pub(crate) fn one_or_many<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany<T> {
+        Many(Vec<T>),
+        One(T),
+    }
+
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::Many(values) => values,
+        OneOrMany::One(value) => vec![value],
+    })
+}
+
 impl EgpChromosome {
     pub fn make_many(bp: &Blueprint, n: usize, total_activities: usize) -> Vec<Component> {
         iter::repeat_with(|| Component::from_blueprint(bp, total_activities))
@@ -19,20 +40,43 @@ impl EgpChromosome {
     }
 
     pub fn make_group(
-        blueprints: &Vec<Blueprint>,
-        distribution: &Vec<usize>,
+        blueprints: &[Blueprint],
+        distribution: &[usize],
         total_activities: usize,
     ) -> Vec<Component> {
         blueprints
             .iter()
             .zip(distribution.iter())
-            .map(|(bp, n)| EgpChromosome::make_many(bp, *n, total_activities))
-            .flatten()
+            .flat_map(|(bp, n)| EgpChromosome::make_many(bp, *n, total_activities))
             .collect()
     }
 
-    fn distribution(number_of_regulars: usize, size: usize) -> Vec<usize> {
-        let mut rng = rand::thread_rng();
+    pub fn make_group_with_rng<R: Rng + ?Sized>(
+        rng: &mut R,
+        blueprints: &[Blueprint],
+        distribution: &[usize],
+        total_activities: usize,
+    ) -> Vec<Component> {
+        let mut result = vec![];
+
+        for (bp, n) in blueprints.iter().zip(distribution.iter()) {
+            for _ in 0..*n {
+                result.push(Component::from_blueprint_with_rng(
+                    rng,
+                    bp,
+                    total_activities,
+                ));
+            }
+        }
+
+        result
+    }
+
+    fn distribution<R: Rng + ?Sized>(
+        rng: &mut R,
+        number_of_regulars: usize,
+        size: usize,
+    ) -> Vec<usize> {
         let distribution: Vec<f64> = iter::repeat_with(|| rng.gen::<f64>())
             .take(number_of_regulars)
             .collect();
@@ -58,27 +102,88 @@ impl EgpChromosome {
         blueprints: &Blueprints,
         size: usize,
     ) -> EgpChromosome {
+        EgpChromosome::ancestor_from_blueprints_with_rng(&mut rand::thread_rng(), blueprints, size)
+    }
+
+    pub fn ancestor_from_blueprints_with_rng<R: Rng + ?Sized>(
+        rng: &mut R,
+        blueprints: &Blueprints,
+        size: usize,
+    ) -> EgpChromosome {
+        let n_outputs = blueprints.outputs.len();
+
         assert!(
-            size > blueprints.number_of_terminals + 1,
+            size > blueprints.number_of_terminals + n_outputs,
             "need size > {}",
-            blueprints.number_of_terminals + 1
+            blueprints.number_of_terminals + n_outputs
         );
 
         let regulars_distribution = EgpChromosome::distribution(
+            rng,
             blueprints.number_of_regulars,
-            size - 1 - blueprints.number_of_terminals,
+            size - n_outputs - blueprints.number_of_terminals,
         );
 
-        let regular: Vec<Vec<Component>> = blueprints
-            .regular
+        // the distribution covers the blueprints of all groups in order
+        let mut start = 0;
+        let mut regular: Vec<Vec<Component>> = vec![];
+        for bps in &blueprints.regular {
+            regular.push(EgpChromosome::make_group_with_rng(
+                rng,
+                bps,
+                &regulars_distribution[start..start + bps.len()],
+                blueprints.total_activities,
+            ));
+            start += bps.len();
+        }
+
+        let outputs = blueprints
+            .outputs
             .iter()
-            .map(|bps| {
-                EgpChromosome::make_group(bps, &regulars_distribution, blueprints.total_activities)
+            .map(|output| {
+                Component::from_blueprint_with_rng(rng, output, blueprints.total_activities)
             })
             .collect();
 
-        let output = Component::from_blueprint(&blueprints.output, blueprints.total_activities);
+        EgpChromosome { outputs, regular }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use serde_json::Value;
+
+    /// Rewrites the serialized form to the one of versions before 0.2, without `added` fields
+    /// in the regular groups
+    fn single_output(mut value: Value, added: &str) -> Value {
+        let outputs = value.as_object_mut().unwrap().remove("outputs").unwrap();
+        value["output"] = outputs[0].clone();
+
+        for group in value["regular"].as_array_mut().unwrap() {
+            for member in group.as_array_mut().unwrap() {
+                member.as_object_mut().unwrap().remove(added);
+            }
+        }
+
+        value
+    }
+
+    #[test]
+    fn single_output_formats_are_read() {
+        let blueprints = testing::arithmetic();
+        let chromosome = testing::ancestors(&blueprints, 1, 1).remove(0);
+
+        let old = single_output(serde_json::to_value(&chromosome).unwrap(), "value");
+        assert_eq!(
+            serde_json::from_value::<EgpChromosome>(old).unwrap(),
+            chromosome
+        );
 
-        EgpChromosome { output, regular }
+        let current = serde_json::to_value(&blueprints).unwrap();
+        let read: Blueprints =
+            serde_json::from_value(single_output(current.clone(), "ephemeral")).unwrap();
+        assert_eq!(serde_json::to_value(&read).unwrap(), current);
     }
 }
@@ -0,0 +1,316 @@
+use petgraph::graph::NodeIndex;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::pretty::{arguments, output_nodes, shared_name, shared_nodes};
+use crate::Phenotype;
+
+/// Signature of a generated Rust function
+pub struct Signature {
+    pub name: String,
+    pub parameters: Vec<(String, String)>, // name, type
+    pub return_type: String,
+}
+
+impl Signature {
+    pub fn new(name: &str, parameters: &[(&str, &str)], return_type: &str) -> Signature {
+        Signature {
+            name: String::from(name),
+            parameters: parameters
+                .iter()
+                .map(|(name, ty)| (String::from(*name), String::from(*ty)))
+                .collect(),
+            return_type: String::from(return_type),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodegenError {
+    /// No template was given for a label
+    MissingTemplate(String),
+    /// A weak binding refers to an ancestor, which can't be a local variable
+    Cycle(NodeIndex),
+    /// A template refers to an argument the node doesn't have, e.g. an unsatisfied weak binding
+    MissingArgument(String, usize),
+    /// The phenotype has no output node
+    NoOutput,
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodegenError::MissingTemplate(label) => write!(f, "no template for label {}", label),
+            CodegenError::Cycle(node) => {
+                write!(f, "weak binding cycle through node {}", node.index())
+            }
+            CodegenError::MissingArgument(label, index) => {
+                write!(f, "{} has no argument {}", label, index)
+            }
+            CodegenError::NoOutput => write!(f, "phenotype has no output node"),
+        }
+    }
+}
+
+impl std::error::Error for CodegenError {}
+
+/// Fills `{0}`, `{1}`, ... in a template with arguments, compound arguments are parenthesized
+fn fill(label: &str, template: &str, arguments: &[String]) -> Result<String, CodegenError> {
+    let mut result = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+
+        let placeholder = after
+            .find('}')
+            .and_then(|end| after[..end].parse::<usize>().ok().map(|index| (index, end)));
+
+        match placeholder {
+            Some((index, _end)) if index >= arguments.len() => {
+                return Err(CodegenError::MissingArgument(String::from(label), index));
+            }
+            Some((index, end)) => {
+                let argument = &arguments[index];
+                if !argument
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '_' || c == '.')
+                {
+                    result.push('(');
+                    result.push_str(argument);
+                    result.push(')');
+                } else {
+                    result.push_str(argument);
+                }
+                rest = &after[end + 1..];
+            }
+            _ => {
+                result.push('{');
+                rest = after;
+            }
+        }
+    }
+
+    result.push_str(rest);
+    Ok(result)
+}
+
+fn generate(
+    phenotype: &Phenotype,
+    node: NodeIndex,
+    templates: &HashMap<String, String>,
+    shared: &HashSet<NodeIndex>,
+    defined: &HashSet<NodeIndex>,
+) -> Result<String, CodegenError> {
+    let arguments = arguments(phenotype, node)
+        .into_iter()
+        .map(|argument| {
+            if !shared.contains(&argument) {
+                generate(phenotype, argument, templates, shared, defined)
+            } else if defined.contains(&argument) {
+                Ok(shared_name(argument))
+            } else {
+                Err(CodegenError::Cycle(argument))
+            }
+        })
+        .collect::<Result<Vec<String>, CodegenError>>()?;
+
+    // ephemeral random constants are emitted as literals
+    if let Some(value) = phenotype[node].value {
+        return Ok(literal(value));
+    }
+
+    let label = &phenotype[node].label;
+    let template = templates
+        .get(label)
+        .ok_or_else(|| CodegenError::MissingTemplate(label.clone()))?;
+
+    fill(label, template, &arguments)
+}
+
+/// Rust expression of a constant, Rust having no literals for infinities and NaN
+fn literal(value: f64) -> String {
+    if value.is_nan() {
+        String::from("f64::NAN")
+    } else if value == f64::INFINITY {
+        String::from("f64::INFINITY")
+    } else if value == f64::NEG_INFINITY {
+        String::from("f64::NEG_INFINITY")
+    } else {
+        format!("{:?}f64", value)
+    }
+}
+
+/// Generates a standalone Rust function computing the phenotype
+///
+/// `templates` maps labels to Rust expressions, where `{0}`, `{1}`, ... stand for the arguments
+/// (strong bindings in order, then weak bindings). Terminals usually map to parameter names or
+/// literals. Nodes shared through weak bindings become local variables. A phenotype with several
+/// outputs returns them as a tuple.
+pub fn rust_function(
+    phenotype: &Phenotype,
+    templates: &HashMap<String, String>,
+    signature: &Signature,
+) -> Result<String, CodegenError> {
+    let outputs = output_nodes(phenotype);
+    if outputs.is_empty() {
+        return Err(CodegenError::NoOutput);
+    }
+    let order = shared_nodes(phenotype);
+    let shared: HashSet<NodeIndex> = order.iter().cloned().collect();
+    let mut defined = HashSet::new();
+
+    let parameters: Vec<String> = signature
+        .parameters
+        .iter()
+        .map(|(name, ty)| format!("{}: {}", name, ty))
+        .collect();
+
+    let mut result = format!(
+        "pub fn {}({}) -> {} {{\n",
+        signature.name,
+        parameters.join(", "),
+        signature.return_type
+    );
+
+    for node in order {
+        let value = generate(phenotype, node, templates, &shared, &defined)?;
+        result.push_str(&format!("    let {} = {};\n", shared_name(node), value));
+        defined.insert(node);
+    }
+
+    let bodies = outputs
+        .into_iter()
+        .map(|output| {
+            if shared.contains(&output) {
+                Ok(shared_name(output))
+            } else {
+                generate(phenotype, output, templates, &shared, &defined)
+            }
+        })
+        .collect::<Result<Vec<String>, CodegenError>>()?;
+
+    let body = if bodies.len() == 1 {
+        bodies.join("")
+    } else {
+        format!("({})", bodies.join(", "))
+    };
+
+    result.push_str(&format!("    {}\n}}\n", body));
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::{Binding, ComponentIndex, Phenotype};
+
+    fn templates() -> HashMap<String, String> {
+        [
+            ("+", "{0} + {1}"),
+            ("*", "{0} * {1}"),
+            ("use", "{1} - {0}"),
+            ("out", "{0}"),
+            ("x", "x"),
+            ("1", "1.0"),
+        ]
+        .iter()
+        .map(|(label, template)| (String::from(*label), String::from(*template)))
+        .collect()
+    }
+
+    fn signature() -> Signature {
+        Signature::new("f", &[("x", "f64")], "f64")
+    }
+
+    #[test]
+    fn shared_nodes_become_locals() {
+        assert_eq!(
+            rust_function(&testing::shared_sum(), &templates(), &signature()),
+            Ok(String::from(
+                "pub fn f(x: f64) -> f64 {\n    let v2 = x + 1.0;\n    (v2 * (v2 - x))\n}\n"
+            ))
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut missing = templates();
+        missing.remove("use");
+        assert_eq!(
+            rust_function(&testing::shared_sum(), &missing, &signature()),
+            Err(CodegenError::MissingTemplate(String::from("use")))
+        );
+
+        let mut too_many = templates();
+        too_many.insert(String::from("use"), String::from("{2}"));
+        assert_eq!(
+            rust_function(&testing::shared_sum(), &too_many, &signature()),
+            Err(CodegenError::MissingArgument(String::from("use"), 2))
+        );
+
+        assert_eq!(
+            rust_function(&Phenotype::new(), &templates(), &signature()),
+            Err(CodegenError::NoOutput)
+        );
+    }
+
+    #[test]
+    fn constants_are_literals() {
+        let constants = |a: f64, b: f64| {
+            let mut phenotype = testing::phenotype(
+                &[
+                    ("out", ComponentIndex::Output(0)),
+                    ("+", ComponentIndex::Regular(0, 0)),
+                    ("c", ComponentIndex::Regular(0, 1)),
+                    ("c", ComponentIndex::Regular(0, 2)),
+                ],
+                &[
+                    (0, 1, Binding::Strong(0)),
+                    (1, 2, Binding::Strong(0)),
+                    (1, 3, Binding::Strong(1)),
+                ],
+            );
+            phenotype[NodeIndex::new(2)].value = Some(a);
+            phenotype[NodeIndex::new(3)].value = Some(b);
+            rust_function(&phenotype, &templates(), &signature()).unwrap()
+        };
+        let body =
+            |expression: &str| format!("pub fn f(x: f64) -> f64 {{\n    {}\n}}\n", expression);
+
+        assert_eq!(constants(0.5, -2.), body("(0.5f64 + (-2.0f64))"));
+        assert_eq!(
+            constants(f64::INFINITY, f64::NEG_INFINITY),
+            body("((f64::INFINITY) + (f64::NEG_INFINITY))")
+        );
+        assert_eq!(constants(f64::NAN, 1e300), body("((f64::NAN) + 1e300f64)"));
+    }
+
+    #[test]
+    fn weak_bindings_to_ancestors_are_cycles() {
+        let phenotype = testing::phenotype(
+            &[
+                ("out", ComponentIndex::Output(0)),
+                ("+", ComponentIndex::Regular(0, 0)),
+                ("use", ComponentIndex::Regular(0, 1)),
+                ("x", ComponentIndex::Terminal(0, 0)),
+                ("x", ComponentIndex::Terminal(0, 0)),
+            ],
+            &[
+                (0, 1, Binding::Strong(0)),
+                (1, 2, Binding::Strong(0)),
+                (1, 3, Binding::Strong(1)),
+                (2, 4, Binding::Strong(0)),
+                (2, 1, Binding::Weak(0)),
+            ],
+        );
+
+        assert!(matches!(
+            rust_function(&phenotype, &templates(), &signature()),
+            Err(CodegenError::Cycle(_))
+        ));
+    }
+}
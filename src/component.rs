@@ -13,6 +13,9 @@ pub struct Component {
     pub binding_sites_groups: Vec<usize>,
     pub weak_binding_sites: Vec<Vec<f32>>, // must connect to already-expressed components
     pub weak_binding_sites_groups: Vec<usize>,
+    /// Value of an ephemeral random constant
+    #[serde(default)]
+    pub value: Option<f64>,
 }
 
 impl Component {
@@ -23,11 +26,22 @@ impl Component {
     }
 
     pub fn from_blueprint(blueprint: &Blueprint, total_activities: usize) -> Component {
+        Component::from_blueprint_with_rng(&mut rand::thread_rng(), blueprint, total_activities)
+    }
+
+    pub fn from_blueprint_with_rng<R: Rng + ?Sized>(
+        rng: &mut R,
+        blueprint: &Blueprint,
+        total_activities: usize,
+    ) -> Component {
         let binding_sites_groups = blueprint.binding_sites.clone();
-        let binding_sites = random_binding_sites(&blueprint.binding_sites, total_activities);
+        let binding_sites = random_binding_sites(rng, &blueprint.binding_sites, total_activities);
         let weak_binding_sites_groups = blueprint.weak_binding_sites.clone();
         let weak_binding_sites =
-            random_binding_sites(&blueprint.weak_binding_sites, total_activities);
+            random_binding_sites(rng, &blueprint.weak_binding_sites, total_activities);
+        let value = blueprint
+            .ephemeral
+            .map(|ephemeral| rng.gen_range(ephemeral.min, ephemeral.max));
 
         Component {
             activity: blueprint.activity,
@@ -36,6 +50,7 @@ impl Component {
             binding_sites_groups,
             weak_binding_sites,
             weak_binding_sites_groups,
+            value,
         }
     }
 
@@ -60,9 +75,11 @@ impl Component {
     }
 }
 
-fn random_binding_sites(from: &Vec<usize>, total_activities: usize) -> Vec<Vec<f32>> {
-    let mut rng = rand::thread_rng();
-
+fn random_binding_sites<R: Rng + ?Sized>(
+    rng: &mut R,
+    from: &[usize],
+    total_activities: usize,
+) -> Vec<Vec<f32>> {
     from.iter()
         .map(|_| {
             iter::repeat_with(|| rng.gen::<f32>())
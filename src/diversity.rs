@@ -0,0 +1,394 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+use crate::analysis::edit_size;
+use crate::blueprints::Blueprints;
+use crate::chromosome::EgpChromosome;
+use crate::evolution::phenotype_hash;
+use crate::simplify::Expr;
+use crate::{express_with, vectors, ExpressionConfig, Phenotype};
+
+/// Minimal total cost of assigning rows to columns in a square matrix (Hungarian algorithm)
+fn assignment_cost(costs: &[Vec<f64>]) -> f64 {
+    let n = costs.len();
+    if n == 0 {
+        return 0.;
+    }
+
+    // 1-based potentials and matching, column 0 is a sentinel
+    let mut u = vec![0.; n + 1];
+    let mut v = vec![0.; n + 1];
+    let mut matched = vec![0; n + 1]; // row matched to each column
+    let mut way = vec![0; n + 1];
+
+    for row in 1..=n {
+        matched[0] = row;
+        let mut column = 0;
+        let mut min_to = vec![f64::INFINITY; n + 1];
+        let mut used = vec![false; n + 1];
+
+        loop {
+            used[column] = true;
+            let current_row = matched[column];
+            let mut delta = f64::INFINITY;
+            let mut next = 0;
+
+            for j in 1..=n {
+                if !used[j] {
+                    let reduced = costs[current_row - 1][j - 1] - u[current_row] - v[j];
+                    if reduced < min_to[j] {
+                        min_to[j] = reduced;
+                        way[j] = column;
+                    }
+                    if min_to[j] < delta {
+                        delta = min_to[j];
+                        next = j;
+                    }
+                }
+            }
+
+            for j in 0..=n {
+                if used[j] {
+                    u[matched[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min_to[j] -= delta;
+                }
+            }
+
+            column = next;
+            if matched[column] == 0 {
+                break;
+            }
+        }
+
+        loop {
+            let previous = way[column];
+            matched[column] = matched[previous];
+            column = previous;
+            if column == 0 {
+                break;
+            }
+        }
+    }
+
+    (1..=n).map(|j| costs[matched[j] - 1][j - 1]).sum()
+}
+
+/// Distance between two groups of profiles under their optimal matching
+///
+/// Components left without a partner cost the norm of their profile.
+fn group_distance(a: &[Vec<f32>], b: &[Vec<f32>]) -> f64 {
+    let n = a.len().max(b.len());
+
+    let costs: Vec<Vec<f64>> = (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| match (a.get(i), b.get(j)) {
+                    (Some(a), Some(b)) => vectors::distance(a, b) as f64,
+                    (Some(unmatched), None) | (None, Some(unmatched)) => {
+                        vectors::norm(unmatched) as f64
+                    }
+                    (None, None) => 0.,
+                })
+                .collect()
+        })
+        .collect();
+
+    assignment_cost(&costs)
+}
+
+fn profiles(
+    blueprints: &Blueprints,
+    chromosome: &EgpChromosome,
+    input_bias: f32,
+) -> Vec<Vec<Vec<f32>>> {
+    chromosome
+        .regular
+        .iter()
+        .map(|group| {
+            group
+                .iter()
+                .map(|component| component.profile(blueprints.total_activities, input_bias))
+                .collect()
+        })
+        .collect()
+}
+
+/// Genotypic distance, the sum of optimal profile matchings in each group and the output distance
+///
+/// Component order doesn't matter, so chromosomes differing only by a permutation are at zero.
+pub fn genotypic_distance(blueprints: &Blueprints, a: &EgpChromosome, b: &EgpChromosome) -> f64 {
+    genotypic_distance_with(blueprints, a, b, &ExpressionConfig::default())
+}
+
+/// Genotypic distance like `genotypic_distance`, with profiles computed for the given expression
+/// parameters
+pub fn genotypic_distance_with(
+    blueprints: &Blueprints,
+    a: &EgpChromosome,
+    b: &EgpChromosome,
+    config: &ExpressionConfig,
+) -> f64 {
+    let output_distance: f64 = a
+        .outputs
+        .iter()
+        .zip(&b.outputs)
+        .map(|(a, b)| {
+            let a = a.profile(blueprints.total_activities, config.input_bias);
+            let b = b.profile(blueprints.total_activities, config.input_bias);
+            vectors::distance(&a, &b) as f64
+        })
+        .sum();
+
+    let groups_distance: f64 = profiles(blueprints, a, config.input_bias)
+        .iter()
+        .zip(profiles(blueprints, b, config.input_bias).iter())
+        .map(|(a, b)| group_distance(a, b))
+        .sum();
+
+    output_distance + groups_distance
+}
+
+/// Phenotypic distance, an approximation of the tree edit distance of the expressed programs
+pub fn phenotypic_distance(a: &Phenotype, b: &Phenotype) -> usize {
+    match (Expr::from_phenotype(a), Expr::from_phenotype(b)) {
+        (Some(a), Some(b)) => edit_size(&a, &b),
+        (Some(a), None) | (None, Some(a)) => a.size(),
+        (None, None) => 0,
+    }
+}
+
+/// Diversity summary of a population, for logging each generation
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Diversity {
+    pub population_size: usize,
+    pub distinct_phenotypes: usize,
+    pub mean_genotypic_distance: f64,
+    pub max_genotypic_distance: f64,
+    pub mean_phenotypic_distance: f64,
+    pub mean_chromosome_length: f64,
+    /// Number of pairs the distances were averaged over
+    pub pairs: usize,
+}
+
+/// Summarizes the diversity of a population
+///
+/// Distances are averaged over at most `max_pairs` pairs, sampled randomly when the population
+/// has more.
+pub fn diversity(
+    blueprints: &Blueprints,
+    population: &[EgpChromosome],
+    max_pairs: usize,
+) -> Diversity {
+    diversity_with(
+        &mut rand::thread_rng(),
+        blueprints,
+        population,
+        max_pairs,
+        &ExpressionConfig::default(),
+    )
+}
+
+/// Summarizes the diversity of a population like `diversity`, for the given expression parameters
+/// and sampling pairs from the given random number generator
+pub fn diversity_with<R: Rng + ?Sized>(
+    rng: &mut R,
+    blueprints: &Blueprints,
+    population: &[EgpChromosome],
+    max_pairs: usize,
+    config: &ExpressionConfig,
+) -> Diversity {
+    let n = population.len();
+    if n == 0 {
+        return Diversity::default();
+    }
+
+    let phenotypes: Vec<Phenotype> = population
+        .iter()
+        .map(|chromosome| express_with(blueprints, chromosome, config))
+        .collect();
+
+    let distinct_phenotypes = phenotypes
+        .iter()
+        .map(phenotype_hash)
+        .collect::<HashSet<u64>>()
+        .len();
+
+    let total_pairs = n * (n - 1) / 2;
+    let pairs: Vec<(usize, usize)> = if total_pairs <= max_pairs {
+        (0..n)
+            .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+            .collect()
+    } else {
+        std::iter::repeat_with(|| {
+            let i = rng.gen_range(0, n);
+            let j = (i + rng.gen_range(1, n)) % n;
+            (i, j)
+        })
+        .take(max_pairs)
+        .collect()
+    };
+
+    let mut total_genotypic = 0.;
+    let mut max_genotypic: f64 = 0.;
+    let mut total_phenotypic = 0;
+
+    for (i, j) in &pairs {
+        let genotypic =
+            genotypic_distance_with(blueprints, &population[*i], &population[*j], config);
+        total_genotypic += genotypic;
+        max_genotypic = max_genotypic.max(genotypic);
+        total_phenotypic += phenotypic_distance(&phenotypes[*i], &phenotypes[*j]);
+    }
+
+    let n_pairs = pairs.len().max(1) as f64;
+    let total_length: usize = population
+        .iter()
+        .map(|chromosome| Blueprints::sum_group_lens(&chromosome.regular))
+        .sum();
+
+    Diversity {
+        population_size: n,
+        distinct_phenotypes,
+        mean_genotypic_distance: total_genotypic / n_pairs,
+        max_genotypic_distance: max_genotypic,
+        mean_phenotypic_distance: total_phenotypic as f64 / n_pairs,
+        mean_chromosome_length: total_length as f64 / n as f64,
+        pairs: pairs.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// Minimal assignment cost over all permutations of the columns
+    fn brute_force(costs: &[Vec<f64>]) -> f64 {
+        fn search(costs: &[Vec<f64>], row: usize, used: &mut [bool]) -> f64 {
+            if row == costs.len() {
+                return 0.;
+            }
+
+            let mut best = f64::INFINITY;
+            for column in 0..costs.len() {
+                if !used[column] {
+                    used[column] = true;
+                    best = best.min(costs[row][column] + search(costs, row + 1, used));
+                    used[column] = false;
+                }
+            }
+            best
+        }
+
+        search(costs, 0, &mut vec![false; costs.len()])
+    }
+
+    #[test]
+    fn assignment_cost_is_optimal() {
+        let mut rng = testing::rng(1);
+
+        assert_eq!(assignment_cost(&[]), 0.);
+        assert_eq!(assignment_cost(&[vec![4., 1.], vec![2., 8.]]), 3.);
+
+        for n in 1..=6 {
+            for _ in 0..20 {
+                let costs: Vec<Vec<f64>> = (0..n)
+                    .map(|_| (0..n).map(|_| rng.gen_range(0., 10.)).collect())
+                    .collect();
+
+                assert!((assignment_cost(&costs) - brute_force(&costs)).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn genotypic_distance_ignores_component_order() {
+        let blueprints = testing::arithmetic();
+        let chromosomes = testing::ancestors(&blueprints, 2, 4);
+
+        let mut shuffled = chromosomes[0].clone();
+        shuffled.regular[0].reverse();
+
+        assert_eq!(
+            genotypic_distance(&blueprints, &chromosomes[0], &shuffled),
+            0.
+        );
+        for a in &chromosomes {
+            for b in &chromosomes {
+                let distance = genotypic_distance(&blueprints, a, b);
+                assert!((distance - genotypic_distance(&blueprints, b, a)).abs() < 1e-6);
+                assert!(distance >= 0.);
+            }
+        }
+    }
+
+    #[test]
+    fn phenotypic_distance_compares_every_output() {
+        let (a, b) = (testing::two_roots("x"), testing::two_roots("1"));
+
+        assert_eq!(phenotypic_distance(&a, &a), 0);
+        assert_eq!(phenotypic_distance(&a, &b), 1);
+    }
+
+    #[test]
+    fn diversity_of_clones() {
+        let blueprints = testing::arithmetic();
+        let chromosome = testing::ancestors(&blueprints, 3, 1).remove(0);
+        let clones = vec![chromosome; 5];
+
+        let summary = diversity(&blueprints, &clones, 100);
+
+        assert_eq!(summary.population_size, 5);
+        assert_eq!(summary.distinct_phenotypes, 1);
+        assert_eq!(summary.pairs, 10);
+        assert_eq!(summary.mean_genotypic_distance, 0.);
+        assert_eq!(summary.mean_phenotypic_distance, 0.);
+        assert_eq!(summary.mean_chromosome_length, clones_length(&clones));
+        assert_eq!(diversity(&blueprints, &clones, 4).pairs, 4);
+    }
+
+    #[test]
+    fn seeded_pair_sampling_is_deterministic() {
+        let blueprints = testing::arithmetic();
+        let population = testing::ancestors(&blueprints, 5, 10);
+        let config = ExpressionConfig::default();
+
+        let summarize = |seed| {
+            let mut rng = testing::rng(seed);
+            diversity_with(&mut rng, &blueprints, &population, 8, &config)
+        };
+        let (a, b) = (summarize(6), summarize(6));
+
+        assert_eq!(a.pairs, 8);
+        assert_eq!(a.mean_genotypic_distance, b.mean_genotypic_distance);
+        assert_eq!(a.max_genotypic_distance, b.max_genotypic_distance);
+        assert_eq!(a.mean_phenotypic_distance, b.mean_phenotypic_distance);
+    }
+
+    fn clones_length(clones: &[EgpChromosome]) -> f64 {
+        clones[0]
+            .regular
+            .iter()
+            .map(|group| group.len())
+            .sum::<usize>() as f64
+    }
+
+    #[test]
+    fn genotypic_distance_uses_the_input_bias() {
+        let blueprints = testing::arithmetic();
+        let chromosomes = testing::ancestors(&blueprints, 4, 2);
+        let (a, b) = (&chromosomes[0], &chromosomes[1]);
+
+        assert_eq!(
+            genotypic_distance_with(&blueprints, a, b, &ExpressionConfig::default()),
+            genotypic_distance(&blueprints, a, b)
+        );
+        assert!(
+            genotypic_distance_with(&blueprints, a, b, &testing::expression())
+                != genotypic_distance(&blueprints, a, b)
+        );
+    }
+}
@@ -0,0 +1,1530 @@
+use petgraph::graph::NodeIndex;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::blueprints::Blueprints;
+use crate::checkpoint::{Checkpoint, RunState};
+use crate::chromosome::EgpChromosome;
+use crate::lineage::{Event, IndividualId, Lineage, Operator};
+use crate::novelty::{self, BehaviourArchive, NoveltyConfig};
+use crate::nsga2::{dominates, Ranking};
+use crate::operators::vary_with_rng;
+use crate::pretty::{bound_arguments, output_nodes};
+use crate::speciation::{Speciation, SpeciesSet};
+use crate::statistics::{Observer, OperatorStats, Report, Statistics};
+use crate::termination::{Progress, StopCondition, StopReason};
+use crate::{express_with, Binding, ExpressionConfig, Phenotype};
+
+/// Parameters of an evolutionary run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Config {
+    pub population_size: usize,
+    /// Size of the ancestors, see `EgpChromosome::ancestor_from_blueprints`
+    pub chromosome_size: usize,
+    pub tournament_size: usize,
+    /// Probability that an offspring is produced by recombination instead of mutation
+    pub recombination_rate: f64,
+    pub n_transfer: usize,
+    /// Number of best individuals kept unchanged, see `Replacement` for each strategy
+    pub elitism: usize,
+    pub generations: usize,
+    /// Reuse the fitness of structurally identical phenotypes
+    pub cache_fitness: bool,
+    /// Most evaluations kept in the fitness cache, the oldest being dropped first
+    ///
+    /// The cache is saved with every checkpoint, `None` lets it grow without bound.
+    pub cache_capacity: Option<usize>,
+    /// Record parents and operators of every individual
+    pub track_lineage: bool,
+    /// Seed of the run's random number generator, drawn from the thread generator if missing
+    pub seed: Option<u64>,
+    pub expression: ExpressionConfig,
+    pub replacement: Replacement,
+    /// Cluster the population into species, sharing fitness and restricting mating within them
+    pub speciation: Option<Speciation>,
+    /// Select for novel behaviour, the fitness function returning `WithBehaviour`
+    pub novelty: Option<NoveltyConfig>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            population_size: 100,
+            chromosome_size: 40,
+            tournament_size: 3,
+            recombination_rate: 0.2,
+            n_transfer: 3,
+            elitism: 1,
+            generations: 100,
+            cache_fitness: true,
+            cache_capacity: Some(100_000),
+            track_lineage: false,
+            seed: None,
+            expression: ExpressionConfig::default(),
+            replacement: Replacement::Generational,
+            speciation: None,
+            novelty: None,
+        }
+    }
+}
+
+impl Config {
+    /// Checks that the parameters can be used together
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if let Replacement::MuCommaLambda { lambda } = self.replacement {
+            let required = self.population_size.saturating_sub(self.elitism);
+            if lambda < required {
+                return Err(ConfigError::TooFewOffspring { lambda, required });
+            }
+        }
+
+        if self.novelty.is_some() {
+            if self.speciation.is_some() {
+                return Err(ConfigError::NoveltyWithSpeciation);
+            }
+            if self.replacement == Replacement::Nsga2 {
+                return Err(ConfigError::NoveltyWithNsga2);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Parameters of a `Config` that can't be used together
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// `Replacement::MuCommaLambda` produces fewer offspring than the places left by the elite
+    TooFewOffspring { lambda: usize, required: usize },
+    /// Tournaments of speciated runs compare shared fitness rather than novelty
+    NoveltyWithSpeciation,
+    /// NSGA-II tournaments compare rank and crowding distance rather than novelty
+    NoveltyWithNsga2,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::TooFewOffspring { lambda, required } => write!(
+                f,
+                "lambda is {} but at least {} offspring are needed to refill the population",
+                lambda, required
+            ),
+            ConfigError::NoveltyWithSpeciation => {
+                write!(f, "novelty search can't be combined with speciation")
+            }
+            ConfigError::NoveltyWithNsga2 => {
+                write!(f, "novelty search can't be combined with NSGA-II")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// How offspring enter the population in each generation
+///
+/// The population size `mu` is `Config::population_size`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Replacement {
+    /// The `elitism` best are copied into an otherwise new population
+    Generational,
+    /// `mu` offspring are produced one at a time, each replacing a member chosen by `Removal`
+    ///
+    /// The `elitism` best members are never removed.
+    SteadyState(Removal),
+    /// `lambda` offspring are produced and the best `mu - elitism` of them join the `elitism`
+    /// best parents
+    ///
+    /// `lambda` must be at least `mu - elitism`, see `Config::validate`.
+    MuCommaLambda { lambda: usize },
+    /// `lambda` offspring are produced and the best `mu` of parents and offspring together
+    /// survive, which is elitist by itself, so `elitism` is ignored
+    MuPlusLambda { lambda: usize },
+    /// NSGA-II: `mu` offspring are produced and the best `mu` of parents and offspring together
+    /// survive by non-dominated rank, then crowding distance
+    ///
+    /// Tournaments compare rank and crowding distance too. The objectives are those of a fitness
+    /// function returning `Vec<f64>`, see `Fitness`. Survival keeps the Pareto front whenever it
+    /// fits, so `elitism` is ignored.
+    Nsga2,
+}
+
+/// Which member a steady-state offspring replaces
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Removal {
+    Worst,
+    /// The worst of this many randomly chosen members
+    InverseTournament(usize),
+}
+
+/// Fitness is maximized
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Individual {
+    pub id: IndividualId,
+    pub chromosome: EgpChromosome,
+    /// The first objective when there are several
+    #[serde(with = "crate::checkpoint::float")]
+    pub fitness: f64,
+    /// All objectives when the fitness function returns several, otherwise empty
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        with = "crate::checkpoint::float::vec"
+    )]
+    pub objectives: Vec<f64>,
+    /// Number of expressed nodes
+    pub phenotype_size: usize,
+    /// Behaviour descriptor for novelty search, otherwise empty
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        with = "crate::checkpoint::float::vec"
+    )]
+    pub behaviour: Vec<f64>,
+}
+
+impl Individual {
+    /// The objectives, or the fitness as the only one
+    pub fn objectives(&self) -> &[f64] {
+        if self.objectives.is_empty() {
+            std::slice::from_ref(&self.fitness)
+        } else {
+            &self.objectives
+        }
+    }
+}
+
+/// Value of a fitness function, maximized
+///
+/// Functions return a single `f64`, or a `Vec<f64>` of objectives for `Replacement::Nsga2`, or
+/// either along with a behaviour descriptor for novelty search, see `WithBehaviour`.
+pub trait Fitness {
+    fn into_objectives(self) -> Vec<f64>;
+
+    /// The objectives along with the behaviour descriptor, empty by default
+    fn into_evaluation(self) -> Evaluation
+    where
+        Self: Sized,
+    {
+        Evaluation {
+            objectives: self.into_objectives(),
+            behaviour: vec![],
+        }
+    }
+}
+
+impl Fitness for f64 {
+    fn into_objectives(self) -> Vec<f64> {
+        vec![self]
+    }
+}
+
+impl Fitness for Vec<f64> {
+    fn into_objectives(self) -> Vec<f64> {
+        self
+    }
+}
+
+/// Fitness along with the behaviour descriptor compared by novelty search, see `Config::novelty`
+#[derive(Debug, Clone, PartialEq)]
+pub struct WithBehaviour<S> {
+    pub fitness: S,
+    pub behaviour: Vec<f64>,
+}
+
+impl<S: Fitness> Fitness for WithBehaviour<S> {
+    fn into_objectives(self) -> Vec<f64> {
+        self.fitness.into_objectives()
+    }
+
+    fn into_evaluation(self) -> Evaluation {
+        Evaluation {
+            objectives: self.fitness.into_objectives(),
+            behaviour: self.behaviour,
+        }
+    }
+}
+
+/// What a fitness function returned for a phenotype
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Evaluation {
+    #[serde(with = "crate::checkpoint::float::vec")]
+    pub objectives: Vec<f64>,
+    /// Behaviour descriptor for novelty search, otherwise empty
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        with = "crate::checkpoint::float::vec"
+    )]
+    pub behaviour: Vec<f64>,
+}
+
+/// 64 bit FNV-1a over explicitly encoded values
+///
+/// Unlike `DefaultHasher` and the `Hash` implementations, its output is fixed by its
+/// specification, so hashes saved in a checkpoint stay valid with other Rust releases and
+/// platforms.
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    fn new() -> Fnv1a {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn number(&mut self, number: u64) {
+        self.bytes(&number.to_le_bytes());
+    }
+
+    fn text(&mut self, text: &str) {
+        self.number(text.len() as u64);
+        self.bytes(text.as_bytes());
+    }
+}
+
+/// Hash of the expressed structure: labels, values, and the binding of each argument
+///
+/// Node indices don't matter, so different chromosomes expressing the same program hash equally.
+pub fn phenotype_hash(phenotype: &Phenotype) -> u64 {
+    let mut hasher = Fnv1a::new();
+
+    visit_structure(phenotype, |token| match token {
+        Token::Reference(order) => {
+            hasher.bytes(&[0]);
+            hasher.number(order as u64);
+        }
+        Token::Binding(Binding::Strong(index)) => {
+            hasher.bytes(&[1]);
+            hasher.number(index as u64);
+        }
+        Token::Binding(Binding::Weak(index)) => {
+            hasher.bytes(&[2]);
+            hasher.number(index as u64);
+        }
+        Token::Node {
+            label,
+            value,
+            n_strong,
+            n_arguments,
+        } => {
+            hasher.bytes(&[3]);
+            hasher.text(label);
+            if let Some(value) = value {
+                hasher.number(value.to_bits());
+            }
+            hasher.number(n_strong as u64);
+            hasher.number(n_arguments as u64);
+        }
+    });
+
+    hasher.0
+}
+
+/// Encoding of the structure hashed by `phenotype_hash`, equal exactly when the structures are
+pub fn phenotype_key(phenotype: &Phenotype) -> String {
+    let mut key = String::new();
+
+    visit_structure(phenotype, |token| match token {
+        Token::Reference(order) => key.push_str(&format!("#{} ", order)),
+        Token::Binding(Binding::Strong(index)) => key.push_str(&format!("s{} ", index)),
+        Token::Binding(Binding::Weak(index)) => key.push_str(&format!("w{} ", index)),
+        Token::Node {
+            label,
+            value,
+            n_strong,
+            n_arguments,
+        } => {
+            key.push_str(&format!("{:?}", label));
+            if let Some(value) = value {
+                key.push_str(&format!("={:x}", value.to_bits()));
+            }
+            key.push_str(&format!("/{}/{} ", n_strong, n_arguments));
+        }
+    });
+
+    key
+}
+
+/// A step of the traversal shared by `phenotype_hash` and `phenotype_key`
+enum Token<'p> {
+    /// A node reached again through a weak binding, by the order of its first visit
+    Reference(usize),
+    /// The edge to the next argument, visited right after
+    Binding(Binding),
+    Node {
+        label: &'p str,
+        value: Option<f64>,
+        n_strong: usize,
+        n_arguments: usize,
+    },
+}
+
+fn visit_structure<'p, V: FnMut(Token<'p>)>(phenotype: &'p Phenotype, mut visit: V) {
+    let mut visited = HashMap::new();
+
+    for output in output_nodes(phenotype) {
+        visit_node(phenotype, output, &mut visited, &mut visit);
+    }
+}
+
+fn visit_node<'p, V: FnMut(Token<'p>)>(
+    phenotype: &'p Phenotype,
+    node: NodeIndex,
+    visited: &mut HashMap<NodeIndex, usize>,
+    visit: &mut V,
+) {
+    // nodes reached again through weak bindings are references to the first visit
+    if let Some(order) = visited.get(&node) {
+        visit(Token::Reference(*order));
+        return;
+    }
+
+    visited.insert(node, visited.len());
+
+    let arguments = bound_arguments(phenotype, node);
+    let n_strong = phenotype
+        .edges(node)
+        .filter(|edge| matches!(edge.weight(), Binding::Strong(_)))
+        .count();
+
+    visit(Token::Node {
+        label: &phenotype[node].label,
+        value: phenotype[node].value,
+        n_strong,
+        n_arguments: arguments.len(),
+    });
+
+    for (binding, argument) in arguments {
+        visit(Token::Binding(binding));
+        visit_node(phenotype, argument, visited, visit);
+    }
+}
+
+/// Outcome of `Evolution::run_until`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunResult {
+    pub best: Option<Individual>,
+    pub reason: StopReason,
+    pub generation: usize,
+    pub evaluations: usize,
+    pub elapsed: Duration,
+}
+
+/// An unevaluated offspring and how it was produced
+pub struct Offspring {
+    pub chromosome: EgpChromosome,
+    pub parents: Vec<IndividualId>,
+    pub operator: Operator,
+}
+
+/// Index of the winner of a tournament of `size` draws from `0..n`
+///
+/// `beats(a, b)` tells whether `a` wins against the current winner `b`.
+pub fn tournament_with_rng<R, B>(rng: &mut R, n: usize, size: usize, mut beats: B) -> usize
+where
+    R: Rng + ?Sized,
+    B: FnMut(usize, usize) -> bool,
+{
+    let mut winner = rng.gen_range(0, n);
+
+    for _ in 1..size {
+        let contender = rng.gen_range(0, n);
+        if beats(contender, winner) {
+            winner = contender;
+        }
+    }
+
+    winner
+}
+
+/// Evaluations with the keys of their phenotypes, by phenotype hash
+type Scores = HashMap<u64, Vec<(String, Evaluation)>>;
+
+fn hit_rate(hits: usize, misses: usize) -> f64 {
+    let lookups = hits + misses;
+
+    if lookups == 0 {
+        0.
+    } else {
+        hits as f64 / lookups as f64
+    }
+}
+
+/// Fitness scores keyed by `phenotype_hash`
+///
+/// Each score is stored with the `phenotype_key` of its phenotype, so phenotypes whose hashes
+/// collide are told apart.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FitnessCache {
+    scores: Scores,
+    /// Hash of each stored score, oldest first
+    order: VecDeque<u64>,
+    pub hits: usize,
+    pub misses: usize,
+    /// Lookups since `start_generation`, the totals above cover the whole run
+    #[serde(default)]
+    generation_hits: usize,
+    #[serde(default)]
+    generation_misses: usize,
+}
+
+impl FitnessCache {
+    pub fn new() -> FitnessCache {
+        FitnessCache::default()
+    }
+
+    /// Returns the cached evaluation of the phenotype, computing and storing it on a miss
+    pub fn score<F, S>(&mut self, phenotype: &Phenotype, fitness: F) -> Evaluation
+    where
+        F: FnOnce(&Phenotype) -> S,
+        S: Fitness,
+    {
+        let hash = phenotype_hash(phenotype);
+        let key = phenotype_key(phenotype);
+        let bucket = self.scores.entry(hash).or_default();
+
+        if let Some((_key, score)) = bucket.iter().find(|(cached, _score)| *cached == key) {
+            self.hits += 1;
+            self.generation_hits += 1;
+            score.clone()
+        } else {
+            self.misses += 1;
+            self.generation_misses += 1;
+            let score = fitness(phenotype).into_evaluation();
+            bucket.push((key, score.clone()));
+            self.order.push_back(hash);
+            score
+        }
+    }
+
+    /// Drops the oldest scores beyond `capacity`
+    pub fn truncate(&mut self, capacity: usize) {
+        while self.order.len() > capacity {
+            let hash = self.order.pop_front().unwrap();
+            let bucket = self.scores.get_mut(&hash).unwrap();
+
+            // a bucket holds its scores oldest first
+            bucket.remove(0);
+            if bucket.is_empty() {
+                self.scores.remove(&hash);
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.values().map(|bucket| bucket.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    /// Fraction of lookups answered from the cache
+    pub fn hit_rate(&self) -> f64 {
+        hit_rate(self.hits, self.misses)
+    }
+
+    /// Fraction of lookups since `start_generation` answered from the cache
+    pub fn generation_hit_rate(&self) -> f64 {
+        hit_rate(self.generation_hits, self.generation_misses)
+    }
+
+    /// Starts counting the lookups of a new generation
+    pub fn start_generation(&mut self) {
+        self.generation_hits = 0;
+        self.generation_misses = 0;
+    }
+
+    pub fn clear(&mut self) {
+        self.scores.clear();
+        self.order.clear();
+        self.hits = 0;
+        self.misses = 0;
+        self.start_generation();
+    }
+}
+
+/// Evolution with tournament selection and a choice of `Replacement` strategies
+///
+/// All randomness comes from `rng`, so a run is reproducible from its seed and can be resumed
+/// from a checkpoint exactly, as long as the fitness function is deterministic.
+pub struct Evolution<'a, F> {
+    pub blueprints: &'a Blueprints,
+    pub config: Config,
+    pub population: Vec<Individual>,
+    pub generation: usize,
+    pub cache: FitnessCache,
+    /// Present when `config.track_lineage` is set
+    pub lineage: Option<Lineage>,
+    pub rng: Pcg32,
+    /// Statistics of every generation so far, starting with the ancestors as generation 0
+    pub statistics: Vec<Statistics>,
+    /// Calls of the fitness function so far
+    pub evaluations: usize,
+    /// Species of the current population when `config.speciation` is set
+    pub species: Option<SpeciesSet>,
+    /// Behaviours found novel so far when `config.novelty` is set
+    pub archive: Option<BehaviourArchive>,
+    /// Ranking of the current population with `Replacement::Nsga2`, computed before selection
+    ranking: Option<Ranking>,
+    /// Selection scores of the current population when `config.novelty` is set
+    novelty_scores: Option<Vec<f64>>,
+    observers: Vec<Box<dyn Observer + 'a>>,
+    next_id: IndividualId,
+    fitness: F,
+}
+
+impl<'a, F, S> Evolution<'a, F>
+where
+    F: FnMut(&Phenotype) -> S,
+    S: Fitness,
+{
+    /// Creates and evaluates a population of ancestors
+    ///
+    /// # Panics
+    ///
+    /// If the config is invalid, see `Config::validate`.
+    pub fn new(blueprints: &'a Blueprints, config: Config, fitness: F) -> Evolution<'a, F> {
+        match Evolution::try_new(blueprints, config, fitness) {
+            Ok(evolution) => evolution,
+            Err(error) => panic!("invalid config: {}", error),
+        }
+    }
+
+    /// Creates and evaluates a population of ancestors, unless the config is invalid
+    pub fn try_new(
+        blueprints: &'a Blueprints,
+        config: Config,
+        fitness: F,
+    ) -> Result<Evolution<'a, F>, ConfigError> {
+        config.validate()?;
+
+        let seed = config
+            .seed
+            .unwrap_or_else(|| rand::thread_rng().gen::<u64>());
+
+        let mut evolution = Evolution {
+            blueprints,
+            config,
+            population: vec![],
+            generation: 0,
+            cache: FitnessCache::new(),
+            lineage: None,
+            rng: Pcg32::seed_from_u64(seed),
+            statistics: vec![],
+            evaluations: 0,
+            species: None,
+            archive: None,
+            ranking: None,
+            novelty_scores: None,
+            observers: vec![],
+            next_id: 0,
+            fitness,
+        };
+
+        if evolution.config.track_lineage {
+            evolution.lineage = Some(Lineage::new());
+        }
+
+        for _ in 0..evolution.config.population_size {
+            let chromosome = EgpChromosome::ancestor_from_blueprints_with_rng(
+                &mut evolution.rng,
+                blueprints,
+                evolution.config.chromosome_size,
+            );
+            let individual = evolution.birth(Offspring {
+                chromosome,
+                parents: vec![],
+                operator: Operator::Ancestor,
+            });
+            evolution.population.push(individual);
+        }
+
+        evolution.assess_novelty(false);
+        evolution.observe(OperatorStats::default());
+
+        Ok(evolution)
+    }
+
+    /// Continues a run from its saved state
+    ///
+    /// # Panics
+    ///
+    /// If the saved config is invalid, see `Config::validate`.
+    pub fn resume(blueprints: &'a Blueprints, state: RunState, fitness: F) -> Evolution<'a, F> {
+        if let Err(error) = state.config.validate() {
+            panic!("invalid config: {}", error);
+        }
+
+        let mut evolution = Evolution {
+            blueprints,
+            config: state.config,
+            population: state.population,
+            generation: state.generation,
+            cache: state.cache,
+            lineage: state.lineage,
+            rng: state.rng,
+            statistics: state.statistics,
+            evaluations: state.evaluations,
+            species: state.species,
+            archive: state.archive,
+            ranking: None,
+            novelty_scores: None,
+            observers: vec![],
+            next_id: state.next_id,
+            fitness,
+        };
+
+        evolution.assess_novelty(false);
+
+        evolution
+    }
+
+    /// Saves everything needed to resume the run
+    pub fn state(&self) -> RunState {
+        RunState {
+            config: self.config.clone(),
+            population: self.population.clone(),
+            generation: self.generation,
+            cache: self.cache.clone(),
+            lineage: self.lineage.clone(),
+            rng: self.rng.clone(),
+            statistics: self.statistics.clone(),
+            evaluations: self.evaluations,
+            species: self.species.clone(),
+            archive: self.archive.clone(),
+            next_id: self.next_id,
+        }
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            blueprints: self.blueprints.clone(),
+            state: self.state(),
+        }
+    }
+
+    /// Evaluates an offspring, giving it an id and recording it in the lineage
+    pub fn birth(&mut self, offspring: Offspring) -> Individual {
+        let individual = self.evaluate(offspring.chromosome);
+
+        if let Some(lineage) = &mut self.lineage {
+            lineage.record(Event {
+                id: individual.id,
+                generation: self.generation,
+                parents: offspring.parents,
+                operator: offspring.operator,
+            });
+        }
+
+        individual
+    }
+
+    /// Expresses and scores a chromosome, giving it a new id
+    pub fn evaluate(&mut self, chromosome: EgpChromosome) -> Individual {
+        let (evaluation, phenotype_size) = self.score(&chromosome);
+        let mut objectives = evaluation.objectives;
+        let fitness = objectives.first().cloned().unwrap_or(f64::NEG_INFINITY);
+        if objectives.len() == 1 {
+            objectives.clear();
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+
+        Individual {
+            id,
+            chromosome,
+            fitness,
+            objectives,
+            phenotype_size,
+            behaviour: evaluation.behaviour,
+        }
+    }
+
+    /// Evaluation and phenotype size of a chromosome
+    fn score(&mut self, chromosome: &EgpChromosome) -> (Evaluation, usize) {
+        let phenotype = express_with(self.blueprints, chromosome, &self.config.expression);
+
+        let fitness = &mut self.fitness;
+        let evaluations = &mut self.evaluations;
+        let mut score = |phenotype: &Phenotype| {
+            *evaluations += 1;
+            fitness(phenotype)
+        };
+
+        let fitness = if self.config.cache_fitness {
+            let fitness = self.cache.score(&phenotype, score);
+            if let Some(capacity) = self.config.cache_capacity {
+                self.cache.truncate(capacity);
+            }
+            fitness
+        } else {
+            score(&phenotype).into_evaluation()
+        };
+
+        (fitness, phenotype.node_count())
+    }
+
+    /// Index of the winner of a tournament, on shared fitness when speciated, on rank and
+    /// crowding distance with `Replacement::Nsga2` and on the blend of novelty and fitness with
+    /// `config.novelty`
+    pub fn tournament(&mut self) -> usize {
+        self.tournament_by(self.population.len(), |index| index)
+    }
+
+    /// Index of the winner of a tournament among the given indices
+    fn tournament_among(&mut self, candidates: &[usize]) -> usize {
+        self.tournament_by(candidates.len(), |index| candidates[index])
+    }
+
+    /// Tournament among `n` members, the `i`th being the member at `member(i)`
+    fn tournament_by<M>(&mut self, n: usize, member: M) -> usize
+    where
+        M: Fn(usize) -> usize,
+    {
+        let population = &self.population;
+        let species = &self.species;
+        let ranking = &self.ranking;
+        let novelty_scores = &self.novelty_scores;
+        let fitness = |index: usize| match (species, novelty_scores) {
+            (Some(species), _) => species.shared_fitness[index],
+            (None, Some(scores)) => scores[index],
+            (None, None) => population[index].fitness,
+        };
+
+        let winner = tournament_with_rng(&mut self.rng, n, self.config.tournament_size, |a, b| {
+            let (a, b) = (member(a), member(b));
+            match ranking {
+                Some(ranking) => ranking.compare(a, b) == Ordering::Less,
+                None => fitness(a) > fitness(b),
+            }
+        });
+
+        member(winner)
+    }
+
+    /// Index of a recombination donor, mostly from the parent's species when speciated
+    ///
+    /// Before the population has been clustered, e.g. for `offspring` before the first `step`,
+    /// the donor comes from the whole population.
+    fn donor(&mut self, parent_index: usize) -> usize {
+        let interbreeding_rate = match (self.config.speciation, &self.species) {
+            (Some(speciation), Some(_)) => speciation.interbreeding_rate,
+            _ => return self.tournament(),
+        };
+
+        if self.rng.gen_range(0., 1.) < interbreeding_rate {
+            self.tournament()
+        } else {
+            let mates = self.species.as_ref().unwrap().mates(parent_index).to_vec();
+            self.tournament_among(&mates)
+        }
+    }
+
+    /// Produces an unevaluated offspring by recombination or mutation of selected parents
+    pub fn offspring(&mut self) -> Offspring {
+        let parent_index = self.tournament();
+        let donor_index = if self.rng.gen_range(0., 1.) < self.config.recombination_rate {
+            Some(self.donor(parent_index))
+        } else {
+            None
+        };
+
+        let population = &self.population;
+        let parent = &population[parent_index];
+        let donor = donor_index.map(|index| &population[index]);
+        let (chromosome, operator) = vary_with_rng(
+            &mut self.rng,
+            self.blueprints,
+            self.config.n_transfer,
+            &parent.chromosome,
+            donor.map(|donor| &donor.chromosome),
+        );
+
+        Offspring {
+            chromosome,
+            parents: std::iter::once(parent.id)
+                .chain(donor.map(|donor| donor.id))
+                .collect(),
+            operator,
+        }
+    }
+
+    /// Runs one generation, or `mu` replacements in steady state
+    ///
+    /// # Panics
+    ///
+    /// If the config was changed to an invalid one, see `Config::validate`.
+    pub fn step(&mut self) {
+        if let Err(error) = self.config.validate() {
+            panic!("invalid config: {}", error);
+        }
+
+        self.generation += 1;
+
+        let mut operators = OperatorStats::default();
+        self.speciate();
+        if self.config.replacement == Replacement::Nsga2 {
+            self.ranking = Some(Ranking::of(&self.population));
+        }
+        self.assess_novelty(true);
+
+        match self.config.replacement {
+            Replacement::Generational => {
+                let mut next: Vec<Individual> = self.elite().into_iter().cloned().collect();
+
+                while next.len() < self.config.population_size {
+                    next.push(self.breed(&mut operators));
+                }
+
+                self.population = next;
+            }
+            Replacement::SteadyState(removal) => {
+                for _ in 0..self.config.population_size {
+                    let individual = self.breed(&mut operators);
+                    let removed = self.removal(removal);
+                    self.population[removed] = individual;
+                    self.assess_novelty(true);
+
+                    if let (Some(species), Some(speciation)) =
+                        (&mut self.species, self.config.speciation)
+                    {
+                        species.reassign(
+                            self.blueprints,
+                            &self.population,
+                            removed,
+                            speciation.threshold,
+                            &self.config.expression,
+                        );
+                    }
+                }
+            }
+            Replacement::MuCommaLambda { lambda } => {
+                let mut offspring: Vec<Individual> =
+                    (0..lambda).map(|_| self.breed(&mut operators)).collect();
+                offspring.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+
+                let mut next: Vec<Individual> = self.elite().into_iter().cloned().collect();
+                let places = self.config.population_size.saturating_sub(next.len());
+                next.extend(offspring.into_iter().take(places));
+
+                self.population = next;
+            }
+            Replacement::MuPlusLambda { lambda } => {
+                let offspring: Vec<Individual> =
+                    (0..lambda).map(|_| self.breed(&mut operators)).collect();
+                self.population.extend(offspring);
+
+                // stable, so parents survive ties with their offspring
+                self.population
+                    .sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+                self.population.truncate(self.config.population_size);
+            }
+            Replacement::Nsga2 => {
+                let offspring: Vec<Individual> = (0..self.config.population_size)
+                    .map(|_| self.breed(&mut operators))
+                    .collect();
+                let mut pool = std::mem::take(&mut self.population);
+                pool.extend(offspring);
+
+                // stable, so parents survive ties with their offspring
+                let mut order = Ranking::of(&pool).order();
+                order.truncate(self.config.population_size);
+                order.sort_unstable();
+
+                let mut pool: Vec<Option<Individual>> = pool.into_iter().map(Some).collect();
+                self.population = order
+                    .into_iter()
+                    .map(|index| pool[index].take().unwrap())
+                    .collect();
+            }
+        }
+
+        self.observe(operators);
+    }
+
+    /// Scores the current population for selection when novelty search is configured
+    ///
+    /// With `offer`, the members born since the last offer are then offered to the archive. The
+    /// scores only depend on the state saved between generations, so resumed runs select alike.
+    fn assess_novelty(&mut self, offer: bool) {
+        if let Some(config) = self.config.novelty {
+            let archive = self.archive.get_or_insert_with(BehaviourArchive::new);
+            let novelty = archive.novelty(&self.population, config.k);
+            if offer {
+                archive.offer(&mut self.rng, &config, &self.population, &novelty);
+            }
+
+            self.novelty_scores = Some(novelty::scores(&config, &self.population, &novelty));
+        }
+    }
+
+    /// Clusters the current population when speciation is configured
+    fn speciate(&mut self) {
+        if let Some(speciation) = self.config.speciation {
+            let species = self.species.get_or_insert_with(SpeciesSet::new);
+            species.speciate(
+                &mut self.rng,
+                self.blueprints,
+                &self.population,
+                speciation.threshold,
+                &self.config.expression,
+            );
+        }
+    }
+
+    /// Produces and evaluates one offspring, counting whether it improved on its parent
+    ///
+    /// With several objectives, an improvement is an offspring dominating its parent.
+    fn breed(&mut self, operators: &mut OperatorStats) -> Individual {
+        let offspring = self.offspring();
+        let parent = offspring.parents[0];
+        let parent_objectives = self
+            .population
+            .iter()
+            .find(|individual| individual.id == parent)
+            .map(|individual| individual.objectives().to_vec())
+            .unwrap_or_else(|| vec![f64::NEG_INFINITY]);
+        let recombined = matches!(offspring.operator, Operator::Recombination(_));
+
+        let individual = self.birth(offspring);
+        let success = dominates(individual.objectives(), &parent_objectives);
+
+        if recombined {
+            operators.recombinations += 1;
+            operators.recombination_successes += success as usize;
+        } else {
+            operators.mutations += 1;
+            operators.mutation_successes += success as usize;
+        }
+
+        individual
+    }
+
+    /// Index of the member replaced in steady state, never one of the `elitism` best
+    fn removal(&mut self, removal: Removal) -> usize {
+        let mut order: Vec<usize> = (0..self.population.len()).collect();
+        order.sort_by(|&a, &b| {
+            self.population[b]
+                .fitness
+                .total_cmp(&self.population[a].fitness)
+        });
+        let protected = self.config.elitism.min(order.len().saturating_sub(1));
+        let candidates = &order[protected..];
+
+        match removal {
+            Removal::Worst => candidates[candidates.len() - 1],
+            Removal::InverseTournament(size) => {
+                let mut loser = candidates[self.rng.gen_range(0, candidates.len())];
+
+                for _ in 1..size {
+                    let contender = candidates[self.rng.gen_range(0, candidates.len())];
+                    if self.population[contender].fitness < self.population[loser].fitness {
+                        loser = contender;
+                    }
+                }
+
+                loser
+            }
+        }
+    }
+
+    /// Registers an observer, shown a report after every generation
+    ///
+    /// Observers added before the first generation are also shown the ancestors.
+    pub fn add_observer(&mut self, mut observer: Box<dyn Observer + 'a>) {
+        if let (0, Some(statistics)) = (self.generation, self.statistics.last()) {
+            observer.observe(&Report {
+                population: &self.population,
+                statistics,
+            });
+        }
+
+        self.observers.push(observer);
+    }
+
+    fn observe(&mut self, operators: OperatorStats) {
+        let statistics = Statistics::compute(
+            self.generation,
+            self.blueprints,
+            &self.population,
+            &operators,
+            &self.cache,
+        );
+        self.cache.start_generation();
+
+        let report = Report {
+            population: &self.population,
+            statistics: &statistics,
+        };
+
+        for observer in &mut self.observers {
+            observer.observe(&report);
+        }
+
+        self.statistics.push(statistics);
+    }
+
+    /// Runs the remaining generations, returning the best individual
+    pub fn run(&mut self) -> Option<&Individual> {
+        while self.generation < self.config.generations {
+            self.step();
+        }
+
+        self.best()
+    }
+
+    /// Runs generations until the condition holds, checking it before every generation
+    pub fn run_until(&mut self, stop: &StopCondition) -> RunResult {
+        let start = Instant::now();
+
+        let reason = loop {
+            if let Some(reason) = stop.check(&self.progress(start.elapsed())) {
+                break reason;
+            }
+
+            self.step();
+        };
+
+        RunResult {
+            best: self.best().cloned(),
+            reason,
+            generation: self.generation,
+            evaluations: self.evaluations,
+            elapsed: start.elapsed(),
+        }
+    }
+
+    /// Progress of the run, given the time spent so far
+    pub fn progress(&self, elapsed: Duration) -> Progress {
+        let best_fitness = self.best().map_or(f64::NEG_INFINITY, |best| best.fitness);
+
+        let mut best_so_far = f64::NEG_INFINITY;
+        let mut last_improvement = 0;
+        for (index, statistics) in self.statistics.iter().enumerate() {
+            if statistics.best_fitness > best_so_far {
+                best_so_far = statistics.best_fitness;
+                last_improvement = index;
+            }
+        }
+        let generations_without_improvement =
+            self.statistics.len().saturating_sub(last_improvement + 1);
+
+        Progress {
+            generation: self.generation,
+            evaluations: self.evaluations,
+            elapsed,
+            best_fitness,
+            generations_without_improvement,
+        }
+    }
+
+    /// Runs the remaining generations, saving a checkpoint to `path` every `interval` generations
+    pub fn run_with_checkpoints<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        interval: usize,
+    ) -> io::Result<Option<&Individual>> {
+        while self.generation < self.config.generations {
+            self.step();
+
+            if interval > 0 && self.generation % interval == 0 {
+                self.checkpoint().save(&path)?;
+            }
+        }
+
+        Ok(self.best())
+    }
+
+    /// The best `config.elitism` individuals, best first
+    pub fn elite(&self) -> Vec<&Individual> {
+        let mut sorted: Vec<&Individual> = self.population.iter().collect();
+        sorted.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+        sorted.truncate(self.config.elitism);
+        sorted
+    }
+
+    /// Members of the population no other member dominates
+    pub fn pareto_front(&self) -> Vec<&Individual> {
+        let ranking = Ranking::of(&self.population);
+
+        self.population
+            .iter()
+            .zip(ranking.rank)
+            .filter(|(_, rank)| *rank == 0)
+            .map(|(individual, _)| individual)
+            .collect()
+    }
+
+    /// Members of the Pareto front with their phenotypes, expressed as during the run
+    pub fn pareto_front_phenotypes(&self) -> Vec<(&Individual, Phenotype)> {
+        self.pareto_front()
+            .into_iter()
+            .map(|individual| {
+                let phenotype = express_with(
+                    self.blueprints,
+                    &individual.chromosome,
+                    &self.config.expression,
+                );
+                (individual, phenotype)
+            })
+            .collect()
+    }
+
+    /// The fittest individual, `None` if the population is empty
+    pub fn best(&self) -> Option<&Individual> {
+        self.population
+            .iter()
+            .max_by(|a, b| a.fitness.total_cmp(&b.fitness))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{express, ComponentIndex};
+    use crate::{pretty, testing};
+
+    fn reordered_shared_sum() -> Phenotype {
+        // the nodes of `testing::shared_sum` in reverse order
+        let reversed = |node: usize| 6 - node;
+        let phenotype = testing::shared_sum();
+
+        let nodes: Vec<(&str, ComponentIndex)> = (0..7)
+            .rev()
+            .map(|node| {
+                let expressed = &phenotype[NodeIndex::new(node)];
+                (expressed.label.as_str(), expressed.index)
+            })
+            .collect();
+        let edges: Vec<(usize, usize, Binding)> = phenotype
+            .raw_edges()
+            .iter()
+            .map(|edge| {
+                (
+                    reversed(edge.source().index()),
+                    reversed(edge.target().index()),
+                    edge.weight,
+                )
+            })
+            .collect();
+
+        testing::phenotype(&nodes, &edges)
+    }
+
+    #[test]
+    fn hash_and_key_ignore_node_indices() {
+        let a = testing::shared_sum();
+        let b = reordered_shared_sum();
+
+        assert_eq!(phenotype_hash(&a), phenotype_hash(&b));
+        assert_eq!(phenotype_key(&a), phenotype_key(&b));
+
+        let mut relabeled = testing::shared_sum();
+        relabeled[NodeIndex::new(3)].label = String::from("1");
+        assert_ne!(phenotype_hash(&a), phenotype_hash(&relabeled));
+        assert_ne!(phenotype_key(&a), phenotype_key(&relabeled));
+    }
+
+    #[test]
+    fn hash_and_key_include_the_bindings() {
+        let a = testing::shared_sum();
+
+        // the weak binding comes from another binding site, the arguments stay in order
+        let mut rebound = testing::shared_sum();
+        let weak = rebound
+            .find_edge(NodeIndex::new(5), NodeIndex::new(2))
+            .unwrap();
+        rebound[weak] = Binding::Weak(1);
+
+        assert_eq!(pretty::sexpr(&a), pretty::sexpr(&rebound));
+        assert_ne!(phenotype_hash(&a), phenotype_hash(&rebound));
+        assert_ne!(phenotype_key(&a), phenotype_key(&rebound));
+    }
+
+    #[test]
+    fn cache_hits_only_equal_phenotypes() {
+        let phenotype = testing::shared_sum();
+        let mut cache = FitnessCache::new();
+
+        fn objectives(
+            cache: &mut FitnessCache,
+            phenotype: &Phenotype,
+            values: Vec<f64>,
+        ) -> Vec<f64> {
+            cache.score(phenotype, |_| values).objectives
+        }
+
+        assert_eq!(objectives(&mut cache, &phenotype, vec![1.]), vec![1.]);
+        assert_eq!(
+            objectives(&mut cache, &reordered_shared_sum(), vec![2.]),
+            vec![1.]
+        );
+        assert_eq!((cache.hits, cache.misses, cache.len()), (1, 1, 1));
+
+        // a different phenotype filed under the same hash is a miss
+        let hash = phenotype_hash(&phenotype);
+        cache.clear();
+        let other = Evaluation {
+            objectives: vec![5.],
+            behaviour: vec![],
+        };
+        cache
+            .scores
+            .insert(hash, vec![(String::from("other"), other)]);
+        cache.order.push_back(hash);
+        assert_eq!(
+            objectives(&mut cache, &phenotype, vec![3., 1.]),
+            vec![3., 1.]
+        );
+        assert_eq!(objectives(&mut cache, &phenotype, vec![4.]), vec![3., 1.]);
+        assert_eq!((cache.hits, cache.misses, cache.len()), (1, 1, 2));
+    }
+
+    #[test]
+    fn hashes_are_fixed() {
+        // saved in checkpoints, so they must not change with the toolchain
+        assert_eq!(
+            phenotype_hash(&testing::shared_sum()),
+            0x3a7c_59ef_7793_6cfd
+        );
+    }
+
+    #[test]
+    fn truncated_cache_keeps_the_newest_scores() {
+        let blueprints = testing::arithmetic();
+        let phenotypes: Vec<Phenotype> = testing::ancestors(&blueprints, 3, 3)
+            .iter()
+            .map(|chromosome| express(&blueprints, chromosome))
+            .collect();
+        let mut cache = FitnessCache::new();
+
+        for (score, phenotype) in phenotypes.iter().enumerate() {
+            cache.score(phenotype, |_| score as f64);
+        }
+        cache.truncate(2);
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.score(&phenotypes[2], |_| 10.).objectives, vec![2.]);
+        assert_eq!(cache.score(&phenotypes[0], |_| 10.).objectives, vec![10.]);
+    }
+
+    #[test]
+    fn cache_keeps_behaviours() {
+        let phenotype = testing::shared_sum();
+        let mut cache = FitnessCache::new();
+        let evaluate = |behaviour| WithBehaviour {
+            fitness: 1.,
+            behaviour,
+        };
+
+        let first = cache.score(&phenotype, |_| evaluate(vec![2., 3.]));
+        assert_eq!(cache.score(&phenotype, |_| evaluate(vec![])), first);
+        assert_eq!(first.behaviour, vec![2., 3.]);
+        assert_eq!(cache.hits, 1);
+    }
+
+    #[test]
+    fn cached_scores_match_fresh_ones() {
+        let blueprints = testing::arithmetic();
+        let mut evolution = Evolution::new(&blueprints, testing::config(1), testing::size);
+        evolution.run();
+
+        for individual in &evolution.population {
+            let phenotype = express(&blueprints, &individual.chromosome);
+            assert_eq!(individual.fitness, testing::size(&phenotype));
+        }
+        assert!(evolution.cache.hits > 0);
+        assert_eq!(evolution.evaluations, evolution.cache.misses);
+    }
+
+    #[test]
+    fn elitism_keeps_the_best() {
+        let blueprints = testing::arithmetic();
+        let mut evolution = Evolution::new(&blueprints, testing::config(2), testing::size);
+
+        run_monotonic(&mut evolution);
+    }
+
+    /// Runs five generations, checking that the best fitness never drops
+    fn run_monotonic<F: FnMut(&Phenotype) -> f64>(evolution: &mut Evolution<F>) {
+        let mut best = evolution.best().unwrap().fitness;
+
+        for _ in 0..5 {
+            evolution.step();
+            let fitness = evolution.best().unwrap().fitness;
+            assert!(fitness >= best);
+            best = fitness;
+        }
+    }
+
+    #[test]
+    fn steady_state_never_removes_the_elite() {
+        let blueprints = testing::arithmetic();
+
+        for removal in [Removal::Worst, Removal::InverseTournament(3)] {
+            let config = Config {
+                replacement: Replacement::SteadyState(removal),
+                elitism: 2,
+                cache_fitness: false,
+                ..testing::config(7)
+            };
+            let mut evolution = Evolution::new(&blueprints, config, testing::size);
+
+            run_monotonic(&mut evolution);
+            assert_eq!(evolution.population.len(), 20);
+            assert_eq!(evolution.evaluations, 20 * 6);
+        }
+    }
+
+    #[test]
+    fn comma_selection_keeps_only_the_elite_parents() {
+        let blueprints = testing::arithmetic();
+        let config = Config {
+            replacement: Replacement::MuCommaLambda { lambda: 30 },
+            elitism: 2,
+            cache_fitness: false,
+            ..testing::config(8)
+        };
+        let mut evolution = Evolution::new(&blueprints, config, testing::size);
+        let elite: Vec<IndividualId> = evolution.elite().iter().map(|best| best.id).collect();
+
+        evolution.step();
+        assert_eq!(evolution.population.len(), 20);
+        assert_eq!(evolution.evaluations, 20 + 30);
+        for individual in &evolution.population {
+            assert!(elite.contains(&individual.id) || individual.id >= 20);
+        }
+    }
+
+    #[test]
+    fn comma_selection_needs_offspring_for_every_place() {
+        let blueprints = testing::arithmetic();
+        let config = |lambda| Config {
+            replacement: Replacement::MuCommaLambda { lambda },
+            elitism: 2,
+            ..testing::config(9)
+        };
+
+        assert!(config(18).validate().is_ok());
+        assert_eq!(
+            config(17).validate(),
+            Err(ConfigError::TooFewOffspring {
+                lambda: 17,
+                required: 18
+            })
+        );
+        assert!(Evolution::try_new(&blueprints, config(5), testing::size).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid config")]
+    fn configs_changed_after_creation_are_checked() {
+        let blueprints = testing::arithmetic();
+        let mut evolution = Evolution::new(&blueprints, testing::config(10), testing::size);
+
+        evolution.config.replacement = Replacement::MuCommaLambda { lambda: 5 };
+        evolution.step();
+    }
+
+    #[test]
+    fn plus_selection_keeps_the_best_of_both() {
+        let blueprints = testing::arithmetic();
+        let config = Config {
+            replacement: Replacement::MuPlusLambda { lambda: 10 },
+            elitism: 0,
+            cache_fitness: false,
+            ..testing::config(9)
+        };
+        let mut evolution = Evolution::new(&blueprints, config, testing::size);
+
+        run_monotonic(&mut evolution);
+        assert_eq!(evolution.population.len(), 20);
+        assert_eq!(evolution.evaluations, 20 + 5 * 10);
+    }
+
+    #[test]
+    fn empty_population_has_no_best() {
+        let blueprints = testing::arithmetic();
+        let mut evolution = Evolution::new(&blueprints, testing::config(3), testing::size);
+        evolution.population.clear();
+
+        assert!(evolution.best().is_none());
+    }
+
+    struct Generations<'g>(&'g mut Vec<usize>);
+
+    impl<'g> Observer for Generations<'g> {
+        fn observe(&mut self, report: &Report) {
+            self.0.push(report.statistics.generation);
+        }
+    }
+
+    #[test]
+    fn observers_see_the_ancestors() {
+        let blueprints = testing::arithmetic();
+        let mut generations = vec![];
+
+        let mut evolution = Evolution::new(&blueprints, testing::config(6), testing::size);
+        evolution.add_observer(Box::new(Generations(&mut generations)));
+        evolution.run();
+        drop(evolution);
+
+        assert_eq!(generations, vec![0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn nsga2_keeps_a_ranked_population() {
+        let blueprints = testing::arithmetic();
+        let config = Config {
+            replacement: Replacement::Nsga2,
+            expression: testing::expression(),
+            ..testing::config(4)
+        };
+        // larger phenotypes against fewer distinct labels
+        let objectives = |phenotype: &Phenotype| {
+            let labels: std::collections::HashSet<&str> = phenotype
+                .node_weights()
+                .map(|expressed| expressed.label.as_str())
+                .collect();
+            vec![testing::size(phenotype), -(labels.len() as f64)]
+        };
+
+        let mut evolution = Evolution::new(&blueprints, config, objectives);
+        evolution.run();
+
+        assert_eq!(evolution.population.len(), 20);
+        for individual in &evolution.population {
+            assert_eq!(individual.objectives.len(), 2);
+            assert_eq!(individual.fitness, individual.objectives[0]);
+        }
+
+        let front = evolution.pareto_front();
+        assert!(!front.is_empty());
+        for a in &front {
+            assert!(evolution
+                .population
+                .iter()
+                .all(|b| !dominates(b.objectives(), a.objectives())));
+        }
+        for (individual, phenotype) in evolution.pareto_front_phenotypes() {
+            assert_eq!(objectives(&phenotype), individual.objectives);
+        }
+        assert_eq!(evolution.statistics.len(), 6);
+    }
+}
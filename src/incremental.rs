@@ -0,0 +1,619 @@
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use petgraph::visit::EdgeRef;
+use std::collections::{HashMap, HashSet};
+
+use crate::blueprints::Blueprints;
+use crate::chromosome::EgpChromosome;
+use crate::component::Component;
+use crate::operators::Mutation;
+use crate::vectors;
+use crate::{
+    choose_component, component_at, group_of, min_satisfying, resume_walk, Binding, ComponentIndex,
+    Expressed, ExpressionConfig, OutputSharing, Phenotype, Resume, Walk,
+};
+
+/// The component chosen by a binding site during expression
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Choice {
+    pub owner: ComponentIndex,
+    pub binding_site: usize,
+    pub chosen: ComponentIndex,
+}
+
+/// Distances from a binding site to the components of its group
+struct SiteDistances {
+    group: usize,
+    regular: Vec<f32>,
+    terminal: (usize, f32), // nearest terminal, these don't change
+}
+
+/// Expression cache for re-expressing a chromosome after `mutate`
+///
+/// Records which binding site chose which component, along with the distances behind each
+/// choice. After a mutation only the distances involving the mutated binding site or the mutated
+/// component's profile are recomputed, and only the choices depending on them are made again.
+/// Expression resumes from the first choice that changed: the nodes and strong edges expressed
+/// before it are kept, the rest of the phenotype is expressed again and the weak bindings are
+/// satisfied again. If no choice changed, only the weak bindings are updated.
+///
+/// Recombination changes the number of components, the cache is rebuilt when it detects that.
+pub struct ExpressionCache {
+    phenotype: Phenotype,
+    choices: Vec<Choice>,
+    profiles: Vec<Vec<Vec<f32>>>, // regular profiles, by group
+    terminal_profiles: Vec<Vec<Vec<f32>>>,
+    sites: HashMap<(ComponentIndex, usize), SiteDistances>, // computed on first use
+    rewalked: usize,
+    config: ExpressionConfig,
+}
+
+impl ExpressionCache {
+    /// Expresses the chromosome like `express`, recording the choices made
+    pub fn new(blueprints: &Blueprints, chromosome: &EgpChromosome) -> ExpressionCache {
+        ExpressionCache::new_with(blueprints, chromosome, &ExpressionConfig::default())
+    }
+
+    /// Expresses the chromosome like `express_with`, re-expressing it later with the same
+    /// parameters
+    pub fn new_with(
+        blueprints: &Blueprints,
+        chromosome: &EgpChromosome,
+        config: &ExpressionConfig,
+    ) -> ExpressionCache {
+        let total_activities = blueprints.total_activities;
+        let profiles = group_profiles(&chromosome.regular, total_activities, config.input_bias);
+        let terminal_profiles =
+            group_profiles(&blueprints.terminal, total_activities, config.input_bias);
+
+        let mut cache = ExpressionCache {
+            phenotype: Phenotype::new(),
+            choices: vec![],
+            profiles,
+            terminal_profiles,
+            sites: HashMap::new(),
+            rewalked: 0,
+            config: *config,
+        };
+
+        let (walk, choices) = cache.walk(blueprints, chromosome, Phenotype::new(), None);
+        cache.phenotype = walk.finish(blueprints, chromosome, config.input_bias);
+        cache.rewalked = choices.len();
+        cache.choices = choices;
+
+        cache
+    }
+
+    pub fn phenotype(&self) -> &Phenotype {
+        &self.phenotype
+    }
+
+    pub fn into_phenotype(self) -> Phenotype {
+        self.phenotype
+    }
+
+    /// Binding site choices in expression order
+    pub fn choices(&self) -> &[Choice] {
+        &self.choices
+    }
+
+    /// Number of choices expressed again by the last `reexpress`, all of them after a rebuild
+    pub fn rewalked(&self) -> usize {
+        self.rewalked
+    }
+
+    /// Updates the phenotype after `chromosome` was changed as described by `mutation`
+    ///
+    /// Returns whether the phenotype changed.
+    pub fn reexpress(
+        &mut self,
+        blueprints: &Blueprints,
+        chromosome: &EgpChromosome,
+        mutation: &Mutation,
+    ) -> bool {
+        self.rewalked = 0;
+
+        if !self.matches_shape(chromosome) {
+            *self = ExpressionCache::new_with(blueprints, chromosome, &self.config);
+            return true;
+        }
+
+        let (mutated, binding_site) = match *mutation {
+            Mutation::Unchanged => return false,
+            Mutation::OutputBindingSite {
+                output,
+                binding_site,
+                ..
+            } => {
+                let mutated = ComponentIndex::Output(output);
+                self.sites.remove(&(mutated, binding_site));
+                (mutated, Some(binding_site))
+            }
+            Mutation::BindingSite {
+                group,
+                index,
+                binding_site,
+                ..
+            } => {
+                let mutated = ComponentIndex::Regular(group, index);
+                self.sites.remove(&(mutated, binding_site));
+                self.update_profile(blueprints, chromosome, group, index);
+                (mutated, Some(binding_site))
+            }
+            Mutation::Activity { group, index } => {
+                self.update_profile(blueprints, chromosome, group, index);
+                (ComponentIndex::Regular(group, index), None)
+            }
+            Mutation::Constant { group, index } => {
+                // the profile doesn't depend on the value, so only the expressed node changes
+                let mutated = ComponentIndex::Regular(group, index);
+                let value = chromosome.regular[group][index].value;
+
+                return match self
+                    .phenotype
+                    .node_weights_mut()
+                    .find(|expressed| expressed.index == mutated)
+                {
+                    Some(expressed) => {
+                        expressed.value = value;
+                        true
+                    }
+                    None => false,
+                };
+            }
+        };
+
+        let relabeled = self.relabel(blueprints, chromosome, mutated);
+
+        if let Some((step, expressed_regulars)) =
+            self.first_change(blueprints, chromosome, mutated, binding_site)
+        {
+            self.resume(blueprints, chromosome, step, expressed_regulars);
+            return true;
+        }
+
+        // the strong structure is unchanged, weak bindings can still change through the profile
+        let expressed = self
+            .phenotype
+            .node_weights()
+            .any(|expressed| expressed.index == mutated);
+
+        if !expressed {
+            return false;
+        }
+
+        let weak_before = weak_edges(&self.phenotype);
+        let mut phenotype = std::mem::take(&mut self.phenotype);
+        truncate_edges(&mut phenotype, self.choices.len());
+        self.phenotype = Walk::new(blueprints, chromosome, phenotype).finish(
+            blueprints,
+            chromosome,
+            self.config.input_bias,
+        );
+
+        relabeled || weak_edges(&self.phenotype) != weak_before
+    }
+
+    fn matches_shape(&self, chromosome: &EgpChromosome) -> bool {
+        self.profiles.len() == chromosome.regular.len()
+            && self
+                .profiles
+                .iter()
+                .zip(chromosome.regular.iter())
+                .all(|(profiles, group)| profiles.len() == group.len())
+    }
+
+    fn update_profile(
+        &mut self,
+        blueprints: &Blueprints,
+        chromosome: &EgpChromosome,
+        group: usize,
+        index: usize,
+    ) {
+        let profile = chromosome.regular[group][index]
+            .profile(blueprints.total_activities, self.config.input_bias);
+
+        for ((owner, binding_site), site) in self.sites.iter_mut() {
+            if site.group == group {
+                let binding =
+                    &component_at(blueprints, chromosome, *owner).binding_sites[*binding_site];
+                site.regular[index] = vectors::distance(binding, &profile);
+            }
+        }
+
+        self.profiles[group][index] = profile;
+    }
+
+    /// Updates the expressed node of a mutated component, returns whether it changed
+    fn relabel(
+        &mut self,
+        blueprints: &Blueprints,
+        chromosome: &EgpChromosome,
+        mutated: ComponentIndex,
+    ) -> bool {
+        let component = component_at(blueprints, chromosome, mutated);
+
+        match self
+            .phenotype
+            .node_weights_mut()
+            .find(|expressed| expressed.index == mutated)
+        {
+            Some(expressed)
+                if expressed.activity != component.activity
+                    || expressed.label != component.label =>
+            {
+                *expressed = Expressed::from_component(component, mutated);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Replays the cached choices, making again only those whose distances changed
+    ///
+    /// Returns the first choice that differs along with the regulars expressed before it.
+    fn first_change(
+        &mut self,
+        blueprints: &Blueprints,
+        chromosome: &EgpChromosome,
+        mutated: ComponentIndex,
+        binding_site: Option<usize>,
+    ) -> Option<(usize, HashSet<ComponentIndex>)> {
+        let profile_group = match mutated {
+            ComponentIndex::Regular(group, _) => Some(group),
+            _ => None,
+        };
+        let profiles = &self.profiles;
+        let terminal_profiles = &self.terminal_profiles;
+        let sites = &mut self.sites;
+        let mut expressed_regulars = HashSet::new();
+        let mut shared = HashSet::new();
+        let mut output = None;
+
+        for (step, choice) in self.choices.iter().enumerate() {
+            // the first choices of an output are those of its binding sites
+            if let ComponentIndex::Output(index) = choice.owner {
+                if output != Some(index) {
+                    if self.config.sharing == OutputSharing::Shared {
+                        shared.extend(expressed_regulars.drain());
+                    }
+                    output = Some(index);
+                }
+            }
+
+            let group = group_of(choice.chosen);
+            let affected = (choice.owner == mutated && Some(choice.binding_site) == binding_site)
+                || profile_group == Some(group);
+
+            if affected {
+                let site = sites
+                    .entry((choice.owner, choice.binding_site))
+                    .or_insert_with(|| {
+                        let binding = &component_at(blueprints, chromosome, choice.owner)
+                            .binding_sites[choice.binding_site];
+                        site_distances(binding, group, profiles, terminal_profiles)
+                    });
+
+                if nearest(site, &expressed_regulars) != choice.chosen {
+                    return Some((step, expressed_regulars));
+                }
+            }
+
+            // shared regulars are linked rather than expressed again
+            if let ComponentIndex::Regular(_, _) = choice.chosen {
+                if !shared.contains(&choice.chosen) {
+                    expressed_regulars.insert(choice.chosen);
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Discards the expression from choice `step` on and expresses it again
+    fn resume(
+        &mut self,
+        blueprints: &Blueprints,
+        chromosome: &EgpChromosome,
+        step: usize,
+        expressed_regulars: HashSet<ComponentIndex>,
+    ) {
+        let mut phenotype = std::mem::take(&mut self.phenotype);
+
+        // the strong edge at `step` is the choice, its source the node choosing
+        let node = phenotype.raw_edges()[step].source();
+        let kept_nodes = phenotype.raw_edges()[..step]
+            .iter()
+            .map(|edge| edge.target().index())
+            .fold(node.index(), usize::max)
+            + 1;
+
+        // the nodes expressed after the choice come last, like their edges
+        truncate_edges(&mut phenotype, step);
+        while phenotype.node_count() > kept_nodes {
+            phenotype.remove_node(NodeIndex::new(phenotype.node_count() - 1));
+        }
+
+        let resume = Resume {
+            node,
+            binding_site: self.choices[step].binding_site,
+            expressed_regulars,
+        };
+        self.choices.truncate(step);
+
+        let (walk, choices) = self.walk(blueprints, chromosome, phenotype, Some(resume));
+        self.phenotype = walk.finish(blueprints, chromosome, self.config.input_bias);
+        self.rewalked = choices.len();
+        self.choices.extend(choices);
+    }
+
+    fn walk(
+        &mut self,
+        blueprints: &Blueprints,
+        chromosome: &EgpChromosome,
+        phenotype: Phenotype,
+        resume: Option<Resume>,
+    ) -> (Walk, Vec<Choice>) {
+        let profiles = &self.profiles;
+        let terminal_profiles = &self.terminal_profiles;
+        let sites = &mut self.sites;
+        let mut choices = vec![];
+
+        let walk = resume_walk(
+            blueprints,
+            chromosome,
+            self.config.sharing,
+            phenotype,
+            resume,
+            |owner, binding_site, group, expressed_regulars| {
+                let site = sites.entry((owner, binding_site)).or_insert_with(|| {
+                    let binding =
+                        &component_at(blueprints, chromosome, owner).binding_sites[binding_site];
+                    site_distances(binding, group, profiles, terminal_profiles)
+                });
+
+                let chosen = nearest(site, expressed_regulars);
+
+                choices.push(Choice {
+                    owner,
+                    binding_site,
+                    chosen,
+                });
+
+                chosen
+            },
+        );
+
+        (walk, choices)
+    }
+}
+
+/// Component chosen by a binding site, given the regulars already expressed
+fn nearest(site: &SiteDistances, expressed_regulars: &HashSet<ComponentIndex>) -> ComponentIndex {
+    let regular = min_satisfying(site.regular.iter().cloned(), |index| {
+        !expressed_regulars.contains(&ComponentIndex::Regular(site.group, index))
+    });
+
+    choose_component(site.group, regular, site.terminal)
+}
+
+fn group_profiles(
+    groups: &[Vec<Component>],
+    total_activities: usize,
+    input_bias: f32,
+) -> Vec<Vec<Vec<f32>>> {
+    groups
+        .iter()
+        .map(|group| {
+            group
+                .iter()
+                .map(|component| component.profile(total_activities, input_bias))
+                .collect()
+        })
+        .collect()
+}
+
+fn site_distances(
+    binding: &[f32],
+    group: usize,
+    profiles: &[Vec<Vec<f32>>],
+    terminal_profiles: &[Vec<Vec<f32>>],
+) -> SiteDistances {
+    let regular = profiles[group]
+        .iter()
+        .map(|profile| vectors::distance(binding, profile))
+        .collect();
+
+    let terminal = min_satisfying(
+        terminal_profiles[group]
+            .iter()
+            .map(|profile| vectors::distance(binding, profile)),
+        |_| true,
+    )
+    .unwrap(); // we can always find a terminal, they can be expressed multiple times
+
+    SiteDistances {
+        group,
+        regular,
+        terminal,
+    }
+}
+
+/// Keeps the first `count` edges, weak edges come after the strong edges of each choice
+fn truncate_edges(phenotype: &mut Phenotype, count: usize) {
+    while phenotype.edge_count() > count {
+        phenotype.remove_edge(EdgeIndex::new(phenotype.edge_count() - 1));
+    }
+}
+
+fn weak_edges(phenotype: &Phenotype) -> Vec<(usize, usize, Binding)> {
+    phenotype
+        .edge_references()
+        .filter(|edge| matches!(edge.weight(), Binding::Weak(_)))
+        .map(|edge| (edge.source().index(), edge.target().index(), *edge.weight()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blueprints::Ephemeral;
+    use crate::operators::{mutate_with_rng, recombine_with_rng};
+    use crate::problems::regression::arithmetic_blueprints_with_ephemeral;
+    use crate::testing;
+    use crate::{express, express_with};
+
+    #[test]
+    fn reexpress_matches_express_after_mutations() {
+        let blueprints = testing::arithmetic();
+        let mut rng = testing::rng(1);
+
+        for mut chromosome in testing::ancestors(&blueprints, 2, 10) {
+            let mut cache = ExpressionCache::new(&blueprints, &chromosome);
+            assert!(testing::same(
+                cache.phenotype(),
+                &express(&blueprints, &chromosome)
+            ));
+
+            for _ in 0..50 {
+                let before = express(&blueprints, &chromosome);
+                let mutation = mutate_with_rng(&mut rng, &blueprints, &mut chromosome);
+                let changed = cache.reexpress(&blueprints, &chromosome, &mutation);
+                let expected = express(&blueprints, &chromosome);
+
+                assert!(testing::same(cache.phenotype(), &expected));
+                if !changed {
+                    assert!(testing::same(&before, &expected));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reexpress_keeps_the_expression_before_the_first_change() {
+        let blueprints = testing::arithmetic();
+        let mut rng = testing::rng(30);
+        let (mut total, mut rewalked, mut partial) = (0, 0, 0);
+
+        for mut chromosome in testing::ancestors(&blueprints, 31, 10) {
+            let mut cache = ExpressionCache::new(&blueprints, &chromosome);
+
+            for _ in 0..50 {
+                let before = cache.choices().to_vec();
+                let mutation = mutate_with_rng(&mut rng, &blueprints, &mut chromosome);
+                cache.reexpress(&blueprints, &chromosome, &mutation);
+
+                // only the choices after the first change were made again
+                let kept = cache.choices().len() - cache.rewalked();
+                assert_eq!(cache.choices()[..kept], before[..kept]);
+                assert!(testing::same(
+                    cache.phenotype(),
+                    &express(&blueprints, &chromosome)
+                ));
+
+                total += cache.choices().len();
+                rewalked += cache.rewalked();
+                if cache.rewalked() > 0 && kept > 0 {
+                    partial += 1;
+                }
+            }
+        }
+
+        assert!(partial > 0);
+        assert!(rewalked * 2 < total);
+    }
+
+    #[test]
+    fn reexpress_rebuilds_after_recombination() {
+        let blueprints = testing::arithmetic();
+        let mut rng = testing::rng(3);
+        let parents = testing::ancestors(&blueprints, 4, 10);
+
+        for pair in parents.windows(2) {
+            let mut cache = ExpressionCache::new(&blueprints, &pair[0]);
+            let (child, _) = recombine_with_rng(&mut rng, &blueprints, 5, &pair[0], &pair[1]);
+
+            cache.reexpress(&blueprints, &child, &Mutation::Unchanged);
+            assert!(testing::same(
+                cache.phenotype(),
+                &express(&blueprints, &child)
+            ));
+        }
+    }
+
+    #[test]
+    fn reexpress_keeps_the_expression_parameters() {
+        let blueprints = testing::arithmetic();
+        let config = testing::expression();
+        let mut rng = testing::rng(5);
+        let mut differs = false;
+
+        for pair in testing::ancestors(&blueprints, 6, 10).windows(2) {
+            let mut chromosome = pair[0].clone();
+            let mut cache = ExpressionCache::new_with(&blueprints, &chromosome, &config);
+            differs |= !testing::same(cache.phenotype(), &express(&blueprints, &chromosome));
+
+            for _ in 0..20 {
+                let mutation = mutate_with_rng(&mut rng, &blueprints, &mut chromosome);
+                cache.reexpress(&blueprints, &chromosome, &mutation);
+                assert!(testing::same(
+                    cache.phenotype(),
+                    &express_with(&blueprints, &chromosome, &config)
+                ));
+            }
+
+            // rebuilt with the same parameters
+            let (child, _) = recombine_with_rng(&mut rng, &blueprints, 5, &chromosome, &pair[1]);
+            cache.reexpress(&blueprints, &child, &Mutation::Unchanged);
+            assert!(testing::same(
+                cache.phenotype(),
+                &express_with(&blueprints, &child, &config)
+            ));
+        }
+
+        assert!(differs);
+    }
+
+    #[test]
+    fn reexpress_keeps_shared_outputs() {
+        let blueprints = testing::two_outputs();
+        let config = testing::shared_outputs();
+        let mut rng = testing::rng(15);
+
+        for mut chromosome in testing::ancestors(&blueprints, 16, 10) {
+            let mut cache = ExpressionCache::new_with(&blueprints, &chromosome, &config);
+
+            for _ in 0..20 {
+                let mutation = mutate_with_rng(&mut rng, &blueprints, &mut chromosome);
+                cache.reexpress(&blueprints, &chromosome, &mutation);
+                assert!(testing::same(
+                    cache.phenotype(),
+                    &express_with(&blueprints, &chromosome, &config)
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn reexpress_updates_constants_in_place() {
+        let ephemeral = Ephemeral {
+            min: -1.,
+            max: 1.,
+            sigma: 0.5,
+        };
+        let blueprints = arithmetic_blueprints_with_ephemeral(&["+", "*"], &["x"], Some(ephemeral));
+        let mut rng = testing::rng(22);
+
+        for mut chromosome in testing::ancestors(&blueprints, 23, 10) {
+            let mut cache = ExpressionCache::new(&blueprints, &chromosome);
+
+            for _ in 0..50 {
+                let mutation = mutate_with_rng(&mut rng, &blueprints, &mut chromosome);
+                cache.reexpress(&blueprints, &chromosome, &mutation);
+                assert!(testing::same(
+                    cache.phenotype(),
+                    &express(&blueprints, &chromosome)
+                ));
+            }
+        }
+    }
+}
@@ -0,0 +1,378 @@
+use petgraph::graph::NodeIndex;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::pretty::{arguments, output_nodes};
+use crate::Phenotype;
+
+/// Arithmetic operations known to the interpreter
+///
+/// Division, logarithm, square root and inverse are protected so every program is defined
+/// everywhere: `a / 0` and `inv(0)` are 1, `log` and `sqrt` take the absolute value and
+/// `log(0)` is 0.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Neg,
+    Inv,
+    Square,
+    Sqrt,
+    Sin,
+    Cos,
+    Exp,
+    Log,
+    Max,
+    Min,
+}
+
+impl Operation {
+    /// Operation named by a label: `+ - * / neg inv square sqrt sin cos exp log max min`
+    pub fn from_label(label: &str) -> Option<Operation> {
+        let operation = match label {
+            "+" => Operation::Add,
+            "-" => Operation::Sub,
+            "*" => Operation::Mul,
+            "/" => Operation::Div,
+            "neg" => Operation::Neg,
+            "inv" => Operation::Inv,
+            "square" => Operation::Square,
+            "sqrt" => Operation::Sqrt,
+            "sin" => Operation::Sin,
+            "cos" => Operation::Cos,
+            "exp" => Operation::Exp,
+            "log" => Operation::Log,
+            "max" => Operation::Max,
+            "min" => Operation::Min,
+            _ => return None,
+        };
+
+        Some(operation)
+    }
+
+    pub fn arity(self) -> usize {
+        match self {
+            Operation::Add
+            | Operation::Sub
+            | Operation::Mul
+            | Operation::Div
+            | Operation::Max
+            | Operation::Min => 2,
+            _ => 1,
+        }
+    }
+
+    /// Applies the operation to the first `arity` arguments
+    pub fn apply(self, arguments: &[f64]) -> f64 {
+        let a = arguments[0];
+        let b = || arguments[1];
+
+        match self {
+            Operation::Add => a + b(),
+            Operation::Sub => a - b(),
+            Operation::Mul => a * b(),
+            Operation::Div => {
+                if b() == 0. {
+                    1.
+                } else {
+                    a / b()
+                }
+            }
+            Operation::Neg => -a,
+            Operation::Inv => {
+                if a == 0. {
+                    1.
+                } else {
+                    1. / a
+                }
+            }
+            Operation::Square => a * a,
+            Operation::Sqrt => a.abs().sqrt(),
+            Operation::Sin => a.sin(),
+            Operation::Cos => a.cos(),
+            Operation::Exp => a.exp(),
+            Operation::Log => {
+                if a == 0. {
+                    0.
+                } else {
+                    a.abs().ln()
+                }
+            }
+            Operation::Max => a.max(b()),
+            Operation::Min => a.min(b()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterpreterError {
+    /// A label is neither a variable, an operation nor a number
+    UnknownLabel(String),
+    /// A node has fewer arguments than its operation needs, e.g. an unsatisfied weak binding
+    MissingArgument(String, usize),
+    /// A weak binding refers to an ancestor
+    Cycle(NodeIndex),
+    /// The phenotype has no output node, or the output node has no argument
+    NoOutput,
+}
+
+impl fmt::Display for InterpreterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterpreterError::UnknownLabel(label) => write!(f, "unknown label {}", label),
+            InterpreterError::MissingArgument(label, index) => {
+                write!(f, "{} has no argument {}", label, index)
+            }
+            InterpreterError::Cycle(node) => {
+                write!(f, "weak binding cycle through node {}", node.index())
+            }
+            InterpreterError::NoOutput => write!(f, "phenotype has no output"),
+        }
+    }
+}
+
+impl std::error::Error for InterpreterError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Instruction {
+    Variable(usize),
+    Constant(f64),
+    /// Operation and the registers of its arguments
+    Apply(Operation, Vec<usize>),
+}
+
+/// A phenotype compiled for repeated arithmetic evaluation
+///
+/// Each expressed node is computed once per evaluation, including nodes shared through weak
+/// bindings. The output node passes its first argument through.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    instructions: Vec<Instruction>,
+}
+
+impl Program {
+    /// Compiles a phenotype, terminals labelled with one of `variables` read the input at the
+    /// same position
+    pub fn compile(phenotype: &Phenotype, variables: &[&str]) -> Result<Program, InterpreterError> {
+        Program::compile_output(phenotype, variables, 0)
+    }
+
+    /// Compiles one output of a phenotype, see `compile`
+    pub fn compile_output(
+        phenotype: &Phenotype,
+        variables: &[&str],
+        output: usize,
+    ) -> Result<Program, InterpreterError> {
+        let output = *output_nodes(phenotype)
+            .get(output)
+            .ok_or(InterpreterError::NoOutput)?;
+        let result = *arguments(phenotype, output)
+            .first()
+            .ok_or(InterpreterError::NoOutput)?;
+
+        let mut compiler = Compiler {
+            phenotype,
+            variables,
+            registers: HashMap::new(),
+            instructions: vec![],
+        };
+        compiler.compile(result)?;
+
+        Ok(Program {
+            instructions: compiler.instructions,
+        })
+    }
+
+    /// Value of the program for one input
+    pub fn evaluate(&self, inputs: &[f64]) -> f64 {
+        let mut registers = Vec::with_capacity(self.instructions.len());
+        let mut arguments = vec![];
+
+        for instruction in &self.instructions {
+            let value = match instruction {
+                Instruction::Variable(index) => inputs[*index],
+                Instruction::Constant(value) => *value,
+                Instruction::Apply(operation, sources) => {
+                    arguments.clear();
+                    arguments.extend(sources.iter().map(|source| registers[*source]));
+                    operation.apply(&arguments)
+                }
+            };
+            registers.push(value);
+        }
+
+        registers.last().cloned().unwrap_or(0.)
+    }
+
+    /// Number of instructions, the expressed nodes below the output
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+}
+
+struct Compiler<'p> {
+    phenotype: &'p Phenotype,
+    variables: &'p [&'p str],
+    /// Register of every compiled node, `None` while its arguments are being compiled
+    registers: HashMap<NodeIndex, Option<usize>>,
+    instructions: Vec<Instruction>,
+}
+
+impl<'p> Compiler<'p> {
+    fn compile(&mut self, node: NodeIndex) -> Result<usize, InterpreterError> {
+        match self.registers.get(&node) {
+            Some(Some(register)) => return Ok(*register),
+            Some(None) => return Err(InterpreterError::Cycle(node)),
+            None => {}
+        }
+        self.registers.insert(node, None);
+
+        let label = &self.phenotype[node].label;
+
+        let instruction = if let Some(value) = self.phenotype[node].value {
+            Instruction::Constant(value)
+        } else if let Some(index) = self.variables.iter().position(|v| v == label) {
+            Instruction::Variable(index)
+        } else if let Some(operation) = Operation::from_label(label) {
+            let arguments = arguments(self.phenotype, node);
+            if arguments.len() < operation.arity() {
+                return Err(InterpreterError::MissingArgument(
+                    label.clone(),
+                    arguments.len(),
+                ));
+            }
+
+            let sources = arguments[..operation.arity()]
+                .iter()
+                .map(|argument| self.compile(*argument))
+                .collect::<Result<Vec<usize>, InterpreterError>>()?;
+
+            Instruction::Apply(operation, sources)
+        } else if let Ok(value) = label.parse::<f64>() {
+            Instruction::Constant(value)
+        } else {
+            return Err(InterpreterError::UnknownLabel(label.clone()));
+        };
+
+        let register = self.instructions.len();
+        self.instructions.push(instruction);
+        self.registers.insert(node, Some(register));
+
+        Ok(register)
+    }
+}
+
+/// Compiles and evaluates a phenotype once, see `Program`
+pub fn evaluate(
+    phenotype: &Phenotype,
+    variables: &[&str],
+    inputs: &[f64],
+) -> Result<f64, InterpreterError> {
+    Ok(Program::compile(phenotype, variables)?.evaluate(inputs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::{Binding, ComponentIndex};
+
+    const OUT: ComponentIndex = ComponentIndex::Output(0);
+    const REGULAR: ComponentIndex = ComponentIndex::Regular(0, 0);
+    const TERMINAL: ComponentIndex = ComponentIndex::Terminal(0, 0);
+
+    #[test]
+    fn protected_operations_are_defined_everywhere() {
+        assert_eq!(Operation::Div.apply(&[3., 0.]), 1.);
+        assert_eq!(Operation::Div.apply(&[3., 2.]), 1.5);
+        assert_eq!(Operation::Inv.apply(&[0.]), 1.);
+        assert_eq!(Operation::Sqrt.apply(&[-4.]), 2.);
+        assert_eq!(Operation::Log.apply(&[0.]), 0.);
+        assert_eq!(Operation::Log.apply(&[-1.]), 0.);
+        assert_eq!(Operation::from_label("max").unwrap().arity(), 2);
+        assert_eq!(Operation::from_label("use"), None);
+    }
+
+    #[test]
+    fn weakly_bound_nodes_are_computed_once() {
+        // (* (+ x 1) <weak +>)
+        let phenotype = testing::phenotype(
+            &[
+                ("out", OUT),
+                ("*", REGULAR),
+                ("+", REGULAR),
+                ("x", TERMINAL),
+                ("1", TERMINAL),
+            ],
+            &[
+                (0, 1, Binding::Strong(0)),
+                (1, 2, Binding::Strong(0)),
+                (1, 2, Binding::Weak(0)),
+                (2, 3, Binding::Strong(0)),
+                (2, 4, Binding::Strong(1)),
+            ],
+        );
+
+        let program = Program::compile(&phenotype, &["x"]).unwrap();
+        assert_eq!(program.len(), 4);
+        assert_eq!(program.evaluate(&[2.]), 9.);
+        assert_eq!(evaluate(&phenotype, &["x"], &[-1.]), Ok(0.));
+    }
+
+    #[test]
+    fn malformed_phenotypes_are_errors() {
+        let unknown = testing::phenotype(
+            &[("out", OUT), ("foo", TERMINAL)],
+            &[(0, 1, Binding::Strong(0))],
+        );
+        assert_eq!(
+            Program::compile(&unknown, &["x"]),
+            Err(InterpreterError::UnknownLabel(String::from("foo")))
+        );
+
+        let missing = testing::phenotype(
+            &[("out", OUT), ("+", REGULAR), ("x", TERMINAL)],
+            &[(0, 1, Binding::Strong(0)), (1, 2, Binding::Strong(0))],
+        );
+        assert_eq!(
+            Program::compile(&missing, &["x"]),
+            Err(InterpreterError::MissingArgument(String::from("+"), 1))
+        );
+
+        // the `+` weakly binds its own parent
+        let cycle = testing::phenotype(
+            &[
+                ("out", OUT),
+                ("neg", REGULAR),
+                ("+", REGULAR),
+                ("x", TERMINAL),
+            ],
+            &[
+                (0, 1, Binding::Strong(0)),
+                (1, 2, Binding::Strong(0)),
+                (2, 3, Binding::Strong(0)),
+                (2, 1, Binding::Weak(0)),
+            ],
+        );
+        assert_eq!(
+            Program::compile(&cycle, &["x"]),
+            Err(InterpreterError::Cycle(NodeIndex::new(1)))
+        );
+
+        let empty = testing::phenotype(&[("out", OUT)], &[]);
+        assert_eq!(
+            Program::compile(&empty, &["x"]),
+            Err(InterpreterError::NoOutput)
+        );
+        assert_eq!(
+            Program::compile_output(&missing, &["x"], 1),
+            Err(InterpreterError::NoOutput)
+        );
+    }
+}
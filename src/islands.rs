@@ -0,0 +1,349 @@
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use crate::blueprints::Blueprints;
+use crate::evolution::{Config, Evolution, Individual, Offspring, RunResult};
+use crate::lineage::Operator;
+use crate::termination::{Progress, StopCondition};
+use crate::Phenotype;
+
+/// Which islands send migrants to which
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Topology {
+    /// Island `i` sends to island `i + 1`, the last to the first
+    Ring,
+    /// Every island sends to every other
+    FullyConnected,
+    /// Every island sends to one other island, chosen at each migration
+    Random,
+}
+
+/// How emigrants are chosen from their island
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MigrantSelection {
+    Best,
+    Random,
+    Tournament(usize),
+}
+
+/// Which individuals of the receiving island are replaced by immigrants
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MigrantReplacement {
+    Worst,
+    Random,
+}
+
+/// Parameters of the island model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IslandConfig {
+    pub topology: Topology,
+    /// Generations between migrations
+    pub interval: usize,
+    /// Migrants sent along each connection
+    pub migrants: usize,
+    pub selection: MigrantSelection,
+    pub replacement: MigrantReplacement,
+    /// Seed for migration decisions, drawn from the thread generator if missing
+    pub seed: Option<u64>,
+}
+
+impl Default for IslandConfig {
+    fn default() -> IslandConfig {
+        IslandConfig {
+            topology: Topology::Ring,
+            interval: 10,
+            migrants: 2,
+            selection: MigrantSelection::Best,
+            replacement: MigrantReplacement::Worst,
+            seed: None,
+        }
+    }
+}
+
+/// Several evolution engines exchanging individuals
+///
+/// Each island has its own `Config`, so islands can differ in expression parameters and operator
+/// rates. Immigrants are scored again by their new island and given one of its ids, their lineage
+/// event is an `Operator::Migration` naming the source island and id. Individual ids are only
+/// unique within an island.
+pub struct Islands<'a, F>
+where
+    F: FnMut(&Phenotype) -> f64 + Clone,
+{
+    pub islands: Vec<Evolution<'a, F>>,
+    pub config: IslandConfig,
+    pub generation: usize,
+    rng: Pcg32,
+}
+
+impl<'a, F> Islands<'a, F>
+where
+    F: FnMut(&Phenotype) -> f64 + Clone,
+{
+    /// Creates one island per configuration, each with its own copy of the fitness function
+    pub fn new(
+        blueprints: &'a Blueprints,
+        configs: Vec<Config>,
+        config: IslandConfig,
+        fitness: F,
+    ) -> Islands<'a, F> {
+        let seed = config
+            .seed
+            .unwrap_or_else(|| rand::thread_rng().gen::<u64>());
+
+        let islands = configs
+            .into_iter()
+            .map(|island_config| Evolution::new(blueprints, island_config, fitness.clone()))
+            .collect();
+
+        Islands {
+            islands,
+            config,
+            generation: 0,
+            rng: Pcg32::seed_from_u64(seed),
+        }
+    }
+
+    /// Runs one generation on every island, migrating every `config.interval` generations
+    pub fn step(&mut self) {
+        for island in &mut self.islands {
+            island.step();
+        }
+
+        self.generation += 1;
+
+        if self.config.interval > 0 && self.generation % self.config.interval == 0 {
+            self.migrate();
+        }
+    }
+
+    /// Pairs of (from, to) islands for one migration
+    fn connections(&mut self) -> Vec<(usize, usize)> {
+        let n = self.islands.len();
+        if n < 2 {
+            return vec![];
+        }
+
+        match self.config.topology {
+            Topology::Ring => (0..n).map(|from| (from, (from + 1) % n)).collect(),
+            Topology::FullyConnected => (0..n)
+                .flat_map(|from| {
+                    (0..n)
+                        .filter(move |to| *to != from)
+                        .map(move |to| (from, to))
+                })
+                .collect(),
+            Topology::Random => (0..n)
+                .map(|from| (from, (from + self.rng.gen_range(1, n)) % n))
+                .collect(),
+        }
+    }
+
+    fn emigrants(&mut self, from: usize) -> Vec<Individual> {
+        let population = &self.islands[from].population;
+        let rng = &mut self.rng;
+        let n = self.config.migrants.min(population.len());
+
+        match self.config.selection {
+            MigrantSelection::Best => {
+                let mut sorted: Vec<&Individual> = population.iter().collect();
+                sorted.sort_by(|a, b| b.fitness.total_cmp(&a.fitness));
+                sorted.into_iter().take(n).cloned().collect()
+            }
+            MigrantSelection::Random => population.choose_multiple(rng, n).cloned().collect(),
+            MigrantSelection::Tournament(size) => (0..n)
+                .map(|_| {
+                    let mut winner = &population[rng.gen_range(0, population.len())];
+                    for _ in 1..size {
+                        let contender = &population[rng.gen_range(0, population.len())];
+                        if contender.fitness > winner.fitness {
+                            winner = contender;
+                        }
+                    }
+                    winner.clone()
+                })
+                .collect(),
+        }
+    }
+
+    /// Sends migrants along every connection, all chosen before any are placed
+    pub fn migrate(&mut self) {
+        let connections = self.connections();
+
+        let moves: Vec<(usize, usize, Vec<Individual>)> = connections
+            .into_iter()
+            .map(|(from, to)| (from, to, self.emigrants(from)))
+            .collect();
+
+        for (from, to, emigrants) in moves {
+            for emigrant in emigrants {
+                let immigrant = self.islands[to].birth(Offspring {
+                    chromosome: emigrant.chromosome,
+                    parents: vec![],
+                    operator: Operator::Migration {
+                        island: from,
+                        id: emigrant.id,
+                    },
+                });
+
+                let population = &self.islands[to].population;
+                let replaced = match self.config.replacement {
+                    MigrantReplacement::Worst => population
+                        .iter()
+                        .enumerate()
+                        .min_by(|(_, a), (_, b)| a.fitness.total_cmp(&b.fitness))
+                        .map(|(index, _)| index)
+                        .unwrap(),
+                    MigrantReplacement::Random => self.rng.gen_range(0, population.len()),
+                };
+
+                self.islands[to].population[replaced] = immigrant;
+            }
+        }
+    }
+
+    /// The fittest individual of all islands, `None` if they are all empty
+    pub fn best(&self) -> Option<&Individual> {
+        self.islands
+            .iter()
+            .filter_map(|island| island.best())
+            .max_by(|a, b| a.fitness.total_cmp(&b.fitness))
+    }
+
+    /// Progress over all islands, stagnating only when every island does
+    pub fn progress(&self, elapsed: Duration) -> Progress {
+        let progress: Vec<Progress> = self
+            .islands
+            .iter()
+            .map(|island| island.progress(elapsed))
+            .collect();
+
+        Progress {
+            generation: self.generation,
+            evaluations: progress.iter().map(|progress| progress.evaluations).sum(),
+            elapsed,
+            best_fitness: self.best().map_or(f64::NEG_INFINITY, |best| best.fitness),
+            generations_without_improvement: progress
+                .iter()
+                .map(|progress| progress.generations_without_improvement)
+                .min()
+                .unwrap_or(0),
+        }
+    }
+
+    /// Runs generations until the condition holds, checking it before every generation
+    pub fn run_until(&mut self, stop: &StopCondition) -> RunResult {
+        let start = Instant::now();
+
+        let reason = loop {
+            if let Some(reason) = stop.check(&self.progress(start.elapsed())) {
+                break reason;
+            }
+
+            self.step();
+        };
+
+        let progress = self.progress(start.elapsed());
+
+        RunResult {
+            best: self.best().cloned(),
+            reason,
+            generation: self.generation,
+            evaluations: progress.evaluations,
+            elapsed: progress.elapsed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lineage::{Event, IndividualId};
+    use crate::testing;
+    use std::collections::HashSet;
+
+    fn islands(
+        blueprints: &Blueprints,
+        config: IslandConfig,
+    ) -> Islands<'_, fn(&Phenotype) -> f64> {
+        let configs = (0..3)
+            .map(|seed| Config {
+                track_lineage: true,
+                ..testing::config(seed)
+            })
+            .collect();
+
+        Islands::new(blueprints, configs, config, testing::size)
+    }
+
+    #[test]
+    fn immigrants_get_ids_of_their_new_island() {
+        let blueprints = testing::arithmetic();
+        let mut islands = islands(
+            &blueprints,
+            IslandConfig {
+                interval: 1,
+                seed: Some(1),
+                ..Default::default()
+            },
+        );
+
+        for _ in 0..4 {
+            islands.step();
+        }
+
+        for (index, island) in islands.islands.iter().enumerate() {
+            let ids: HashSet<IndividualId> = island.population.iter().map(|i| i.id).collect();
+            assert_eq!(ids.len(), island.population.len());
+
+            let lineage = island.lineage.as_ref().unwrap();
+            let migrations: Vec<&Event> = lineage
+                .events
+                .values()
+                .filter(|event| matches!(event.operator, Operator::Migration { .. }))
+                .collect();
+            assert_eq!(migrations.len(), 4 * islands.config.migrants);
+
+            for event in migrations {
+                assert!(event.parents.is_empty());
+                if let Operator::Migration { island: from, id } = event.operator {
+                    // the ring sends from the previous island
+                    assert_eq!((from + 1) % islands.islands.len(), index);
+                    assert!(islands.islands[from]
+                        .lineage
+                        .as_ref()
+                        .unwrap()
+                        .get(id)
+                        .is_some());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn topologies_connect_distinct_islands() {
+        let blueprints = testing::arithmetic();
+
+        for topology in [Topology::Ring, Topology::FullyConnected, Topology::Random] {
+            let mut islands = islands(
+                &blueprints,
+                IslandConfig {
+                    topology,
+                    seed: Some(2),
+                    ..Default::default()
+                },
+            );
+
+            let connections = islands.connections();
+            assert!(connections.iter().all(|(from, to)| from != to));
+            let expected = match topology {
+                Topology::FullyConnected => 6,
+                _ => 3,
+            };
+            assert_eq!(connections.len(), expected);
+        }
+    }
+}
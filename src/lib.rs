@@ -1,22 +1,48 @@
 use petgraph::graph::{DiGraph, NodeIndex};
-use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 
 use crate::blueprints::Blueprints;
 use crate::chromosome::EgpChromosome;
 use crate::component::Component;
 
+pub mod analysis;
 pub mod blueprints;
+pub mod checkpoint;
 pub mod chromosome;
+pub mod codegen;
 pub mod component;
+pub mod diversity;
+pub mod evolution;
+pub mod incremental;
+pub mod interpreter;
+pub mod islands;
+pub mod lineage;
+pub mod map_elites;
+pub mod novelty;
+pub mod nsga2;
 pub mod operators;
+pub mod pretty;
+pub mod problems;
+pub mod render;
+pub mod simplify;
+pub mod speciation;
+pub mod statistics;
+pub mod termination;
+pub mod trace;
+pub mod typed;
 pub mod vectors;
 
+#[cfg(test)]
+mod testing;
+
 pub type Phenotype = DiGraph<Expressed, Binding>;
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum ComponentIndex {
-    Output,
+    Output(usize),
     Regular(usize, usize), // group, index
     Terminal(usize, usize),
 }
@@ -25,6 +51,8 @@ pub struct Expressed {
     pub label: String, // TODO PERF remove
     pub activity: usize,
     pub index: ComponentIndex,
+    /// Value of an ephemeral random constant
+    pub value: Option<f64>,
 }
 
 impl Expressed {
@@ -33,17 +61,26 @@ impl Expressed {
             label: component.label.clone(),
             activity: component.activity,
             index,
+            value: component.value,
+        }
+    }
+
+    /// The value of ephemeral random constants, the label otherwise
+    pub fn name(&self) -> String {
+        match self.value {
+            Some(value) => format!("{:?}", value),
+            None => self.label.clone(),
         }
     }
 }
 
 impl fmt::Debug for Expressed {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.label)
+        write!(f, "{}", self.name())
     }
 }
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Binding {
     Strong(usize), // index
     Weak(usize),
@@ -67,48 +104,82 @@ impl fmt::Debug for Binding {
     }
 }
 
-fn get_component<'a>(
+/// Input bias used when computing component profiles during expression
+pub(crate) const INPUT_BIAS: f32 = 0.5;
+
+/// How the outputs of a chromosome with several outputs share regular components
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutputSharing {
+    /// Every regular component is expressed at most once in the whole phenotype, earlier outputs
+    /// taking precedence, so outputs only share nodes through weak bindings
+    Exclusive,
+    /// Each output may choose any regular component, binding to the existing node when an
+    /// earlier output already expressed it
+    Shared,
+}
+
+/// Parameters of the genotype-phenotype map
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExpressionConfig {
+    /// Weight of the binding sites against the activity in component profiles
+    pub input_bias: f32,
+    pub sharing: OutputSharing,
+}
+
+impl Default for ExpressionConfig {
+    fn default() -> ExpressionConfig {
+        ExpressionConfig {
+            input_bias: INPUT_BIAS,
+            sharing: OutputSharing::Exclusive,
+        }
+    }
+}
+
+pub(crate) fn component_at<'a>(
     blueprints: &'a Blueprints,
     chromosome: &'a EgpChromosome,
-    phenotype: &Phenotype,
-    node: NodeIndex,
+    index: ComponentIndex,
 ) -> &'a Component {
-    match phenotype[node].index {
-        ComponentIndex::Output => &chromosome.output,
+    match index {
+        ComponentIndex::Output(index) => &chromosome.outputs[index],
         ComponentIndex::Regular(group, index) => &chromosome.regular[group][index],
         ComponentIndex::Terminal(group, index) => &blueprints.terminal[group][index],
     }
 }
 
-fn find_min_satisfying_distance<'a, F, I>(
-    blueprints: &Blueprints,
-    input_bias: f32,
-    binding: &Vec<f32>,
-    group: I,
-    criteria: F,
-) -> Option<(usize, f32)>
+/// Group of a component, outputs belong to the first group
+pub(crate) fn group_of(index: ComponentIndex) -> usize {
+    match index {
+        ComponentIndex::Output(_) => 0,
+        ComponentIndex::Regular(group, _) | ComponentIndex::Terminal(group, _) => group,
+    }
+}
+
+fn get_component<'a>(
+    blueprints: &'a Blueprints,
+    chromosome: &'a EgpChromosome,
+    phenotype: &Phenotype,
+    node: NodeIndex,
+) -> &'a Component {
+    component_at(blueprints, chromosome, phenotype[node].index)
+}
+
+/// Finds the minimal distance among those satisfying the criteria
+///
+/// The search starts from the distance of the first element regardless of the criteria, and later
+/// elements win ties. Both quirks are part of the genotype-phenotype map and must be preserved.
+pub(crate) fn min_satisfying<F, I>(distances: I, criteria: F) -> Option<(usize, f32)>
 where
     F: Fn(usize) -> bool,
-    I: IntoIterator<Item = &'a Component>,
+    I: IntoIterator<Item = f32>,
 {
-    let mut group = group.into_iter().peekable();
-
-    if group.peek().is_none() {
-        return None;
-    }
+    let mut distances = distances.into_iter().peekable();
 
-    let first_profile = group
-        .peek()
-        .unwrap()
-        .profile(blueprints.total_activities, input_bias);
+    let mut min_distance = *distances.peek()?;
     let mut min_distance_index = 0;
-    let mut min_distance = vectors::distance(&binding, &first_profile);
     let mut found = false;
 
-    for (index, potential_component) in group.enumerate() {
-        let profile = potential_component.profile(blueprints.total_activities, input_bias);
-        let distance = vectors::distance(&binding, &profile);
-
+    for (index, distance) in distances.enumerate() {
         if (distance <= min_distance) && criteria(index) {
             min_distance = distance;
             min_distance_index = index;
@@ -123,23 +194,95 @@ where
     }
 }
 
+fn find_min_satisfying_distance<'a, F, I>(
+    blueprints: &Blueprints,
+    input_bias: f32,
+    binding: &[f32],
+    group: I,
+    criteria: F,
+) -> Option<(usize, f32)>
+where
+    F: Fn(usize) -> bool,
+    I: IntoIterator<Item = &'a Component>,
+{
+    min_satisfying(
+        group.into_iter().map(|potential_component| {
+            let profile = potential_component.profile(blueprints.total_activities, input_bias);
+            vectors::distance(binding, &profile)
+        }),
+        criteria,
+    )
+}
+
+/// Decides between the nearest unexpressed regular and the nearest terminal of a group
+pub(crate) fn choose_component(
+    group: usize,
+    regular: Option<(usize, f32)>,
+    terminal: (usize, f32),
+) -> ComponentIndex {
+    let (terminal_index, terminal_distance) = terminal;
+
+    match regular {
+        Some((index, distance)) if distance <= terminal_distance => {
+            ComponentIndex::Regular(group, index)
+        }
+        _ => ComponentIndex::Terminal(group, terminal_index),
+    }
+}
+
+/// Finds the component satisfying a binding site by computing distances to the whole group
+pub(crate) fn nearest_component(
+    blueprints: &Blueprints,
+    chromosome: &EgpChromosome,
+    binding: &[f32],
+    group: usize,
+    expressed_regulars: &HashSet<ComponentIndex>,
+    input_bias: f32,
+) -> ComponentIndex {
+    let regular_find = find_min_satisfying_distance(
+        blueprints,
+        input_bias,
+        binding,
+        chromosome.regular[group].iter(),
+        |index| !expressed_regulars.contains(&ComponentIndex::Regular(group, index)),
+    );
+
+    let terminal_find = find_min_satisfying_distance(
+        blueprints,
+        input_bias,
+        binding,
+        blueprints.terminal[group].iter(),
+        |_| true,
+    )
+    .unwrap(); // we can always find a terminal, they can be expressed multiple times
+
+    choose_component(group, regular_find, terminal_find)
+}
+
 fn satisfy_weak(
     blueprints: &Blueprints,
     chromosome: &EgpChromosome,
     phenotype: &mut Phenotype,
     node: NodeIndex,
-    offering: &Vec<NodeIndex>,
+    offering: &[NodeIndex],
     input_bias: f32,
 ) {
-    let component = get_component(blueprints, chromosome, &phenotype, node);
+    let component = get_component(blueprints, chromosome, phenotype, node);
 
-    for (binding_index, (binding, _group)) in component
+    for (binding_index, (binding, group)) in component
         .weak_binding_sites
         .iter()
         .zip(component.weak_binding_sites_groups.iter())
         .enumerate()
     {
-        let offering_components = offering
+        // filtered beforehand, `min_satisfying` starts from the first distance regardless
+        let in_group: Vec<NodeIndex> = offering
+            .iter()
+            .cloned()
+            .filter(|node| group_of(phenotype[*node].index) == *group)
+            .collect();
+
+        let offering_components = in_group
             .iter()
             .map(|node| get_component(blueprints, chromosome, phenotype, *node));
 
@@ -152,7 +295,7 @@ fn satisfy_weak(
         );
 
         if let Some((index, _distance)) = found {
-            phenotype.add_edge(node, offering[index], Binding::Weak(binding_index));
+            phenotype.add_edge(node, in_group[index], Binding::Weak(binding_index));
         }
     }
 
@@ -165,148 +308,438 @@ fn satisfy_weak(
     // {}
 }
 
+/// State of a breadth-first expression, before weak bindings are satisfied
+pub(crate) struct Walk {
+    pub phenotype: Phenotype,
+    /// Nodes with weak binding sites, sorted to ensure identical outputs
+    pub weak_looking: Vec<NodeIndex>,
+    /// Nodes that weak binding sites may connect to, sorted to ensure identical outputs
+    pub weak_offering: Vec<NodeIndex>,
+}
+
+impl Walk {
+    /// Prepares the weak bindings of a phenotype holding only strong bindings
+    pub fn new(blueprints: &Blueprints, chromosome: &EgpChromosome, phenotype: Phenotype) -> Walk {
+        let mut weak_looking = vec![];
+        let mut weak_offering = vec![];
+
+        for node in phenotype.node_indices() {
+            let component = get_component(blueprints, chromosome, &phenotype, node);
+
+            if blueprints.weak_map.contains_key(&component.label) {
+                weak_looking.push(node);
+            }
+
+            if blueprints
+                .weak_map
+                .values()
+                .any(|val| val == &component.label)
+            {
+                weak_offering.push(node);
+            }
+        }
+
+        Walk {
+            phenotype,
+            weak_looking,
+            weak_offering,
+        }
+    }
+
+    /// Satisfies the weak bindings, completing the phenotype
+    pub fn finish(
+        mut self,
+        blueprints: &Blueprints,
+        chromosome: &EgpChromosome,
+        input_bias: f32,
+    ) -> Phenotype {
+        for node in self.weak_looking {
+            satisfy_weak(
+                blueprints,
+                chromosome,
+                &mut self.phenotype,
+                node,
+                &self.weak_offering,
+                input_bias,
+            );
+        }
+
+        self.phenotype
+    }
+}
+
+struct WalkState {
+    phenotype: Phenotype,
+    queue: VecDeque<NodeIndex>,
+    expressed_regulars: HashSet<ComponentIndex>,
+    /// Nodes of the regulars expressed by earlier outputs, reused under `OutputSharing::Shared`
+    shared_regulars: HashMap<ComponentIndex, NodeIndex>,
+}
+
 // TODO the proper way would probably be to allow unlimited terminal expression
 // only after no satifying limited expression can be found, and to include terminals
 // in distributions
 
-/// Satisfies the bindings for a new node without children
-fn satisfy(
+/// Satisfies the bindings of a node from `first_binding_site` on
+fn satisfy<C>(
     blueprints: &Blueprints,
     chromosome: &EgpChromosome,
-    phenotype: &mut Phenotype,
+    state: &mut WalkState,
     node: NodeIndex,
-    queue: &mut VecDeque<NodeIndex>,
-    weak_looking: &mut HashSet<NodeIndex>, // added for later
-    weak_offering: &mut HashSet<NodeIndex>,
-    expressed_regulars: &mut HashSet<usize>,
-    input_bias: f32,
-) {
-    let component = get_component(blueprints, chromosome, phenotype, node);
+    first_binding_site: usize,
+    choose: &mut C,
+) where
+    C: FnMut(ComponentIndex, usize, usize, &HashSet<ComponentIndex>) -> ComponentIndex,
+{
+    let owner = state.phenotype[node].index;
+    let component = component_at(blueprints, chromosome, owner);
 
-    // println!("activity={}", component.activity);
+    for (binding_index, group) in component
+        .binding_sites_groups
+        .iter()
+        .enumerate()
+        .skip(first_binding_site)
+    {
+        let chosen = choose(owner, binding_index, *group, &state.expressed_regulars);
 
-    if blueprints.weak_map.contains_key(&component.label) {
-        weak_looking.insert(node);
-    }
+        if let Some(existing) = state.shared_regulars.get(&chosen) {
+            state
+                .phenotype
+                .add_edge(node, *existing, Binding::Strong(binding_index));
+            continue;
+        }
 
-    if blueprints
-        .weak_map
-        .values()
-        .any(|val| val == &component.label)
-    {
-        weak_offering.insert(node);
+        if let ComponentIndex::Regular(_, _) = chosen {
+            state.expressed_regulars.insert(chosen);
+        }
+
+        let child = state.phenotype.add_node(Expressed::from_component(
+            component_at(blueprints, chromosome, chosen),
+            chosen,
+        ));
+
+        state
+            .phenotype
+            .add_edge(node, child, Binding::Strong(binding_index));
+
+        state.queue.push_back(child);
     }
+}
 
-    for (binding_index, (binding, group)) in component
-        .binding_sites
-        .iter()
-        .zip(component.binding_sites_groups.iter())
-        .enumerate()
-    {
-        let regular_find = find_min_satisfying_distance(
+/// Expresses the strong bindings of a chromosome breadth-first from each output node in turn
+///
+/// `choose` receives the owner of a binding site, the binding site index, its group and the
+/// regulars expressed so far, and returns the component to express there. Each choice adds one
+/// strong edge, so the edge at index `i` is the `i`-th choice, and nodes are added in order.
+pub(crate) fn expression_walk<C>(
+    blueprints: &Blueprints,
+    chromosome: &EgpChromosome,
+    sharing: OutputSharing,
+    choose: C,
+) -> Walk
+where
+    C: FnMut(ComponentIndex, usize, usize, &HashSet<ComponentIndex>) -> ComponentIndex,
+{
+    let size_est = chromosome.outputs.len() + chromosome.regular.len() + blueprints.terminal.len();
+    let phenotype = Phenotype::with_capacity(size_est, size_est);
+
+    resume_walk(blueprints, chromosome, sharing, phenotype, None, choose)
+}
+
+/// Where an interrupted expression walk continues
+pub(crate) struct Resume {
+    /// Node whose binding sites were being satisfied
+    pub node: NodeIndex,
+    /// First binding site of `node` left to satisfy
+    pub binding_site: usize,
+    /// Regulars expressed before the interruption, as `choose` received them
+    pub expressed_regulars: HashSet<ComponentIndex>,
+}
+
+/// Continues an expression walk from `resume`, or starts one if there is none
+///
+/// `phenotype` holds the nodes and strong edges `expression_walk` added before the interruption,
+/// and nothing else.
+pub(crate) fn resume_walk<C>(
+    blueprints: &Blueprints,
+    chromosome: &EgpChromosome,
+    sharing: OutputSharing,
+    phenotype: Phenotype,
+    resume: Option<Resume>,
+    mut choose: C,
+) -> Walk
+where
+    C: FnMut(ComponentIndex, usize, usize, &HashSet<ComponentIndex>) -> ComponentIndex,
+{
+    let mut state = WalkState {
+        phenotype,
+        queue: VecDeque::new(),
+        expressed_regulars: HashSet::new(),
+        shared_regulars: HashMap::new(),
+    };
+    let mut first_output = 0;
+
+    if let Some(resume) = resume {
+        // the interrupted output is the last one expressed before the node
+        let (output, output_node) = (0..=resume.node.index())
+            .rev()
+            .find_map(|node| match state.phenotype[NodeIndex::new(node)].index {
+                ComponentIndex::Output(output) => Some((output, node)),
+                _ => None,
+            })
+            .unwrap(); // every node was expressed from an output
+
+        if sharing == OutputSharing::Shared {
+            share_regulars(&mut state, 0..output_node);
+        }
+        state.expressed_regulars = resume.expressed_regulars;
+
+        // breadth-first, the nodes expressed after this one haven't been satisfied yet
+        state
+            .queue
+            .extend((resume.node.index() + 1..state.phenotype.node_count()).map(NodeIndex::new));
+        satisfy(
             blueprints,
-            input_bias,
-            binding,
-            chromosome.regular[*group].iter(),
-            |index| !expressed_regulars.contains(&index),
+            chromosome,
+            &mut state,
+            resume.node,
+            resume.binding_site,
+            &mut choose,
         );
+        finish_output(
+            blueprints,
+            chromosome,
+            &mut state,
+            sharing,
+            output_node,
+            &mut choose,
+        );
+
+        first_output = output + 1;
+    }
+
+    for (index, output) in chromosome.outputs.iter().enumerate().skip(first_output) {
+        let first_node = state.phenotype.node_count();
 
-        let (terminal_index, terminal_distance) = find_min_satisfying_distance(
+        let output_node = state.phenotype.add_node(Expressed::from_component(
+            output,
+            ComponentIndex::Output(index),
+        ));
+        state.queue.push_back(output_node);
+
+        finish_output(
             blueprints,
-            input_bias,
-            binding,
-            blueprints.terminal[*group].iter(),
-            |_| true,
-        )
-        .unwrap(); // we can always find a terminal, they can be expressed multiple times
-
-        let child = if let Some((index, distance)) = regular_find {
-            if distance <= terminal_distance {
-                // add regular
-                let child = phenotype.add_node(Expressed::from_component(
-                    &chromosome.regular[*group][index],
-                    ComponentIndex::Regular(*group, index),
-                ));
-
-                expressed_regulars.insert(index);
-
-                child
-            } else {
-                phenotype.add_node(Expressed::from_component(
-                    &blueprints.terminal[*group][terminal_index],
-                    ComponentIndex::Terminal(*group, terminal_index),
-                ))
-            }
-        } else {
-            phenotype.add_node(Expressed::from_component(
-                &blueprints.terminal[*group][terminal_index],
-                ComponentIndex::Terminal(*group, terminal_index),
-            ))
-        };
-
-        phenotype.add_edge(
-            node,
-            child, // HERE
-            Binding::Strong(binding_index),
+            chromosome,
+            &mut state,
+            sharing,
+            first_node,
+            &mut choose,
         );
+    }
+
+    Walk::new(blueprints, chromosome, state.phenotype)
+}
+
+/// Satisfies the queued nodes of the output expressed from `first_node` on
+fn finish_output<C>(
+    blueprints: &Blueprints,
+    chromosome: &EgpChromosome,
+    state: &mut WalkState,
+    sharing: OutputSharing,
+    first_node: usize,
+    choose: &mut C,
+) where
+    C: FnMut(ComponentIndex, usize, usize, &HashSet<ComponentIndex>) -> ComponentIndex,
+{
+    while let Some(node) = state.queue.pop_front() {
+        satisfy(blueprints, chromosome, state, node, 0, choose);
+    }
+
+    if sharing == OutputSharing::Shared {
+        share_regulars(state, first_node..state.phenotype.node_count());
+        state.expressed_regulars.clear();
+    }
+}
 
-        queue.push_back(child);
+/// Makes the regulars expressed by `nodes` available to the following outputs
+fn share_regulars(state: &mut WalkState, nodes: std::ops::Range<usize>) {
+    for node in nodes {
+        let node = NodeIndex::new(node);
+        let index = state.phenotype[node].index;
+        if let ComponentIndex::Regular(_, _) = index {
+            state.shared_regulars.insert(index, node);
+        }
     }
 }
 
 /// Given blueprints and a chromosome, constructs a phenotype
 pub fn express(blueprints: &Blueprints, chromosome: &EgpChromosome) -> Phenotype {
-    let input_bias = 0.5;
+    express_with(blueprints, chromosome, &ExpressionConfig::default())
+}
 
-    let size_est = 1 + chromosome.regular.len() + blueprints.terminal.len();
-    let mut phenotype = Phenotype::with_capacity(size_est, size_est);
+/// Constructs a phenotype like `express`, with the given expression parameters
+pub fn express_with(
+    blueprints: &Blueprints,
+    chromosome: &EgpChromosome,
+    config: &ExpressionConfig,
+) -> Phenotype {
+    let walk = expression_walk(
+        blueprints,
+        chromosome,
+        config.sharing,
+        |owner, binding_index, group, expressed_regulars| {
+            let binding = &component_at(blueprints, chromosome, owner).binding_sites[binding_index];
+            nearest_component(
+                blueprints,
+                chromosome,
+                binding,
+                group,
+                expressed_regulars,
+                config.input_bias,
+            )
+        },
+    );
+
+    walk.finish(blueprints, chromosome, config.input_bias)
+}
 
-    let mut expression_queue: VecDeque<NodeIndex> = VecDeque::new();
-    // weak bindings go last
-    let mut weak_looking: HashSet<NodeIndex> = HashSet::new();
-    let mut weak_offering: HashSet<NodeIndex> = HashSet::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pretty;
+    use petgraph::Direction;
+
+    /// Largest number of strong parents of a node
+    fn max_strong_parents(phenotype: &Phenotype) -> usize {
+        phenotype
+            .node_indices()
+            .map(|node| {
+                phenotype
+                    .edges_directed(node, Direction::Incoming)
+                    .filter(|edge| matches!(edge.weight(), Binding::Strong(_)))
+                    .count()
+            })
+            .max()
+            .unwrap_or(0)
+    }
 
-    let output_node = phenotype.add_node(Expressed::from_component(
-        &chromosome.output,
-        ComponentIndex::Output,
-    ));
-    expression_queue.push_back(output_node);
+    /// Whether every regular component is expressed at most once
+    fn regulars_unique(phenotype: &Phenotype) -> bool {
+        let mut seen = HashSet::new();
 
-    let mut expressed_regulars: HashSet<usize> = HashSet::new();
+        phenotype
+            .node_weights()
+            .filter(|expressed| matches!(expressed.index, ComponentIndex::Regular(_, _)))
+            .all(|expressed| seen.insert(expressed.index))
+    }
 
-    'expression: loop {
-        match expression_queue.pop_front() {
-            None => break 'expression,
-            Some(node) => satisfy(
-                blueprints,
-                chromosome,
-                &mut phenotype,
-                node,
-                &mut expression_queue,
-                &mut weak_looking,
-                &mut weak_offering,
-                &mut expressed_regulars,
-                input_bias,
-            ),
-        }
+    /// Labels and edges of the phenotypes the baseline expressed for `baseline_chromosomes`
+    ///
+    /// Edges are `parent>child:site` for strong bindings and `parent>childwsite` for weak ones.
+    const BASELINE: [(&str, &str); 6] = [
+        (
+            "out use * use use 1 * + + 1 * 1 x 1 use 1",
+            "0>1:0 1>2:0 2>3:0 2>4:1 3>5:0 4>6:0 6>7:0 6>8:1 7>9:0 7>10:1 8>11:0 8>12:1 10>13:0 \
+             10>14:1 14>15:0 1>7w0 3>7w0 4>7w0 14>7w0",
+        ),
+        (
+            "out neg use * neg use neg x use x",
+            "0>1:0 1>2:0 2>3:0 3>4:0 3>5:1 4>6:0 5>7:0 6>8:0 8>9:0",
+        ),
+        (
+            "out use use neg neg + + 1 neg x x",
+            "0>1:0 1>2:0 2>3:0 3>4:0 4>5:0 5>6:0 5>7:1 6>8:0 6>9:1 8>10:0 1>6w0 2>6w0",
+        ),
+        (
+            "out * use use + + 1 x 1 1",
+            "0>1:0 1>2:0 1>3:1 2>4:0 3>5:0 4>6:0 4>7:1 5>8:0 5>9:1 2>5w0 3>4w0",
+        ),
+        (
+            "out * neg * + use neg use + x use x 1 x 1",
+            "0>1:0 1>2:0 1>3:1 2>4:0 3>5:0 3>6:1 4>7:0 4>8:1 5>9:0 6>10:0 7>11:0 8>12:0 8>13:1 \
+             10>14:0 5>4w0 7>4w0 10>4w0",
+        ),
+        (
+            "out neg * + neg neg 1 neg * + 1 + x 1 x x",
+            "0>1:0 1>2:0 2>3:0 2>4:1 3>5:0 3>6:1 4>7:0 5>8:0 7>9:0 8>10:0 8>11:1 9>12:0 9>13:1 \
+             11>14:0 11>15:1",
+        ),
+    ];
+
+    fn summary(phenotype: &Phenotype) -> (String, String) {
+        let labels: Vec<String> = phenotype.node_weights().map(Expressed::name).collect();
+        let edges: Vec<String> = phenotype
+            .raw_edges()
+            .iter()
+            .map(|edge| {
+                let (source, target) = (edge.source().index(), edge.target().index());
+                match edge.weight {
+                    Binding::Strong(site) => format!("{}>{}:{}", source, target, site),
+                    Binding::Weak(site) => format!("{}>{}w{}", source, target, site),
+                }
+            })
+            .collect();
+
+        (labels.join(" "), edges.join(" "))
     }
 
-    // ensures identical outputs
-    let mut offering_vec: Vec<NodeIndex> = weak_offering.into_iter().collect();
-    offering_vec.sort();
-    let mut weak_looking: Vec<NodeIndex> = weak_looking.into_iter().collect();
-    weak_looking.sort();
+    /// Six ancestors of size 12 of `testing::arithmetic`
+    fn baseline_chromosomes() -> Vec<EgpChromosome> {
+        let blueprints = testing::arithmetic();
+        let mut rng = testing::rng(2020);
 
-    for node in weak_looking {
-        satisfy_weak(
-            blueprints,
-            chromosome,
-            &mut phenotype,
-            node,
-            &offering_vec,
-            input_bias,
-        );
+        (0..6)
+            .map(|_| EgpChromosome::ancestor_from_blueprints_with_rng(&mut rng, &blueprints, 12))
+            .collect()
+    }
+
+    #[test]
+    fn single_group_expression_matches_the_baseline() {
+        let blueprints = testing::arithmetic();
+
+        for (chromosome, (labels, edges)) in baseline_chromosomes().iter().zip(BASELINE) {
+            let (expressed_labels, expressed_edges) = summary(&express(&blueprints, chromosome));
+            assert_eq!(expressed_labels, labels);
+            assert_eq!(expressed_edges, edges);
+        }
+    }
+
+    #[test]
+    fn every_output_is_expressed() {
+        let blueprints = testing::two_outputs();
+
+        for chromosome in testing::ancestors(&blueprints, 13, 20) {
+            assert_eq!(chromosome.outputs.len(), 2);
+
+            for config in [ExpressionConfig::default(), testing::shared_outputs()] {
+                let phenotype = express_with(&blueprints, &chromosome, &config);
+                let outputs: Vec<ComponentIndex> = pretty::output_nodes(&phenotype)
+                    .into_iter()
+                    .map(|node| phenotype[node].index)
+                    .collect();
+
+                assert_eq!(
+                    outputs,
+                    vec![ComponentIndex::Output(0), ComponentIndex::Output(1)]
+                );
+                assert!(regulars_unique(&phenotype));
+            }
+        }
     }
 
-    phenotype
+    #[test]
+    fn only_shared_outputs_bind_the_same_node() {
+        let blueprints = testing::two_outputs();
+        let mut shared = false;
+
+        for chromosome in testing::ancestors(&blueprints, 14, 20) {
+            let exclusive = express(&blueprints, &chromosome);
+            assert_eq!(max_strong_parents(&exclusive), 1);
+
+            let phenotype = express_with(&blueprints, &chromosome, &testing::shared_outputs());
+            shared |= max_strong_parents(&phenotype) > 1;
+        }
+
+        assert!(shared);
+    }
 }
@@ -0,0 +1,217 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+
+use crate::operators::{Mutation, Recombination};
+
+/// Identifies an individual within a run
+pub type IndividualId = u64;
+
+/// How an individual was produced
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Operator {
+    Ancestor,
+    Mutation(Mutation),
+    Recombination(Recombination),
+    /// Arrived from another island of `Islands`, where it had the given id
+    Migration {
+        island: usize,
+        id: IndividualId,
+    },
+}
+
+/// Birth of an individual
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub id: IndividualId,
+    pub generation: usize,
+    /// The parent first, then the donor for recombinations
+    pub parents: Vec<IndividualId>,
+    pub operator: Operator,
+}
+
+/// Genealogy of all individuals produced during a run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Lineage {
+    pub events: BTreeMap<IndividualId, Event>,
+}
+
+impl Lineage {
+    pub fn new() -> Lineage {
+        Lineage::default()
+    }
+
+    pub fn record(&mut self, event: Event) {
+        self.events.insert(event.id, event);
+    }
+
+    pub fn get(&self, id: IndividualId) -> Option<&Event> {
+        self.events.get(&id)
+    }
+
+    /// Events of an individual and all of its ancestors, ordered by id
+    pub fn ancestry(&self, id: IndividualId) -> Vec<&Event> {
+        let mut visited = HashSet::new();
+        let mut stack = vec![id];
+
+        while let Some(id) = stack.pop() {
+            if !visited.insert(id) {
+                continue;
+            }
+
+            if let Some(event) = self.events.get(&id) {
+                stack.extend(event.parents.iter().cloned());
+            }
+        }
+
+        let mut result: Vec<&Event> = visited
+            .into_iter()
+            .filter_map(|id| self.events.get(&id))
+            .collect();
+        result.sort_by_key(|event| event.id);
+
+        result
+    }
+
+    /// Keeps only the events in the ancestry of the given individuals
+    pub fn retain_ancestry(&mut self, ids: &[IndividualId]) {
+        let keep: HashSet<IndividualId> = ids
+            .iter()
+            .flat_map(|id| self.ancestry(*id))
+            .map(|event| event.id)
+            .collect();
+
+        self.events.retain(|id, _| keep.contains(id));
+    }
+
+    /// Renders the genealogy of an individual in the Graphviz DOT format, parents pointing to
+    /// their offspring
+    pub fn dot(&self, id: IndividualId) -> String {
+        let mut result = String::new();
+
+        writeln!(result, "digraph lineage {{").unwrap();
+        writeln!(result, "    node [shape=box, fontname=\"Helvetica\"];").unwrap();
+
+        for event in self.ancestry(id) {
+            let operator = match event.operator {
+                Operator::Ancestor => String::from("ancestor"),
+                Operator::Mutation(mutation) => format!("{:?}", mutation),
+                Operator::Recombination(recombination) => format!("{:?}", recombination),
+                Operator::Migration { island, id } => {
+                    format!("migrated from island {}, #{}", island, id)
+                }
+            };
+
+            writeln!(
+                result,
+                "    {} [label=\"#{} (generation {})\\n{}\"];",
+                event.id, event.id, event.generation, operator
+            )
+            .unwrap();
+
+            for (index, parent) in event.parents.iter().enumerate() {
+                let style = if index == 0 { "solid" } else { "dashed" };
+                writeln!(result, "    {} -> {} [style={}];", parent, event.id, style).unwrap();
+            }
+        }
+
+        writeln!(result, "}}").unwrap();
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evolution::{Config, Evolution};
+    use crate::testing;
+
+    fn event(id: IndividualId, parents: &[IndividualId]) -> Event {
+        Event {
+            id,
+            generation: if parents.is_empty() { 0 } else { 1 },
+            parents: parents.to_vec(),
+            operator: if parents.is_empty() {
+                Operator::Ancestor
+            } else {
+                Operator::Mutation(Mutation::Unchanged)
+            },
+        }
+    }
+
+    fn family() -> Lineage {
+        let mut lineage = Lineage::new();
+        for event in [
+            event(0, &[]),
+            event(1, &[]),
+            event(2, &[]),
+            event(3, &[0, 1]),
+            event(4, &[3]),
+            event(5, &[2]),
+        ] {
+            lineage.record(event);
+        }
+        lineage
+    }
+
+    fn ids(events: Vec<&Event>) -> Vec<IndividualId> {
+        events.iter().map(|event| event.id).collect()
+    }
+
+    #[test]
+    fn ancestry_follows_all_parents() {
+        let lineage = family();
+
+        assert_eq!(ids(lineage.ancestry(4)), vec![0, 1, 3, 4]);
+        assert_eq!(ids(lineage.ancestry(5)), vec![2, 5]);
+        assert_eq!(ids(lineage.ancestry(9)), Vec::<IndividualId>::new());
+
+        let dot = lineage.dot(4);
+        assert!(dot.contains("    0 -> 3 [style=solid];"));
+        assert!(dot.contains("    1 -> 3 [style=dashed];"));
+        assert!(!dot.contains("    2 "));
+    }
+
+    #[test]
+    fn retain_ancestry_drops_other_branches() {
+        let mut lineage = family();
+        lineage.retain_ancestry(&[4]);
+
+        assert_eq!(
+            lineage.events.keys().cloned().collect::<Vec<_>>(),
+            vec![0, 1, 3, 4]
+        );
+    }
+
+    #[test]
+    fn evolution_records_every_birth() {
+        let blueprints = testing::arithmetic();
+        let config = Config {
+            track_lineage: true,
+            ..testing::config(1)
+        };
+        let mut evolution = Evolution::new(&blueprints, config, testing::size);
+        evolution.run();
+
+        let lineage = evolution.lineage.as_ref().unwrap();
+        let mut ids = HashSet::new();
+
+        for individual in &evolution.population {
+            assert!(ids.insert(individual.id));
+
+            for event in lineage.ancestry(individual.id) {
+                assert!(event.parents.iter().all(|parent| *parent < event.id));
+                match event.operator {
+                    Operator::Ancestor => {
+                        assert!(event.parents.is_empty());
+                        assert_eq!(event.generation, 0);
+                    }
+                    Operator::Mutation(_) => assert_eq!(event.parents.len(), 1),
+                    Operator::Recombination(_) => assert_eq!(event.parents.len(), 2),
+                    Operator::Migration { .. } => unreachable!(),
+                }
+            }
+        }
+    }
+}
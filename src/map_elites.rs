@@ -0,0 +1,469 @@
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+
+use crate::blueprints::Blueprints;
+use crate::chromosome::EgpChromosome;
+use crate::evolution::Config;
+use crate::operators::vary_with_rng;
+use crate::{express_with, Binding, Phenotype};
+
+/// One axis of the descriptor grid, split into `bins` equal intervals of `[min, max)`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Dimension {
+    pub name: String,
+    pub min: f64,
+    pub max: f64,
+    pub bins: usize,
+}
+
+impl Dimension {
+    pub fn new(name: &str, min: f64, max: f64, bins: usize) -> Dimension {
+        Dimension {
+            name: name.to_string(),
+            min,
+            max,
+            bins,
+        }
+    }
+
+    /// Bin of a value, values outside the range go to the first or last bin
+    pub fn bin(&self, value: f64) -> usize {
+        let position = (value - self.min) / (self.max - self.min) * self.bins as f64;
+
+        if position.is_nan() || position < 0. {
+            0
+        } else {
+            (position as usize).min(self.bins - 1)
+        }
+    }
+
+    /// Lower bound of a bin
+    pub fn lower(&self, bin: usize) -> f64 {
+        self.min + (self.max - self.min) * bin as f64 / self.bins as f64
+    }
+}
+
+/// Expressed nodes, a descriptor for `Dimension`s of phenotype size
+pub fn phenotype_size(phenotype: &Phenotype) -> f64 {
+    phenotype.node_count() as f64
+}
+
+/// Satisfied weak bindings
+pub fn weak_bindings(phenotype: &Phenotype) -> f64 {
+    phenotype
+        .edge_weights()
+        .filter(|binding| matches!(binding, Binding::Weak(_)))
+        .count() as f64
+}
+
+/// The best chromosome found for a cell
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Elite {
+    pub chromosome: EgpChromosome,
+    pub fitness: f64,
+    pub descriptor: Vec<f64>,
+}
+
+/// Cells of a descriptor grid, each holding at most one elite
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Archive {
+    pub dimensions: Vec<Dimension>,
+    /// Row-major, the last dimension varies fastest
+    pub cells: Vec<Option<Elite>>,
+}
+
+impl Archive {
+    /// Panics if a dimension has no bins or an empty range
+    pub fn new(dimensions: Vec<Dimension>) -> Archive {
+        for dimension in &dimensions {
+            assert!(
+                dimension.bins > 0,
+                "dimension {} has no bins",
+                dimension.name
+            );
+            assert!(
+                dimension.min < dimension.max,
+                "dimension {} has an empty range",
+                dimension.name
+            );
+        }
+
+        let size = dimensions.iter().map(|dimension| dimension.bins).product();
+
+        Archive {
+            dimensions,
+            cells: vec![None; size],
+        }
+    }
+
+    /// Bins of a descriptor in every dimension
+    pub fn coordinates(&self, descriptor: &[f64]) -> Vec<usize> {
+        self.dimensions
+            .iter()
+            .zip(descriptor)
+            .map(|(dimension, value)| dimension.bin(*value))
+            .collect()
+    }
+
+    pub fn index(&self, coordinates: &[usize]) -> usize {
+        self.dimensions
+            .iter()
+            .zip(coordinates)
+            .fold(0, |index, (dimension, bin)| index * dimension.bins + bin)
+    }
+
+    pub fn get(&self, coordinates: &[usize]) -> Option<&Elite> {
+        self.cells[self.index(coordinates)].as_ref()
+    }
+
+    /// Places the elite in its cell if the cell is empty or holds a less fit elite
+    ///
+    /// Elites with a NaN fitness are never placed, they compare neither better nor worse.
+    ///
+    /// Panics if the descriptor does not have a value for each dimension.
+    pub fn insert(&mut self, elite: Elite) -> bool {
+        assert_eq!(
+            elite.descriptor.len(),
+            self.dimensions.len(),
+            "descriptor length differs from the number of dimensions"
+        );
+        if elite.fitness.is_nan() {
+            return false;
+        }
+
+        let index = self.index(&self.coordinates(&elite.descriptor));
+
+        match &self.cells[index] {
+            Some(occupant) if occupant.fitness >= elite.fitness => false,
+            _ => {
+                self.cells[index] = Some(elite);
+                true
+            }
+        }
+    }
+
+    pub fn elites(&self) -> impl Iterator<Item = &Elite> {
+        self.cells.iter().flatten()
+    }
+
+    /// Fraction of cells holding an elite
+    pub fn coverage(&self) -> f64 {
+        if self.cells.is_empty() {
+            0.
+        } else {
+            self.elites().count() as f64 / self.cells.len() as f64
+        }
+    }
+
+    /// Sum of the elites' fitness
+    pub fn qd_score(&self) -> f64 {
+        self.elites().map(|elite| elite.fitness).sum()
+    }
+
+    pub fn best(&self) -> Option<&Elite> {
+        self.elites().max_by(|a, b| a.fitness.total_cmp(&b.fitness))
+    }
+
+    /// Best fitness per cell of two dimensions, maximized over the others
+    ///
+    /// Rows are bins of `y`, columns bins of `x`.
+    pub fn heatmap(&self, x: usize, y: usize) -> Vec<Vec<Option<f64>>> {
+        let mut heatmap = vec![vec![None; self.dimensions[x].bins]; self.dimensions[y].bins];
+
+        for elite in self.elites() {
+            let coordinates = self.coordinates(&elite.descriptor);
+            let cell = &mut heatmap[coordinates[y]][coordinates[x]];
+
+            if cell.map_or(true, |fitness| elite.fitness > fitness) {
+                *cell = Some(elite.fitness);
+            }
+        }
+
+        heatmap
+    }
+
+    /// Writes one line per elite: its bins, descriptor and fitness
+    pub fn write_csv<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut header: Vec<String> = self
+            .dimensions
+            .iter()
+            .map(|dimension| format!("{}_bin", dimension.name))
+            .collect();
+        header.extend(
+            self.dimensions
+                .iter()
+                .map(|dimension| dimension.name.clone()),
+        );
+        header.push(String::from("fitness"));
+        writeln!(writer, "{}", header.join(","))?;
+
+        for elite in self.elites() {
+            let mut row: Vec<String> = self
+                .coordinates(&elite.descriptor)
+                .iter()
+                .map(|bin| bin.to_string())
+                .collect();
+            row.extend(elite.descriptor.iter().map(|value| value.to_string()));
+            row.push(elite.fitness.to_string());
+            writeln!(writer, "{}", row.join(","))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// MAP-Elites: variation of uniformly chosen elites, each offspring competing only within its cell
+///
+/// Of the `Config`, `population_size` is the number of ancestors and of offspring per iteration,
+/// `generations` the number of iterations; selection and elitism parameters are ignored.
+pub struct MapElites<'a, F, D>
+where
+    F: FnMut(&Phenotype) -> f64,
+    D: FnMut(&Phenotype) -> Vec<f64>,
+{
+    pub blueprints: &'a Blueprints,
+    pub config: Config,
+    pub archive: Archive,
+    pub iteration: usize,
+    pub rng: Pcg32,
+    /// Calls of the fitness function so far
+    pub evaluations: usize,
+    fitness: F,
+    descriptor: D,
+}
+
+impl<'a, F, D> MapElites<'a, F, D>
+where
+    F: FnMut(&Phenotype) -> f64,
+    D: FnMut(&Phenotype) -> Vec<f64>,
+{
+    /// Seeds the archive with ancestors
+    pub fn new(
+        blueprints: &'a Blueprints,
+        config: Config,
+        dimensions: Vec<Dimension>,
+        fitness: F,
+        descriptor: D,
+    ) -> MapElites<'a, F, D> {
+        let seed = config
+            .seed
+            .unwrap_or_else(|| rand::thread_rng().gen::<u64>());
+
+        let mut map_elites = MapElites {
+            blueprints,
+            config,
+            archive: Archive::new(dimensions),
+            iteration: 0,
+            rng: Pcg32::seed_from_u64(seed),
+            evaluations: 0,
+            fitness,
+            descriptor,
+        };
+
+        for _ in 0..map_elites.config.population_size {
+            let chromosome = EgpChromosome::ancestor_from_blueprints_with_rng(
+                &mut map_elites.rng,
+                blueprints,
+                map_elites.config.chromosome_size,
+            );
+            map_elites.insert(chromosome);
+        }
+
+        map_elites
+    }
+
+    /// Evaluates a chromosome and offers it to the archive
+    pub fn insert(&mut self, chromosome: EgpChromosome) -> bool {
+        let phenotype = express_with(self.blueprints, &chromosome, &self.config.expression);
+        self.evaluations += 1;
+
+        let elite = Elite {
+            fitness: (self.fitness)(&phenotype),
+            descriptor: (self.descriptor)(&phenotype),
+            chromosome,
+        };
+
+        self.archive.insert(elite)
+    }
+
+    fn random_elite(&mut self) -> Option<EgpChromosome> {
+        let n = self.archive.elites().count();
+        if n == 0 {
+            return None;
+        }
+
+        let chosen = self.rng.gen_range(0, n);
+        self.archive
+            .elites()
+            .nth(chosen)
+            .map(|elite| elite.chromosome.clone())
+    }
+
+    /// Produces and inserts `config.population_size` offspring, returning how many were placed
+    pub fn step(&mut self) -> usize {
+        self.iteration += 1;
+
+        let mut placed = 0;
+        for _ in 0..self.config.population_size {
+            let parent = match self.random_elite() {
+                Some(parent) => parent,
+                None => EgpChromosome::ancestor_from_blueprints_with_rng(
+                    &mut self.rng,
+                    self.blueprints,
+                    self.config.chromosome_size,
+                ),
+            };
+
+            let donor = if self.rng.gen_range(0., 1.) < self.config.recombination_rate {
+                Some(self.random_elite().unwrap_or_else(|| parent.clone()))
+            } else {
+                None
+            };
+            let (chromosome, _) = vary_with_rng(
+                &mut self.rng,
+                self.blueprints,
+                self.config.n_transfer,
+                &parent,
+                donor.as_ref(),
+            );
+
+            if self.insert(chromosome) {
+                placed += 1;
+            }
+        }
+
+        placed
+    }
+
+    /// Runs `config.generations` iterations and returns the archive
+    pub fn run(&mut self) -> &Archive {
+        while self.iteration < self.config.generations {
+            self.step();
+        }
+
+        &self.archive
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn elite(descriptor: Vec<f64>, fitness: f64) -> Elite {
+        let blueprints = testing::arithmetic();
+
+        Elite {
+            chromosome: testing::ancestors(&blueprints, 0, 1).remove(0),
+            fitness,
+            descriptor,
+        }
+    }
+
+    fn grid() -> Archive {
+        Archive::new(vec![
+            Dimension::new("size", 0., 10., 5),
+            Dimension::new("weak", 0., 3., 3),
+        ])
+    }
+
+    #[test]
+    fn values_outside_the_range_are_clamped() {
+        let dimension = Dimension::new("size", 0., 10., 5);
+
+        assert_eq!(dimension.bin(-1.), 0);
+        assert_eq!(dimension.bin(0.), 0);
+        assert_eq!(dimension.bin(4.), 2);
+        assert_eq!(dimension.bin(10.), 4);
+        assert_eq!(dimension.bin(f64::NAN), 0);
+        assert_eq!(dimension.lower(2), 4.);
+    }
+
+    #[test]
+    fn cells_keep_the_fittest() {
+        let mut archive = grid();
+
+        assert!(archive.insert(elite(vec![4., 1.], 1.)));
+        assert!(!archive.insert(elite(vec![5., 1.5], 1.)));
+        assert!(archive.insert(elite(vec![5., 1.5], 2.)));
+        assert!(archive.insert(elite(vec![9., 0.], 0.5)));
+
+        assert_eq!(archive.index(&[2, 1]), 7);
+        assert_eq!(archive.get(&[2, 1]).unwrap().fitness, 2.);
+        assert_eq!(archive.coverage(), 2. / 15.);
+        assert_eq!(archive.qd_score(), 2.5);
+        assert_eq!(archive.best().unwrap().fitness, 2.);
+
+        let heatmap = archive.heatmap(0, 1);
+        assert_eq!(heatmap.len(), 3);
+        assert_eq!(heatmap[1][2], Some(2.));
+        assert_eq!(heatmap[0][4], Some(0.5));
+        assert_eq!(heatmap[2][0], None);
+    }
+
+    #[test]
+    fn nan_fitness_is_never_placed() {
+        let mut archive = grid();
+
+        assert!(!archive.insert(elite(vec![4., 1.], f64::NAN)));
+        assert_eq!(archive.coverage(), 0.);
+
+        assert!(archive.insert(elite(vec![4., 1.], f64::NEG_INFINITY)));
+        assert!(!archive.insert(elite(vec![4., 1.], f64::NAN)));
+        assert_eq!(archive.get(&[2, 1]).unwrap().fitness, f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn csv_has_a_line_per_elite() {
+        let mut archive = grid();
+        archive.insert(elite(vec![4., 1.], 1.));
+
+        let mut csv = Vec::new();
+        archive.write_csv(&mut csv).unwrap();
+
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "size_bin,weak_bin,size,weak,fitness\n2,1,4,1,1\n"
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn descriptors_must_match_the_dimensions() {
+        grid().insert(elite(vec![4.], 1.));
+    }
+
+    #[test]
+    fn run_fills_the_archive() {
+        let blueprints = testing::arithmetic();
+        let mut map_elites = MapElites::new(
+            &blueprints,
+            testing::config(3),
+            vec![
+                Dimension::new("size", 0., 40., 8),
+                Dimension::new("weak", 0., 4., 4),
+            ],
+            testing::size,
+            |phenotype: &Phenotype| vec![phenotype_size(phenotype), weak_bindings(phenotype)],
+        );
+
+        let seeded = map_elites.archive.elites().count();
+        assert!(seeded > 0);
+
+        map_elites.run();
+        assert_eq!(map_elites.iteration, 5);
+        assert_eq!(map_elites.evaluations, 20 * 6);
+        assert!(map_elites.archive.elites().count() >= seeded);
+
+        for elite in map_elites.archive.elites() {
+            let phenotype = express_with(
+                &blueprints,
+                &elite.chromosome,
+                &map_elites.config.expression,
+            );
+            assert_eq!(elite.fitness, testing::size(&phenotype));
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::evolution::Individual;
+use crate::lineage::IndividualId;
+
+/// Parameters of novelty search, see `Config::novelty`
+///
+/// Tournaments compare a blend of novelty and fitness, while elitism and survival still go by
+/// fitness, so the fittest individual is kept as long as `elitism` is at least one.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NoveltyConfig {
+    /// Number of nearest neighbours averaged into the sparseness
+    pub k: usize,
+    /// Individuals at least this sparse are added to the archive
+    #[serde(with = "crate::checkpoint::float")]
+    pub archive_threshold: f64,
+    /// Probability that any other individual is added to the archive
+    pub archive_probability: f64,
+    /// Oldest behaviours are dropped beyond this size
+    pub max_archive: Option<usize>,
+    /// Selection score is `(1 - w) * novelty + w * fitness`, 0 for pure novelty search
+    ///
+    /// Novelty and fitness are blended as they are, so they should be on comparable scales.
+    pub fitness_weight: f64,
+}
+
+impl Default for NoveltyConfig {
+    fn default() -> NoveltyConfig {
+        NoveltyConfig {
+            k: 15,
+            archive_threshold: 1.,
+            archive_probability: 0.,
+            max_archive: None,
+            fitness_weight: 0.,
+        }
+    }
+}
+
+pub fn euclidean(a: &[f64], b: &[f64]) -> f64 {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| (a - b) * (a - b))
+        .sum::<f64>()
+        .sqrt()
+}
+
+/// Mean distance from `behaviour` to its `k` nearest neighbours among `others`
+///
+/// Uses all neighbours when there are fewer than `k`, and 0 when there are none.
+pub fn sparseness<'b, I>(behaviour: &[f64], others: I, k: usize) -> f64
+where
+    I: IntoIterator<Item = &'b [f64]>,
+{
+    let mut distances: Vec<f64> = others
+        .into_iter()
+        .map(|other| euclidean(behaviour, other))
+        .collect();
+    distances.sort_by(|a, b| a.total_cmp(b));
+    distances.truncate(k);
+
+    if distances.is_empty() {
+        0.
+    } else {
+        distances.iter().sum::<f64>() / distances.len() as f64
+    }
+}
+
+/// Behaviours of past individuals that were novel when evaluated, see `Config::novelty`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BehaviourArchive {
+    #[serde(with = "crate::checkpoint::float::nested")]
+    pub behaviours: Vec<Vec<f64>>,
+    /// Individuals with smaller ids were already offered to the archive
+    pub offered: IndividualId,
+}
+
+impl BehaviourArchive {
+    pub fn new() -> BehaviourArchive {
+        BehaviourArchive::default()
+    }
+
+    /// Sparseness of each member against the rest of the population and the archive
+    pub fn novelty(&self, population: &[Individual], k: usize) -> Vec<f64> {
+        (0..population.len())
+            .map(|i| {
+                let others = population
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .map(|(_, other)| other.behaviour.as_slice())
+                    .chain(self.behaviours.iter().map(|behaviour| behaviour.as_slice()));
+
+                sparseness(&population[i].behaviour, others, k)
+            })
+            .collect()
+    }
+
+    /// Offers the members born since the last offer, given their novelty
+    ///
+    /// Members carried over from an earlier population were already offered. The oldest
+    /// behaviours are dropped beyond `max_archive`.
+    pub fn offer<R: Rng + ?Sized>(
+        &mut self,
+        rng: &mut R,
+        config: &NoveltyConfig,
+        population: &[Individual],
+        novelty: &[f64],
+    ) {
+        for (individual, novelty) in population.iter().zip(novelty) {
+            if individual.id < self.offered {
+                continue;
+            }
+
+            if *novelty >= config.archive_threshold
+                || rng.gen_range(0., 1.) < config.archive_probability
+            {
+                self.behaviours.push(individual.behaviour.clone());
+            }
+        }
+
+        if let Some(next) = population.iter().map(|individual| individual.id + 1).max() {
+            self.offered = self.offered.max(next);
+        }
+
+        if let Some(max) = config.max_archive {
+            if self.behaviours.len() > max {
+                let excess = self.behaviours.len() - max;
+                self.behaviours.drain(..excess);
+            }
+        }
+    }
+}
+
+/// Selection scores of a population, see `NoveltyConfig::fitness_weight`
+pub fn scores(config: &NoveltyConfig, population: &[Individual], novelty: &[f64]) -> Vec<f64> {
+    let weight = config.fitness_weight;
+
+    population
+        .iter()
+        .zip(novelty)
+        .map(|(individual, novelty)| (1. - weight) * novelty + weight * individual.fitness)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evolution::{Config, ConfigError, Evolution, Replacement, WithBehaviour};
+    use crate::testing;
+    use crate::Phenotype;
+
+    #[test]
+    fn sparseness_averages_the_nearest() {
+        let others: Vec<Vec<f64>> = vec![vec![1., 0.], vec![0., 2.], vec![3., 4.]];
+        let others = || others.iter().map(|other| other.as_slice());
+
+        assert_eq!(sparseness(&[0., 0.], others(), 2), 1.5);
+        assert_eq!(sparseness(&[0., 0.], others(), 10), 8. / 3.);
+        assert_eq!(sparseness(&[0., 0.], std::iter::empty(), 3), 0.);
+    }
+
+    fn behaviour(phenotype: &Phenotype) -> WithBehaviour<f64> {
+        WithBehaviour {
+            fitness: testing::size(phenotype),
+            behaviour: vec![testing::size(phenotype)],
+        }
+    }
+
+    fn novelty_config(seed: u64) -> Config {
+        Config {
+            elitism: 3,
+            novelty: Some(NoveltyConfig {
+                archive_threshold: 0.,
+                ..Default::default()
+            }),
+            ..testing::config(seed)
+        }
+    }
+
+    #[test]
+    fn only_newborns_are_archived() {
+        let blueprints = testing::arithmetic();
+        let mut evolution = Evolution::new(&blueprints, novelty_config(5), behaviour);
+        let archived =
+            |evolution: &Evolution<_>| evolution.archive.as_ref().unwrap().behaviours.len();
+        assert_eq!(archived(&evolution), 0);
+
+        // each generation offers the members born in the previous one
+        evolution.step();
+        assert_eq!(archived(&evolution), 20);
+        evolution.run();
+        assert_eq!(archived(&evolution), 20 + 4 * (20 - 3));
+        assert!(evolution
+            .population
+            .iter()
+            .all(|individual| individual.behaviour == vec![individual.fitness]));
+    }
+
+    #[test]
+    fn tournaments_prefer_novel_behaviour() {
+        let blueprints = testing::arithmetic();
+        let config = Config {
+            tournament_size: 200,
+            novelty: Some(NoveltyConfig {
+                archive_threshold: f64::INFINITY,
+                ..Default::default()
+            }),
+            ..testing::config(6)
+        };
+        let mut evolution = Evolution::new(&blueprints, config, behaviour);
+
+        // a large tournament picks the most novel member, the archive being empty
+        let novelty = BehaviourArchive::new().novelty(&evolution.population, 15);
+        let most_novel = novelty.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let winner = evolution.tournament();
+        assert_eq!(novelty[winner], most_novel);
+    }
+
+    #[test]
+    fn unsupported_combinations_are_rejected() {
+        let blueprints = testing::arithmetic();
+        let speciated = Config {
+            speciation: Some(Default::default()),
+            ..novelty_config(7)
+        };
+        let nsga2 = Config {
+            replacement: Replacement::Nsga2,
+            ..novelty_config(7)
+        };
+
+        assert_eq!(
+            speciated.validate(),
+            Err(ConfigError::NoveltyWithSpeciation)
+        );
+        assert_eq!(nsga2.validate(), Err(ConfigError::NoveltyWithNsga2));
+        assert!(Evolution::try_new(&blueprints, nsga2, behaviour).is_err());
+        assert!(novelty_config(7).validate().is_ok());
+    }
+}
@@ -0,0 +1,213 @@
+use std::cmp::Ordering;
+
+use crate::evolution::Individual;
+
+/// Whether `a` is at least as good as `b` in every objective and better in one, all maximized
+pub fn dominates(a: &[f64], b: &[f64]) -> bool {
+    let mut better = false;
+
+    for (a, b) in a.iter().zip(b) {
+        if a < b {
+            return false;
+        }
+        if a > b {
+            better = true;
+        }
+    }
+
+    better
+}
+
+/// Fast non-dominated sorting, indices of the first front first
+pub fn non_dominated_sort(objectives: &[Vec<f64>]) -> Vec<Vec<usize>> {
+    let n = objectives.len();
+    let mut dominated: Vec<Vec<usize>> = vec![vec![]; n];
+    let mut domination_count = vec![0; n];
+
+    for i in 0..n {
+        for j in (i + 1)..n {
+            if dominates(&objectives[i], &objectives[j]) {
+                dominated[i].push(j);
+                domination_count[j] += 1;
+            } else if dominates(&objectives[j], &objectives[i]) {
+                dominated[j].push(i);
+                domination_count[i] += 1;
+            }
+        }
+    }
+
+    let mut fronts = vec![];
+    let mut front: Vec<usize> = (0..n).filter(|&i| domination_count[i] == 0).collect();
+
+    while !front.is_empty() {
+        let mut next = vec![];
+
+        for &i in &front {
+            for &j in &dominated[i] {
+                domination_count[j] -= 1;
+                if domination_count[j] == 0 {
+                    next.push(j);
+                }
+            }
+        }
+
+        next.sort_unstable();
+        fronts.push(front);
+        front = next;
+    }
+
+    fronts
+}
+
+/// Crowding distance of every member of a front, in the order of `front`
+///
+/// Boundary solutions of each objective get an infinite distance.
+pub fn crowding_distance(objectives: &[Vec<f64>], front: &[usize]) -> Vec<f64> {
+    let mut distances = vec![0.; front.len()];
+
+    if front.len() <= 2 {
+        return vec![f64::INFINITY; front.len()];
+    }
+
+    let n_objectives = objectives[front[0]].len();
+
+    let columns = (0..n_objectives).map(|objective| {
+        front
+            .iter()
+            .map(|&i| objectives[i][objective])
+            .collect::<Vec<f64>>()
+    });
+
+    for values in columns {
+        let mut order: Vec<usize> = (0..front.len()).collect();
+        order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+
+        let first = order[0];
+        let last = order[order.len() - 1];
+        let range = values[last] - values[first];
+
+        distances[first] = f64::INFINITY;
+        distances[last] = f64::INFINITY;
+
+        if range <= 0. {
+            continue;
+        }
+
+        for window in order.windows(3) {
+            distances[window[1]] += (values[window[2]] - values[window[0]]) / range;
+        }
+    }
+
+    distances
+}
+
+/// Non-dominated rank and crowding distance of every member of a population, by index
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ranking {
+    /// Index of the front of each member, 0 for the Pareto front
+    pub rank: Vec<usize>,
+    pub crowding: Vec<f64>,
+}
+
+impl Ranking {
+    pub fn new(objectives: &[Vec<f64>]) -> Ranking {
+        let mut rank = vec![0; objectives.len()];
+        let mut crowding = vec![0.; objectives.len()];
+
+        for (index, front) in non_dominated_sort(objectives).into_iter().enumerate() {
+            let distances = crowding_distance(objectives, &front);
+
+            for (member, distance) in front.into_iter().zip(distances) {
+                rank[member] = index;
+                crowding[member] = distance;
+            }
+        }
+
+        Ranking { rank, crowding }
+    }
+
+    /// Ranks individuals by their objectives, or their fitness as the single objective
+    pub fn of(population: &[Individual]) -> Ranking {
+        let objectives: Vec<Vec<f64>> = population
+            .iter()
+            .map(|individual| individual.objectives().to_vec())
+            .collect();
+
+        Ranking::new(&objectives)
+    }
+
+    /// Crowded comparison: lower rank first, then larger crowding distance
+    pub fn compare(&self, a: usize, b: usize) -> Ordering {
+        self.rank[a]
+            .cmp(&self.rank[b])
+            .then_with(|| self.crowding[b].total_cmp(&self.crowding[a]))
+    }
+
+    /// Indices in crowded comparison order, ties keeping their index order
+    pub fn order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.rank.len()).collect();
+        order.sort_by(|&a, &b| self.compare(a, b));
+        order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // worked by hand, both objectives maximized
+    fn objectives() -> Vec<Vec<f64>> {
+        vec![
+            vec![1., 5.],
+            vec![2., 4.],
+            vec![4., 1.],
+            vec![1., 3.],
+            vec![2., 2.],
+            vec![0., 0.],
+            vec![3., 2.],
+        ]
+    }
+
+    #[test]
+    fn domination() {
+        assert!(dominates(&[2., 4.], &[1., 3.]));
+        assert!(dominates(&[1., 5.], &[1., 3.]));
+        assert!(!dominates(&[1., 5.], &[2., 4.]));
+        assert!(!dominates(&[1., 5.], &[1., 5.]));
+    }
+
+    #[test]
+    fn fronts() {
+        assert_eq!(
+            non_dominated_sort(&objectives()),
+            vec![vec![0, 1, 2, 6], vec![3, 4], vec![5]]
+        );
+        assert_eq!(non_dominated_sort(&[]), Vec::<Vec<usize>>::new());
+    }
+
+    #[test]
+    fn crowding_distances() {
+        let objectives = objectives();
+
+        // x spans 3 and y spans 4, the inner members have neighbours 2 apart in x and 3 in y
+        let inner = 2. / 3. + 3. / 4.;
+        let distances = crowding_distance(&objectives, &[0, 1, 2, 6]);
+        assert_eq!(distances[0], f64::INFINITY);
+        assert_eq!(distances[2], f64::INFINITY);
+        assert!((distances[1] - inner).abs() < 1e-12);
+        assert!((distances[3] - inner).abs() < 1e-12);
+
+        assert_eq!(
+            crowding_distance(&objectives, &[3, 4]),
+            vec![f64::INFINITY; 2]
+        );
+    }
+
+    #[test]
+    fn crowded_order() {
+        let ranking = Ranking::new(&objectives());
+
+        assert_eq!(ranking.rank, vec![0, 0, 0, 1, 1, 2, 0]);
+        assert_eq!(ranking.order(), vec![0, 2, 1, 6, 3, 4, 5]);
+    }
+}
@@ -1,27 +1,85 @@
 use rand;
 use rand::Rng;
+use serde::{Deserialize, Serialize};
 
+use crate::analysis::bloat;
 use crate::blueprints::Blueprints;
 use crate::chromosome::EgpChromosome;
 use crate::component::Component;
+use crate::evolution::phenotype_key;
+use crate::lineage::Operator;
+use crate::{express_with, ExpressionConfig, Phenotype};
+
+/// Record of the change made by a mutation, used for incremental re-expression
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Mutation {
+    /// No compatible component was found, the chromosome is unchanged
+    Unchanged,
+    Activity {
+        group: usize,
+        index: usize,
+    },
+    BindingSite {
+        group: usize,
+        index: usize,
+        binding_site: usize,
+        dimension: usize,
+    },
+    OutputBindingSite {
+        output: usize,
+        binding_site: usize,
+        dimension: usize,
+    },
+    /// The value of an ephemeral random constant was perturbed
+    Constant {
+        group: usize,
+        index: usize,
+    },
+}
 
-/// Performs the mutation genetic operator in-place
-pub fn mutate(blueprints: &Blueprints, chromosome: &mut EgpChromosome) {
-    let mut rng = rand::thread_rng();
+/// Performs the mutation genetic operator in-place, returning a record of the change
+pub fn mutate(blueprints: &Blueprints, chromosome: &mut EgpChromosome) -> Mutation {
+    mutate_with_rng(&mut rand::thread_rng(), blueprints, chromosome)
+}
 
+/// Performs mutation like `mutate`, drawing from the given random number generator
+pub fn mutate_with_rng<R: Rng + ?Sized>(
+    rng: &mut R,
+    blueprints: &Blueprints,
+    chromosome: &mut EgpChromosome,
+) -> Mutation {
     if rng.gen_range(0., 1.) < 0.5 {
-        mutate_activity(blueprints, chromosome);
+        mutate_activity(rng, blueprints, chromosome)
     } else {
         let n_regulars: usize = chromosome.regular.iter().map(|group| group.len()).sum();
 
-        if rng.gen_range(0., 1.) < 1. / (n_regulars as f32) {
-            mutate_binding_site_output(blueprints, chromosome);
+        let n_outputs = chromosome.outputs.len();
+
+        if rng.gen_range(0., 1.) < n_outputs as f32 / (n_regulars as f32) {
+            mutate_binding_site_output(rng, blueprints, chromosome)
         } else {
-            mutate_binding_site(blueprints, chromosome);
+            mutate_binding_site(rng, blueprints, chromosome)
         }
     }
 }
 
+/// Record of the change made by a recombination
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Recombination {
+    /// `count` components starting at `start` in the donor's group were appended
+    Transfer {
+        group: usize,
+        start: usize,
+        count: usize,
+    },
+    /// `count` components starting at `start` in the group were removed
+    Remove {
+        group: usize,
+        start: usize,
+        count: usize,
+    },
+}
+
 /// Performs the recombination / crossover genetic operator
 ///
 /// The crossover event is either a transfer from parent_a to parent_b, or a
@@ -33,28 +91,84 @@ pub fn recombine(
     parent_a: &EgpChromosome,
     parent_b: &EgpChromosome,
 ) -> EgpChromosome {
-    let mut rng = rand::thread_rng();
+    recombine_recorded(blueprints, n_transfer, parent_a, parent_b).0
+}
 
+/// Performs recombination like `recombine`, also returning a record of the change
+pub fn recombine_recorded(
+    blueprints: &Blueprints,
+    n_transfer: usize,
+    parent_a: &EgpChromosome,
+    parent_b: &EgpChromosome,
+) -> (EgpChromosome, Recombination) {
+    recombine_with_rng(
+        &mut rand::thread_rng(),
+        blueprints,
+        n_transfer,
+        parent_a,
+        parent_b,
+    )
+}
+
+/// Performs recombination like `recombine_recorded`, drawing from the given random number
+/// generator
+pub fn recombine_with_rng<R: Rng + ?Sized>(
+    rng: &mut R,
+    blueprints: &Blueprints,
+    n_transfer: usize,
+    parent_a: &EgpChromosome,
+    parent_b: &EgpChromosome,
+) -> (EgpChromosome, Recombination) {
     if rng.gen_range(0., 1.) < 0.5 {
-        recombine_remove(blueprints, n_transfer, parent_a)
+        recombine_remove(rng, blueprints, n_transfer, parent_a)
     } else {
-        recombine_transfer(blueprints, n_transfer, parent_a, parent_b)
+        recombine_transfer(rng, blueprints, n_transfer, parent_a, parent_b)
+    }
+}
+
+/// Produces an offspring of `parent`, recombined with `donor` when there is one, otherwise mutated
+pub fn vary_with_rng<R: Rng + ?Sized>(
+    rng: &mut R,
+    blueprints: &Blueprints,
+    n_transfer: usize,
+    parent: &EgpChromosome,
+    donor: Option<&EgpChromosome>,
+) -> (EgpChromosome, Operator) {
+    match donor {
+        Some(donor) => {
+            let (chromosome, recombination) =
+                recombine_with_rng(rng, blueprints, n_transfer, parent, donor);
+            (chromosome, Operator::Recombination(recombination))
+        }
+        None => {
+            let mut chromosome = parent.clone();
+            let mutation = mutate_with_rng(rng, blueprints, &mut chromosome);
+            (chromosome, Operator::Mutation(mutation))
+        }
     }
 }
 
-fn recombine_transfer(
+fn recombine_transfer<R: Rng + ?Sized>(
+    rng: &mut R,
     blueprints: &Blueprints,
     n_transfer: usize,
     parent: &EgpChromosome,
     donor: &EgpChromosome,
-) -> EgpChromosome {
+) -> (EgpChromosome, Recombination) {
     let mut child = parent.clone();
 
-    let mut rng = rand::thread_rng();
-
-    let nonempty_group = nonempty_group(blueprints);
+    let nonempty_group = nonempty_group(rng, blueprints);
     let group_len = donor.regular[nonempty_group].len();
 
+    if group_len == 0 {
+        let record = Recombination::Transfer {
+            group: nonempty_group,
+            start: 0,
+            count: 0,
+        };
+        return (child, record);
+    }
+
     let skip = rng.gen_range(0, group_len);
 
     let mut n_transfer = if n_transfer < group_len {
@@ -63,31 +177,48 @@ fn recombine_transfer(
         group_len
     };
 
+    let mut count = 0;
+
     for i in skip..group_len {
-        if n_transfer <= 0 {
+        if n_transfer == 0 {
             break;
         }
 
         child.regular[nonempty_group].push(donor.regular[nonempty_group][i].clone());
 
         n_transfer -= 1;
+        count += 1;
     }
 
-    child
+    let record = Recombination::Transfer {
+        group: nonempty_group,
+        start: skip,
+        count,
+    };
+
+    (child, record)
 }
 
-fn recombine_remove(
+fn recombine_remove<R: Rng + ?Sized>(
+    rng: &mut R,
     blueprints: &Blueprints,
     n_remove: usize,
     parent: &EgpChromosome,
-) -> EgpChromosome {
+) -> (EgpChromosome, Recombination) {
     let mut child = parent.clone();
 
-    let mut rng = rand::thread_rng();
-
-    let nonempty_group = nonempty_group(blueprints);
+    let nonempty_group = nonempty_group(rng, blueprints);
     let group_len = child.regular[nonempty_group].len();
 
+    if group_len == 0 {
+        let record = Recombination::Remove {
+            group: nonempty_group,
+            start: 0,
+            count: 0,
+        };
+        return (child, record);
+    }
+
     let mut n_remove = if n_remove < group_len {
         n_remove
     } else {
@@ -95,6 +226,7 @@ fn recombine_remove(
     };
 
     let skip = rng.gen_range(0, group_len);
+    let mut count = 0;
 
     for i in skip..group_len {
         if n_remove == 0 || i < child.regular[nonempty_group].len() {
@@ -104,15 +236,108 @@ fn recombine_remove(
         child.regular[nonempty_group].remove(i);
 
         n_remove -= 1;
+        count += 1;
+    }
+
+    let record = Recombination::Remove {
+        group: nonempty_group,
+        start: skip,
+        count,
+    };
+
+    (child, record)
+}
+
+/// Removes unexpressed regular components, leaving the phenotype unchanged
+///
+/// Removal shifts component indices, which can change how the remaining components are expressed.
+/// When removing all unexpressed components at once changes the phenotype, they are removed one
+/// by one, keeping only the removals that don't. Groups are never emptied, since the operators
+/// need a component to pick.
+pub fn prune(blueprints: &Blueprints, chromosome: &EgpChromosome) -> EgpChromosome {
+    prune_with(blueprints, chromosome, &ExpressionConfig::default())
+}
+
+/// Removes unexpressed regular components like `prune`, expressing with the given parameters
+pub fn prune_with(
+    blueprints: &Blueprints,
+    chromosome: &EgpChromosome,
+    config: &ExpressionConfig,
+) -> EgpChromosome {
+    prune_by(chromosome, |candidate| {
+        express_with(blueprints, candidate, config)
+    })
+}
+
+/// Removes unexpressed regular components, keeping the `phenotype_key` of the expression
+///
+/// The key covers labels, constant values and the binding of every argument, so a removal that
+/// only moves a weak binding to another site is rejected too.
+fn prune_by<E>(chromosome: &EgpChromosome, express: E) -> EgpChromosome
+where
+    E: Fn(&EgpChromosome) -> Phenotype,
+{
+    let phenotype = express(chromosome);
+    let expected = phenotype_key(&phenotype);
+    let unchanged = |candidate: &EgpChromosome| phenotype_key(&express(candidate)) == expected;
+
+    let unexpressed: Vec<Vec<usize>> = bloat(chromosome, &phenotype)
+        .into_iter()
+        .map(|group| group.unexpressed)
+        .collect();
+
+    let all_removed = remove_components(chromosome, &unexpressed);
+    if unchanged(&all_removed) {
+        return all_removed;
+    }
+
+    let mut pruned = chromosome.clone();
+
+    // backwards, so that earlier indices stay valid
+    for (group, indices) in unexpressed.iter().enumerate() {
+        for index in indices.iter().rev() {
+            if pruned.regular[group].len() == 1 {
+                break;
+            }
+
+            let mut candidate = pruned.clone();
+            candidate.regular[group].remove(*index);
+
+            if unchanged(&candidate) {
+                pruned = candidate;
+            }
+        }
+    }
+
+    pruned
+}
+
+fn remove_components(chromosome: &EgpChromosome, indices: &[Vec<usize>]) -> EgpChromosome {
+    let mut result = chromosome.clone();
+
+    for (group, indices) in indices.iter().enumerate() {
+        let keep_one = indices.len() == result.regular[group].len();
+
+        let mut index = 0;
+        result.regular[group].retain(|_| {
+            let remove = indices.contains(&index) && !(keep_one && index == indices[0]);
+            index += 1;
+            !remove
+        });
     }
 
-    child
+    result
 }
 
-fn mutate_activity(blueprints: &Blueprints, chromosome: &mut EgpChromosome) {
-    let (group, member) = pick_group_and_member(blueprints);
+fn mutate_activity<R: Rng + ?Sized>(
+    rng: &mut R,
+    blueprints: &Blueprints,
+    chromosome: &mut EgpChromosome,
+) -> Mutation {
+    let (group, member) = pick_group_and_member(rng, blueprints);
 
-    let new_component = Component::from_blueprint(
+    let new_component = Component::from_blueprint_with_rng(
+        rng,
         &blueprints.regular[group][member],
         blueprints.total_activities,
     );
@@ -123,11 +348,9 @@ fn mutate_activity(blueprints: &Blueprints, chromosome: &mut EgpChromosome) {
         .count();
 
     if n_compatible == 0 {
-        return;
+        return Mutation::Unchanged;
     }
 
-    let mut rng = rand::thread_rng();
-
     let to_replace = rng.gen_range(0, n_compatible);
 
     let mut n_encountered = 0;
@@ -137,41 +360,93 @@ fn mutate_activity(blueprints: &Blueprints, chromosome: &mut EgpChromosome) {
             if to_replace == n_encountered {
                 chromosome.regular[group][i].activity = new_component.activity;
                 chromosome.regular[group][i].label = new_component.label.clone();
-                break;
+                return Mutation::Activity { group, index: i };
             }
 
             n_encountered += 1;
         }
     }
-}
 
-fn mutate_binding_site(blueprints: &Blueprints, chromosome: &mut EgpChromosome) {
-    let mut rng = rand::thread_rng();
+    Mutation::Unchanged
+}
 
-    let nonempty_group = nonempty_group(blueprints);
+fn mutate_binding_site<R: Rng + ?Sized>(
+    rng: &mut R,
+    blueprints: &Blueprints,
+    chromosome: &mut EgpChromosome,
+) -> Mutation {
+    let nonempty_group = nonempty_group(rng, blueprints);
     let group_len = chromosome.regular[nonempty_group].len();
 
-    let component = &mut chromosome.regular[nonempty_group][rng.gen_range(0, group_len)];
+    // groups with blueprints can still be empty in a chromosome
+    if group_len == 0 {
+        return Mutation::Unchanged;
+    }
+
+    let index = rng.gen_range(0, group_len);
+    let component = &mut chromosome.regular[nonempty_group][index];
+
+    // ephemeral random constants have a value to mutate instead of binding sites
+    if component.binding_sites.is_empty() {
+        let ephemeral = blueprints.regular[nonempty_group]
+            .iter()
+            .find(|blueprint| blueprint.activity == component.activity)
+            .and_then(|blueprint| blueprint.ephemeral);
+
+        return match (component.value.as_mut(), ephemeral) {
+            (Some(value), Some(ephemeral)) => {
+                *value = ephemeral.reflect(*value + ephemeral.sigma * standard_normal(rng));
+                Mutation::Constant {
+                    group: nonempty_group,
+                    index,
+                }
+            }
+            _ => Mutation::Unchanged,
+        };
+    }
 
     let binding_site_index = rng.gen_range(0, component.binding_sites.len());
     let dimension = rng.gen_range(0, blueprints.total_activities);
 
     component.binding_sites[binding_site_index][dimension] = rng.gen::<f32>();
-}
 
-fn mutate_binding_site_output(blueprints: &Blueprints, chromosome: &mut EgpChromosome) {
-    let mut rng = rand::thread_rng();
+    Mutation::BindingSite {
+        group: nonempty_group,
+        index,
+        binding_site: binding_site_index,
+        dimension,
+    }
+}
 
-    let component = &mut chromosome.output;
+fn mutate_binding_site_output<R: Rng + ?Sized>(
+    rng: &mut R,
+    blueprints: &Blueprints,
+    chromosome: &mut EgpChromosome,
+) -> Mutation {
+    let output = rng.gen_range(0, chromosome.outputs.len());
+    let component = &mut chromosome.outputs[output];
 
     let binding_site_index = rng.gen_range(0, component.binding_sites.len());
     let dimension = rng.gen_range(0, blueprints.total_activities);
 
     component.binding_sites[binding_site_index][dimension] = rng.gen::<f32>();
+
+    Mutation::OutputBindingSite {
+        output,
+        binding_site: binding_site_index,
+        dimension,
+    }
 }
 
-fn nonempty_group(blueprints: &Blueprints) -> usize {
-    let mut rng = rand::thread_rng();
+/// Standard normal sample by the Box-Muller transform
+fn standard_normal<R: Rng + ?Sized>(rng: &mut R) -> f64 {
+    let u: f64 = 1. - rng.gen::<f64>(); // in (0, 1], so the logarithm is finite
+    let v: f64 = rng.gen();
+
+    (-2. * u.ln()).sqrt() * (2. * std::f64::consts::PI * v).cos()
+}
+
+fn nonempty_group<R: Rng + ?Sized>(rng: &mut R, blueprints: &Blueprints) -> usize {
     let nonempty_groups = nonempty_groups(blueprints);
     nonempty_groups[rng.gen_range(0, nonempty_groups.len())]
 }
@@ -181,16 +456,143 @@ fn nonempty_groups(blueprints: &Blueprints) -> Vec<usize> {
         .regular
         .iter()
         .enumerate()
-        .filter(|(_group_index, group)| group.len() > 0)
+        .filter(|(_group_index, group)| !group.is_empty())
         .map(|(group_index, _group)| group_index)
         .collect()
 }
 
-fn pick_group_and_member(blueprints: &Blueprints) -> (usize, usize) {
-    let mut rng = rand::thread_rng();
-
-    let group = nonempty_group(blueprints);
+fn pick_group_and_member<R: Rng + ?Sized>(rng: &mut R, blueprints: &Blueprints) -> (usize, usize) {
+    let group = nonempty_group(rng, blueprints);
     let member = rng.gen_range(0, blueprints.regular[group].len());
 
     (group, member)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blueprints::Ephemeral;
+    use crate::problems::regression::arithmetic_blueprints_with_ephemeral;
+    use crate::testing;
+    use crate::{express, Binding};
+
+    #[test]
+    fn prune_keeps_the_phenotype() {
+        let blueprints = testing::arithmetic();
+
+        for chromosome in testing::ancestors(&blueprints, 1, 20) {
+            let phenotype = express(&blueprints, &chromosome);
+            let pruned = prune(&blueprints, &chromosome);
+            let pruned_phenotype = express(&blueprints, &pruned);
+
+            assert_eq!(phenotype_key(&pruned_phenotype), phenotype_key(&phenotype));
+            for (group, components) in pruned.regular.iter().enumerate() {
+                assert!(!components.is_empty());
+                assert!(components.len() <= chromosome.regular[group].len());
+            }
+
+            let before: usize = bloat(&chromosome, &phenotype)
+                .iter()
+                .map(|group| group.unexpressed.len())
+                .sum();
+            let after: usize = bloat(&pruned, &pruned_phenotype)
+                .iter()
+                .map(|group| group.unexpressed.len())
+                .sum();
+            assert!(after <= before);
+        }
+    }
+
+    #[test]
+    fn prune_rejects_removals_moving_a_weak_binding() {
+        let blueprints = testing::arithmetic();
+        let chromosome = testing::ancestors(&blueprints, 3, 20)
+            .into_iter()
+            .find(|chromosome| {
+                let phenotype = express(&blueprints, chromosome);
+                let unexpressed = &bloat(chromosome, &phenotype)[0].unexpressed;
+                phenotype
+                    .edge_weights()
+                    .any(|binding| *binding == Binding::Weak(0))
+                    && !unexpressed.is_empty()
+            })
+            .unwrap();
+        let length = chromosome.regular[0].len();
+
+        // removals only rebind the weak arguments to another site, which `sexpr` doesn't show
+        let pruned = prune_by(&chromosome, |candidate| {
+            let mut phenotype = express(&blueprints, candidate);
+            if candidate.regular[0].len() < length {
+                for binding in phenotype.edge_weights_mut() {
+                    if *binding == Binding::Weak(0) {
+                        *binding = Binding::Weak(1);
+                    }
+                }
+            }
+            phenotype
+        });
+
+        assert_eq!(pruned.regular[0].len(), length);
+    }
+
+    #[test]
+    fn prune_with_keeps_the_phenotype_under_its_parameters() {
+        let blueprints = testing::arithmetic();
+        let config = testing::expression();
+
+        for chromosome in testing::ancestors(&blueprints, 2, 20) {
+            let pruned = prune_with(&blueprints, &chromosome, &config);
+
+            assert_eq!(
+                phenotype_key(&express_with(&blueprints, &pruned, &config)),
+                phenotype_key(&express_with(&blueprints, &chromosome, &config))
+            );
+        }
+    }
+
+    #[test]
+    fn constant_mutations_perturb_only_the_value() {
+        let ephemeral = Ephemeral {
+            min: -1.,
+            max: 1.,
+            sigma: 2.,
+        };
+        assert_eq!(ephemeral.reflect(0.25), 0.25);
+        assert_eq!(ephemeral.reflect(1.5), 0.5);
+        assert_eq!(ephemeral.reflect(-3.5), 0.5);
+        assert_eq!(ephemeral.reflect(4.25), 0.25);
+
+        let blueprints = arithmetic_blueprints_with_ephemeral(&["+", "*"], &["x"], Some(ephemeral));
+        let mut rng = testing::rng(20);
+        let mut constants = 0;
+
+        for mut chromosome in testing::ancestors(&blueprints, 21, 10) {
+            for component in chromosome.regular.iter().flatten() {
+                let value = component.value.unwrap_or(0.);
+                assert_eq!(component.value.is_some(), component.label == "erc");
+                assert!((-1. ..1.).contains(&value));
+            }
+
+            for _ in 0..50 {
+                let before = chromosome.clone();
+
+                if let Mutation::Constant { group, index } =
+                    mutate_with_rng(&mut rng, &blueprints, &mut chromosome)
+                {
+                    constants += 1;
+                    let mut restored = chromosome.clone();
+                    restored.regular[group][index].value = before.regular[group][index].value;
+
+                    assert_eq!(restored, before);
+                    assert_ne!(chromosome, before);
+
+                    // perturbations larger than the range are reflected back into it
+                    let value = chromosome.regular[group][index].value.unwrap();
+                    assert!((-1. ..=1.).contains(&value));
+                }
+            }
+        }
+
+        assert!(constants > 0);
+    }
+}
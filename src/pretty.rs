@@ -0,0 +1,271 @@
+use petgraph::graph::NodeIndex;
+use petgraph::visit::EdgeRef;
+use std::collections::{HashMap, HashSet};
+
+use crate::{Binding, ComponentIndex, Phenotype};
+
+/// The node of the first output of a phenotype
+pub fn output_node(phenotype: &Phenotype) -> Option<NodeIndex> {
+    phenotype
+        .node_indices()
+        .find(|node| phenotype[*node].index == ComponentIndex::Output(0))
+}
+
+/// The output nodes of a phenotype, in the order of the outputs
+pub fn output_nodes(phenotype: &Phenotype) -> Vec<NodeIndex> {
+    let mut outputs: Vec<(usize, NodeIndex)> = phenotype
+        .node_indices()
+        .filter_map(|node| match phenotype[node].index {
+            ComponentIndex::Output(index) => Some((index, node)),
+            _ => None,
+        })
+        .collect();
+    outputs.sort();
+
+    outputs.into_iter().map(|(_index, node)| node).collect()
+}
+
+/// Arguments of a node: strongly bound children in binding site order, then weakly bound ones
+pub fn arguments(phenotype: &Phenotype, node: NodeIndex) -> Vec<NodeIndex> {
+    bound_arguments(phenotype, node)
+        .into_iter()
+        .map(|(_binding, target)| target)
+        .collect()
+}
+
+/// Arguments of a node in the order of `arguments`, along with the bindings reaching them
+pub fn bound_arguments(phenotype: &Phenotype, node: NodeIndex) -> Vec<(Binding, NodeIndex)> {
+    let mut strong = vec![];
+    let mut weak = vec![];
+
+    for edge in phenotype.edges(node) {
+        match *edge.weight() {
+            Binding::Strong(index) => strong.push((index, edge.target())),
+            Binding::Weak(index) => weak.push((index, edge.target())),
+        }
+    }
+
+    strong.sort();
+    weak.sort();
+
+    strong
+        .into_iter()
+        .map(|(index, target)| (Binding::Strong(index), target))
+        .chain(
+            weak.into_iter()
+                .map(|(index, target)| (Binding::Weak(index), target)),
+        )
+        .collect()
+}
+
+/// Nodes targeted by weak bindings or bound by several parents, in an order where each is
+/// defined before it is used
+///
+/// Several parents only occur when outputs share nodes, see `OutputSharing`. A weak binding to an
+/// ancestor makes a cycle, the ancestor then refers to itself by name.
+pub fn shared_nodes(phenotype: &Phenotype) -> Vec<NodeIndex> {
+    let mut parents: HashMap<NodeIndex, usize> = HashMap::new();
+    for edge in phenotype.edge_references() {
+        *parents.entry(edge.target()).or_insert(0) += 1;
+    }
+
+    let shared: HashSet<NodeIndex> = phenotype
+        .edge_references()
+        .filter(|edge| matches!(edge.weight(), Binding::Weak(_)) || parents[&edge.target()] > 1)
+        .map(|edge| edge.target())
+        .collect();
+
+    let mut visited = HashSet::new();
+    let mut result = vec![];
+
+    for output in output_nodes(phenotype) {
+        post_order(phenotype, output, &shared, &mut visited, &mut result);
+    }
+
+    result
+}
+
+fn post_order(
+    phenotype: &Phenotype,
+    node: NodeIndex,
+    shared: &HashSet<NodeIndex>,
+    visited: &mut HashSet<NodeIndex>,
+    result: &mut Vec<NodeIndex>,
+) {
+    if !visited.insert(node) {
+        return;
+    }
+
+    for argument in arguments(phenotype, node) {
+        post_order(phenotype, argument, shared, visited, result);
+    }
+
+    if shared.contains(&node) {
+        result.push(node);
+    }
+}
+
+/// Name used for references to a shared node
+pub fn shared_name(node: NodeIndex) -> String {
+    format!("v{}", node.index())
+}
+
+/// Renders a node, referring to shared nodes other than `node` itself by name
+fn render<F>(
+    phenotype: &Phenotype,
+    node: NodeIndex,
+    shared: &HashSet<NodeIndex>,
+    apply: &F,
+) -> String
+where
+    F: Fn(&str, Vec<String>) -> String,
+{
+    let arguments = arguments(phenotype, node)
+        .into_iter()
+        .map(|argument| {
+            if shared.contains(&argument) {
+                shared_name(argument)
+            } else {
+                render(phenotype, argument, shared, apply)
+            }
+        })
+        .collect();
+
+    apply(&phenotype[node].name(), arguments)
+}
+
+/// Renders a phenotype with each shared node defined once, returns definitions and the body of
+/// each output
+fn render_all<F>(phenotype: &Phenotype, apply: F) -> (Vec<(String, String)>, Vec<String>)
+where
+    F: Fn(&str, Vec<String>) -> String,
+{
+    let order = shared_nodes(phenotype);
+    let shared: HashSet<NodeIndex> = order.iter().cloned().collect();
+
+    let definitions = order
+        .iter()
+        .map(|node| {
+            (
+                shared_name(*node),
+                render(phenotype, *node, &shared, &apply),
+            )
+        })
+        .collect();
+
+    let bodies = output_nodes(phenotype)
+        .into_iter()
+        .map(|output| {
+            if shared.contains(&output) {
+                shared_name(output)
+            } else {
+                render(phenotype, output, &shared, &apply)
+            }
+        })
+        .collect();
+
+    (definitions, bodies)
+}
+
+/// Renders a phenotype as an S-expression, starting from the output node
+///
+/// Nodes bound weakly are not duplicated, they are defined once in an enclosing `let*`. Several
+/// outputs are rendered as `(values ...)`.
+pub fn sexpr(phenotype: &Phenotype) -> String {
+    let (definitions, bodies) = render_all(phenotype, |label, arguments| {
+        if arguments.is_empty() {
+            label.to_string()
+        } else {
+            format!("({} {})", label, arguments.join(" "))
+        }
+    });
+
+    let body = match bodies.len() {
+        0 => String::new(),
+        1 => bodies.join(""),
+        _ => format!("(values {})", bodies.join(" ")),
+    };
+
+    if definitions.is_empty() {
+        body
+    } else {
+        let bindings: Vec<String> = definitions
+            .iter()
+            .map(|(name, definition)| format!("({} {})", name, definition))
+            .collect();
+
+        format!("(let* ({}) {})", bindings.join(" "), body)
+    }
+}
+
+/// Renders a phenotype in infix notation, starting from the output node
+///
+/// Symbolic labels with two arguments are written as binary operators, other labels as function
+/// calls. Nodes bound weakly are defined once on their own line and referred to by name. Each
+/// output is rendered on its own line after the definitions.
+pub fn infix(phenotype: &Phenotype) -> String {
+    let (definitions, bodies) = render_all(phenotype, |label, arguments| {
+        let symbolic = !label.chars().any(char::is_alphanumeric);
+
+        match arguments.len() {
+            0 => label.to_string(),
+            2 if symbolic => format!("({} {} {})", arguments[0], label, arguments[1]),
+            _ => format!("{}({})", label, arguments.join(", ")),
+        }
+    });
+
+    let mut lines: Vec<String> = definitions
+        .iter()
+        .map(|(name, definition)| format!("{} = {}", name, definition))
+        .collect();
+    lines.extend(bodies);
+
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn negation() -> Phenotype {
+        testing::phenotype(
+            &[
+                ("out", ComponentIndex::Output(0)),
+                ("neg", ComponentIndex::Regular(0, 0)),
+                ("x", ComponentIndex::Terminal(0, 0)),
+            ],
+            &[(0, 1, Binding::Strong(0)), (1, 2, Binding::Strong(0))],
+        )
+    }
+
+    #[test]
+    fn trees_print_inline() {
+        assert_eq!(sexpr(&negation()), "(out (neg x))");
+        assert_eq!(infix(&negation()), "out(neg(x))");
+        assert!(shared_nodes(&negation()).is_empty());
+    }
+
+    #[test]
+    fn weakly_bound_nodes_are_defined_once() {
+        let phenotype = testing::shared_sum();
+
+        assert_eq!(shared_nodes(&phenotype), vec![NodeIndex::new(2)]);
+        assert_eq!(
+            sexpr(&phenotype),
+            "(let* ((v2 (+ x 1))) (out (* v2 (use x v2))))"
+        );
+        assert_eq!(infix(&phenotype), "v2 = (x + 1)\nout((v2 * use(x, v2)))");
+    }
+
+    #[test]
+    fn arguments_are_strong_then_weak() {
+        let phenotype = testing::shared_sum();
+
+        assert_eq!(
+            arguments(&phenotype, NodeIndex::new(5)),
+            vec![NodeIndex::new(6), NodeIndex::new(2)]
+        );
+        assert_eq!(output_node(&phenotype), Some(NodeIndex::new(0)));
+    }
+}
@@ -0,0 +1,426 @@
+use petgraph::graph::NodeIndex;
+use std::collections::HashMap;
+
+use crate::blueprints::{Blueprint, Blueprints};
+use crate::interpreter::InterpreterError;
+use crate::pretty::{arguments, output_nodes};
+use crate::Phenotype;
+
+/// Gate set of the built-in benchmarks
+pub const GATES: [&str; 4] = ["and", "or", "nand", "xor"];
+
+/// Bitwise function of a gate, unary gates ignore the second word
+pub type GateFunction = fn(u64, u64) -> u64;
+
+/// Arity and function of a gate label: `and or nand nor xor xnor not`
+pub fn gate(label: &str) -> Option<(usize, GateFunction)> {
+    let gate: (usize, GateFunction) = match label {
+        "and" => (2, |a, b| a & b),
+        "or" => (2, |a, b| a | b),
+        "nand" => (2, |a, b| !(a & b)),
+        "nor" => (2, |a, b| !(a | b)),
+        "xor" => (2, |a, b| a ^ b),
+        "xnor" => (2, |a, b| !(a ^ b)),
+        "not" => (1, |a, _| !a),
+        _ => return None,
+    };
+
+    Some(gate)
+}
+
+/// All input combinations and the expected outputs, packed 64 rows to a word
+///
+/// Row `r` sets input `i` to bit `i` of `r`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TruthTable {
+    pub inputs: Vec<String>,
+    pub rows: usize,
+    /// Words of each input
+    pub input_columns: Vec<Vec<u64>>,
+    /// Words of each output
+    pub target_columns: Vec<Vec<u64>>,
+}
+
+impl TruthTable {
+    /// Builds the table of `target`, which maps the input bits of a row to its output bits
+    pub fn new<T>(inputs: &[&str], outputs: usize, target: T) -> TruthTable
+    where
+        T: Fn(&[bool]) -> Vec<bool>,
+    {
+        let rows: usize = 1 << inputs.len();
+        let words = rows.div_ceil(64);
+        let mut input_columns = vec![vec![0; words]; inputs.len()];
+        let mut target_columns = vec![vec![0; words]; outputs];
+
+        for row in 0..rows {
+            let bits: Vec<bool> = (0..inputs.len()).map(|i| (row >> i) & 1 == 1).collect();
+
+            for (i, bit) in bits.iter().enumerate() {
+                input_columns[i][row / 64] |= (*bit as u64) << (row % 64);
+            }
+
+            for (o, bit) in target(&bits).into_iter().take(outputs).enumerate() {
+                target_columns[o][row / 64] |= (bit as u64) << (row % 64);
+            }
+        }
+
+        TruthTable {
+            inputs: inputs.iter().map(|input| String::from(*input)).collect(),
+            rows,
+            input_columns,
+            target_columns,
+        }
+    }
+
+    pub fn outputs(&self) -> usize {
+        self.target_columns.len()
+    }
+
+    /// Mask of the rows present in a word
+    fn mask(&self, word: usize) -> u64 {
+        let used = self.rows - word * 64;
+        if used >= 64 {
+            !0
+        } else {
+            (1 << used) - 1
+        }
+    }
+}
+
+/// Values of the outputs of a circuit on every row of the table
+///
+/// Each output node passes its first argument through.
+pub fn evaluate(
+    phenotype: &Phenotype,
+    table: &TruthTable,
+) -> Result<Vec<Vec<u64>>, InterpreterError> {
+    let mut values = HashMap::new();
+
+    output_nodes(phenotype)
+        .into_iter()
+        .take(table.outputs())
+        .map(|output| {
+            let node = *arguments(phenotype, output)
+                .first()
+                .ok_or(InterpreterError::NoOutput)?;
+            value(phenotype, node, table, &mut values)
+        })
+        .collect()
+}
+
+fn value(
+    phenotype: &Phenotype,
+    node: NodeIndex,
+    table: &TruthTable,
+    values: &mut HashMap<NodeIndex, Option<Vec<u64>>>,
+) -> Result<Vec<u64>, InterpreterError> {
+    match values.get(&node) {
+        Some(Some(value)) => return Ok(value.clone()),
+        Some(None) => return Err(InterpreterError::Cycle(node)),
+        None => {}
+    }
+    values.insert(node, None);
+
+    let label = &phenotype[node].label;
+
+    let value = if let Some(input) = table.inputs.iter().position(|input| input == label) {
+        table.input_columns[input].clone()
+    } else if let Some((arity, function)) = gate(label) {
+        let arguments = arguments(phenotype, node);
+        if arguments.len() < arity {
+            return Err(InterpreterError::MissingArgument(
+                label.clone(),
+                arguments.len(),
+            ));
+        }
+
+        let a = value(phenotype, arguments[0], table, values)?;
+        let b = if arity > 1 {
+            value(phenotype, arguments[1], table, values)?
+        } else {
+            vec![0; a.len()]
+        };
+
+        a.iter().zip(&b).map(|(a, b)| function(*a, *b)).collect()
+    } else {
+        match label.as_str() {
+            "0" => vec![0; table.input_columns.first().map_or(1, |c| c.len())],
+            "1" => vec![!0; table.input_columns.first().map_or(1, |c| c.len())],
+            _ => return Err(InterpreterError::UnknownLabel(label.clone())),
+        }
+    };
+
+    values.insert(node, Some(value.clone()));
+    Ok(value)
+}
+
+/// Blueprints of a single group of gates and input terminals, with one output per circuit output
+///
+/// Panics if a gate label isn't known to `gate`.
+pub fn circuit_blueprints(gates: &[&str], inputs: &[&str], outputs: usize) -> Blueprints {
+    let gates = gates
+        .iter()
+        .map(|label| {
+            let (arity, _) = gate(label).unwrap_or_else(|| panic!("{} is not a logic gate", label));
+            if arity == 2 {
+                Blueprint::double_main(label)
+            } else {
+                Blueprint::single_main(label)
+            }
+        })
+        .collect();
+
+    let mut regular = vec![gates];
+    let mut terminal = vec![Blueprint::terminals(inputs)];
+    Blueprints::recompute_activities(&mut regular, &mut terminal);
+
+    let outputs = (0..outputs)
+        .map(|output| Blueprint::single_main(&format!("out{}", output)))
+        .collect();
+
+    Blueprints::with_outputs(outputs, regular, terminal, HashMap::new())
+}
+
+/// A digital circuit problem scored over its full truth table
+#[derive(Debug, Clone)]
+pub struct BooleanProblem {
+    pub name: String,
+    pub blueprints: Blueprints,
+    pub table: TruthTable,
+}
+
+impl BooleanProblem {
+    pub fn new(name: &str, gates: &[&str], table: TruthTable) -> BooleanProblem {
+        let inputs: Vec<&str> = table.inputs.iter().map(|input| input.as_str()).collect();
+
+        BooleanProblem {
+            name: String::from(name),
+            blueprints: circuit_blueprints(gates, &inputs, table.outputs()),
+            table,
+        }
+    }
+
+    /// Correct output bits over all rows, missing outputs count as wrong
+    pub fn hits(&self, phenotype: &Phenotype) -> usize {
+        let outputs = match evaluate(phenotype, &self.table) {
+            Ok(outputs) => outputs,
+            Err(_) => return 0,
+        };
+
+        outputs
+            .iter()
+            .zip(&self.table.target_columns)
+            .map(|(output, target)| {
+                output
+                    .iter()
+                    .zip(target)
+                    .enumerate()
+                    .map(|(word, (output, target))| {
+                        (!(output ^ target) & self.table.mask(word)).count_ones() as usize
+                    })
+                    .sum::<usize>()
+            })
+            .sum()
+    }
+
+    /// Number of output bits over all rows
+    pub fn max_hits(&self) -> usize {
+        self.table.rows * self.table.outputs()
+    }
+
+    pub fn solved(&self, phenotype: &Phenotype) -> bool {
+        self.hits(phenotype) == self.max_hits()
+    }
+
+    /// Fraction of correct output bits, 1 for a solution
+    pub fn fitness(&self) -> impl Fn(&Phenotype) -> f64 + Clone + '_ {
+        move |phenotype| self.hits(phenotype) as f64 / self.max_hits() as f64
+    }
+}
+
+fn number(bits: &[bool]) -> usize {
+    bits.iter()
+        .enumerate()
+        .map(|(i, bit)| (*bit as usize) << i)
+        .sum()
+}
+
+fn bits(number: usize, count: usize) -> Vec<bool> {
+    (0..count).map(|i| (number >> i) & 1 == 1).collect()
+}
+
+fn names(prefix: &str, count: usize) -> Vec<String> {
+    (0..count).map(|i| format!("{}{}", prefix, i)).collect()
+}
+
+/// Standard digital circuit benchmarks, all built with `GATES`
+///
+/// Numbers are little endian: input and output 0 are the least significant bits.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Benchmark {
+    /// True when an even number of the n inputs are set
+    EvenParity(usize),
+    /// `k` address bits select one of `2^k` data bits: 2 for the 6-multiplexer, 3 for the 11
+    Multiplexer(usize),
+    /// Sum of two n-bit numbers, n + 1 outputs
+    Adder(usize),
+    /// Product of two n-bit numbers, 2n outputs
+    Multiplier(usize),
+}
+
+impl Benchmark {
+    pub fn name(self) -> String {
+        match self {
+            Benchmark::EvenParity(n) => format!("even-{}-parity", n),
+            Benchmark::Multiplexer(k) => format!("{}-multiplexer", k + (1 << k)),
+            Benchmark::Adder(n) => format!("{}-bit-adder", n),
+            Benchmark::Multiplier(n) => format!("{}-bit-multiplier", n),
+        }
+    }
+
+    pub fn problem(self) -> BooleanProblem {
+        self.problem_with_gates(&GATES)
+    }
+
+    pub fn problem_with_gates(self, gates: &[&str]) -> BooleanProblem {
+        let table = match self {
+            Benchmark::EvenParity(n) => {
+                let inputs = names("d", n);
+                let inputs: Vec<&str> = inputs.iter().map(|s| s.as_str()).collect();
+                TruthTable::new(&inputs, 1, |bits| {
+                    vec![bits.iter().filter(|bit| **bit).count() % 2 == 0]
+                })
+            }
+            Benchmark::Multiplexer(k) => {
+                let mut inputs = names("a", k);
+                inputs.extend(names("d", 1 << k));
+                let inputs: Vec<&str> = inputs.iter().map(|s| s.as_str()).collect();
+                TruthTable::new(&inputs, 1, |bits| vec![bits[k + number(&bits[..k])]])
+            }
+            Benchmark::Adder(n) | Benchmark::Multiplier(n) => {
+                let mut inputs = names("a", n);
+                inputs.extend(names("b", n));
+                let inputs: Vec<&str> = inputs.iter().map(|s| s.as_str()).collect();
+
+                let adder = matches!(self, Benchmark::Adder(_));
+                let outputs = if adder { n + 1 } else { 2 * n };
+
+                TruthTable::new(&inputs, outputs, |bits| {
+                    let a = number(&bits[..n]);
+                    let b = number(&bits[n..]);
+                    let result = if adder { a + b } else { a * b };
+                    self::bits(result, outputs)
+                })
+            }
+        };
+
+        BooleanProblem::new(&self.name(), gates, table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::{Binding, ComponentIndex};
+
+    const GATE: ComponentIndex = ComponentIndex::Regular(0, 0);
+    const INPUT: ComponentIndex = ComponentIndex::Terminal(0, 0);
+
+    /// `(nand (xor d0 d1) (xor d0 d1))` with the `xor` shared, the even-2-parity
+    fn even_parity() -> Phenotype {
+        testing::phenotype(
+            &[
+                ("out0", ComponentIndex::Output(0)),
+                ("nand", GATE),
+                ("xor", GATE),
+                ("d0", INPUT),
+                ("d1", INPUT),
+            ],
+            &[
+                (0, 1, Binding::Strong(0)),
+                (1, 2, Binding::Strong(0)),
+                (1, 2, Binding::Strong(1)),
+                (2, 3, Binding::Strong(0)),
+                (2, 4, Binding::Strong(1)),
+            ],
+        )
+    }
+
+    #[test]
+    fn rows_are_packed_into_words() {
+        let inputs = names("d", 7);
+        let inputs: Vec<&str> = inputs.iter().map(|s| s.as_str()).collect();
+        let table = TruthTable::new(&inputs, 1, |bits| vec![bits[6]]);
+
+        assert_eq!(table.rows, 128);
+        assert_eq!(table.input_columns[0], vec![0xaaaa_aaaa_aaaa_aaaa; 2]);
+        assert_eq!(table.input_columns[6], vec![0, !0]);
+        assert_eq!(table.target_columns, vec![vec![0, !0]]);
+        assert_eq!(table.mask(1), !0);
+
+        let small = TruthTable::new(&["a", "b"], 1, |bits| vec![bits[0] && bits[1]]);
+        assert_eq!(small.target_columns, vec![vec![0b1000]]);
+        assert_eq!(small.mask(0), 0b1111);
+    }
+
+    #[test]
+    fn circuits_are_scored_on_every_row() {
+        let problem = Benchmark::EvenParity(2).problem();
+        assert_eq!(problem.max_hits(), 4);
+        assert!(problem.solved(&even_parity()));
+        assert_eq!(problem.fitness()(&even_parity()), 1.);
+
+        // the xor alone is wrong on every row
+        let odd = testing::phenotype(
+            &[
+                ("out0", ComponentIndex::Output(0)),
+                ("xor", GATE),
+                ("d0", INPUT),
+                ("d1", INPUT),
+            ],
+            &[
+                (0, 1, Binding::Strong(0)),
+                (1, 2, Binding::Strong(0)),
+                (1, 3, Binding::Strong(1)),
+            ],
+        );
+        assert_eq!(problem.hits(&odd), 0);
+
+        let unknown = testing::phenotype(
+            &[("out0", ComponentIndex::Output(0)), ("d9", INPUT)],
+            &[(0, 1, Binding::Strong(0))],
+        );
+        assert_eq!(
+            evaluate(&unknown, &problem.table),
+            Err(InterpreterError::UnknownLabel(String::from("d9")))
+        );
+        assert_eq!(problem.hits(&unknown), 0);
+    }
+
+    #[test]
+    fn benchmarks_have_their_standard_sizes() {
+        let multiplexer = Benchmark::Multiplexer(2).problem();
+        assert_eq!(multiplexer.name, "6-multiplexer");
+        assert_eq!(multiplexer.table.rows, 64);
+        // address 0b10 selects d2
+        let row = 0b10 | (1 << (2 + 2));
+        assert_eq!(multiplexer.table.target_columns[0][0] >> row & 1, 1);
+
+        let adder = Benchmark::Adder(2).problem();
+        assert_eq!(adder.table.outputs(), 3);
+        assert_eq!(adder.max_hits(), 16 * 3);
+        assert_eq!(adder.blueprints.outputs.len(), 3);
+        // 3 + 3 = 6
+        let row = 0b11_11;
+        let sum: Vec<u64> = adder
+            .table
+            .target_columns
+            .iter()
+            .map(|column| column[0] >> row & 1)
+            .collect();
+        assert_eq!(sum, vec![0, 1, 1]);
+
+        assert_eq!(Benchmark::Multiplier(3).problem().table.outputs(), 6);
+    }
+}
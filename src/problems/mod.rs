@@ -0,0 +1,2 @@
+pub mod boolean;
+pub mod regression;
@@ -0,0 +1,599 @@
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::io::{self, BufRead};
+
+use crate::blueprints::{Blueprint, Blueprints, Ephemeral};
+use crate::interpreter::{Operation, Program};
+use crate::Phenotype;
+
+/// Seed of the data sampled by `Benchmark::problem`
+pub const BENCHMARK_SEED: u64 = 0;
+
+/// Fitness of programs that can't be evaluated or whose error isn't finite
+///
+/// Finite, so arithmetic on fitness values such as sharing within species stays defined.
+pub const INVALID_FITNESS: f64 = -1e100;
+
+/// Input rows and target values of a regression problem
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dataset {
+    pub variables: Vec<String>,
+    pub inputs: Vec<Vec<f64>>,
+    pub targets: Vec<f64>,
+}
+
+impl Dataset {
+    pub fn new(variables: &[&str], inputs: Vec<Vec<f64>>, targets: Vec<f64>) -> Dataset {
+        Dataset {
+            variables: variables.iter().map(|v| String::from(*v)).collect(),
+            inputs,
+            targets,
+        }
+    }
+
+    /// Targets computed from the inputs
+    pub fn from_fn<T>(variables: &[&str], inputs: Vec<Vec<f64>>, target: T) -> Dataset
+    where
+        T: Fn(&[f64]) -> f64,
+    {
+        let targets = inputs.iter().map(|input| target(input)).collect();
+        Dataset::new(variables, inputs, targets)
+    }
+
+    /// `count` points, each variable drawn uniformly from its `(name, min, max)` range
+    pub fn uniform<R, T>(
+        rng: &mut R,
+        variables: &[(&str, f64, f64)],
+        count: usize,
+        target: T,
+    ) -> Dataset
+    where
+        R: Rng + ?Sized,
+        T: Fn(&[f64]) -> f64,
+    {
+        let inputs = (0..count)
+            .map(|_| {
+                variables
+                    .iter()
+                    .map(|(_name, min, max)| rng.gen_range(*min, *max))
+                    .collect()
+            })
+            .collect();
+        let names: Vec<&str> = variables.iter().map(|(name, _, _)| *name).collect();
+
+        Dataset::from_fn(&names, inputs, target)
+    }
+
+    /// All combinations of evenly spaced values, `(name, min, max, step)` with `max` included
+    ///
+    /// Panics if a range isn't finite, `max` is less than `min` or `step` isn't positive.
+    pub fn grid<T>(variables: &[(&str, f64, f64, f64)], target: T) -> Dataset
+    where
+        T: Fn(&[f64]) -> f64,
+    {
+        let mut inputs: Vec<Vec<f64>> = vec![vec![]];
+
+        for (name, min, max, step) in variables {
+            assert!(
+                min.is_finite() && max.is_finite() && min <= max,
+                "invalid range of {}",
+                name
+            );
+            assert!(step.is_finite() && *step > 0., "invalid step of {}", name);
+
+            let count = ((max - min) / step).round() as usize + 1;
+            inputs = inputs
+                .into_iter()
+                .flat_map(|input| {
+                    (0..count).map(move |i| {
+                        let mut input = input.clone();
+                        input.push(min + i as f64 * step);
+                        input
+                    })
+                })
+                .collect();
+        }
+
+        let names: Vec<&str> = variables.iter().map(|(name, _, _, _)| *name).collect();
+        Dataset::from_fn(&names, inputs, target)
+    }
+
+    /// Reads comma separated values with a header of variable names, the last column the target
+    pub fn read_csv<R: BufRead>(reader: R) -> io::Result<Dataset> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let mut lines = reader.lines();
+        let header = lines
+            .next()
+            .ok_or_else(|| invalid(String::from("missing header")))??;
+        let mut columns: Vec<String> = header.split(',').map(|c| c.trim().to_string()).collect();
+        if columns.len() < 2 {
+            return Err(invalid(String::from(
+                "expected variables and a target column",
+            )));
+        }
+        columns.pop();
+
+        let mut inputs = vec![];
+        let mut targets = vec![];
+
+        for (number, line) in lines.enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let mut values = line
+                .split(',')
+                .map(|value| value.trim().parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .map_err(|error| invalid(format!("line {}: {}", number + 2, error)))?;
+
+            if values.len() != columns.len() + 1 {
+                return Err(invalid(format!(
+                    "line {}: wrong number of values",
+                    number + 2
+                )));
+            }
+
+            targets.push(values.pop().unwrap());
+            inputs.push(values);
+        }
+
+        Ok(Dataset {
+            variables: columns,
+            inputs,
+            targets,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.targets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+}
+
+/// Blueprints of a single group with the given interpreter operations and terminals
+///
+/// Panics if a function label isn't an `Operation`.
+pub fn arithmetic_blueprints(functions: &[&str], terminals: &[&str]) -> Blueprints {
+    arithmetic_blueprints_with_ephemeral(functions, terminals, None)
+}
+
+/// Like `arithmetic_blueprints`, with an ephemeral random constant among the regulars
+pub fn arithmetic_blueprints_with_ephemeral(
+    functions: &[&str],
+    terminals: &[&str],
+    ephemeral: Option<Ephemeral>,
+) -> Blueprints {
+    let mut functions: Vec<Blueprint> = functions
+        .iter()
+        .map(|label| {
+            let operation = Operation::from_label(label)
+                .unwrap_or_else(|| panic!("{} is not an arithmetic operation", label));
+
+            Blueprint {
+                activity: 0,
+                label: String::from(*label),
+                binding_sites: vec![0; operation.arity()],
+                weak_binding_sites: vec![],
+                ephemeral: None,
+            }
+        })
+        .collect();
+
+    if let Some(Ephemeral { min, max, sigma }) = ephemeral {
+        functions.push(Blueprint::ephemeral("erc", min, max, sigma));
+    }
+
+    let mut regular = vec![functions];
+    let mut terminal = vec![Blueprint::terminals(terminals)];
+    Blueprints::recompute_activities(&mut regular, &mut terminal);
+
+    Blueprints::from_groups(
+        Blueprint::single_main("out"),
+        regular,
+        terminal,
+        HashMap::new(),
+    )
+}
+
+/// A symbolic regression problem over the arithmetic interpreter
+#[derive(Debug, Clone)]
+pub struct RegressionProblem {
+    pub name: String,
+    pub blueprints: Blueprints,
+    pub training: Dataset,
+    /// Held out data for measuring generalization, never used by `fitness`
+    pub test: Option<Dataset>,
+}
+
+impl RegressionProblem {
+    /// A problem whose terminals are the training variables and `constants`
+    pub fn new(
+        name: &str,
+        functions: &[&str],
+        constants: &[&str],
+        training: Dataset,
+        test: Option<Dataset>,
+    ) -> RegressionProblem {
+        RegressionProblem::with_ephemeral(name, functions, constants, None, training, test)
+    }
+
+    /// Like `new`, optionally evolving ephemeral random constants alongside the fixed ones
+    pub fn with_ephemeral(
+        name: &str,
+        functions: &[&str],
+        constants: &[&str],
+        ephemeral: Option<Ephemeral>,
+        training: Dataset,
+        test: Option<Dataset>,
+    ) -> RegressionProblem {
+        let terminals: Vec<&str> = training
+            .variables
+            .iter()
+            .map(|variable| variable.as_str())
+            .chain(constants.iter().cloned())
+            .collect();
+
+        RegressionProblem {
+            name: String::from(name),
+            blueprints: arithmetic_blueprints_with_ephemeral(functions, &terminals, ephemeral),
+            training,
+            test,
+        }
+    }
+
+    fn program(&self, phenotype: &Phenotype) -> Option<Program> {
+        let variables: Vec<&str> = self.training.variables.iter().map(|v| v.as_str()).collect();
+        Program::compile(phenotype, &variables).ok()
+    }
+
+    /// Root mean squared error on a dataset, `None` if the phenotype can't be evaluated
+    pub fn error(&self, phenotype: &Phenotype, dataset: &Dataset) -> Option<f64> {
+        let program = self.program(phenotype)?;
+
+        let squared: f64 = dataset
+            .inputs
+            .iter()
+            .zip(&dataset.targets)
+            .map(|(input, target)| (program.evaluate(input) - target).powi(2))
+            .sum();
+
+        Some((squared / dataset.len().max(1) as f64).sqrt())
+    }
+
+    pub fn test_error(&self, phenotype: &Phenotype) -> Option<f64> {
+        self.test
+            .as_ref()
+            .and_then(|test| self.error(phenotype, test))
+    }
+
+    /// Training points where the program is within `tolerance` of the target
+    pub fn hits(&self, phenotype: &Phenotype, tolerance: f64) -> usize {
+        match self.program(phenotype) {
+            Some(program) => self
+                .training
+                .inputs
+                .iter()
+                .zip(&self.training.targets)
+                .filter(|(input, target)| (program.evaluate(input) - *target).abs() <= tolerance)
+                .count(),
+            None => 0,
+        }
+    }
+
+    /// Negated training error, `INVALID_FITNESS` when it isn't defined
+    pub fn fitness(&self) -> impl Fn(&Phenotype) -> f64 + Clone + '_ {
+        move |phenotype| match self.error(phenotype, &self.training) {
+            Some(error) if error.is_finite() => -error,
+            _ => INVALID_FITNESS,
+        }
+    }
+}
+
+const KOZA_FUNCTIONS: [&str; 8] = ["+", "-", "*", "/", "sin", "cos", "exp", "log"];
+const KEIJZER_FUNCTIONS: [&str; 5] = ["+", "*", "inv", "neg", "sqrt"];
+const KEIJZER_EPHEMERAL: Ephemeral = Ephemeral {
+    min: -5.,
+    max: 5.,
+    sigma: 1.,
+};
+
+/// Standard regression benchmarks, see McDermott et al., "Genetic programming needs better
+/// benchmarks" (2012)
+///
+/// Koza and Nguyen problems use the Koza function set without constants. Keijzer problems use
+/// `+ * inv neg sqrt` with ephemeral random constants drawn from [-5, 5].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Benchmark {
+    /// x^4 + x^3 + x^2 + x
+    KozaQuartic,
+    /// x^6 - 2x^4 + x^2
+    KozaSextic,
+    /// Nguyen-1 to Nguyen-12
+    Nguyen(usize),
+    /// Keijzer-1 to Keijzer-15
+    Keijzer(usize),
+}
+
+impl Benchmark {
+    pub fn all() -> Vec<Benchmark> {
+        let mut all = vec![Benchmark::KozaQuartic, Benchmark::KozaSextic];
+        all.extend((1..=12).map(Benchmark::Nguyen));
+        all.extend((1..=15).map(Benchmark::Keijzer));
+        all
+    }
+
+    pub fn name(self) -> String {
+        match self {
+            Benchmark::KozaQuartic => String::from("koza-quartic"),
+            Benchmark::KozaSextic => String::from("koza-sextic"),
+            Benchmark::Nguyen(n) => format!("nguyen-{}", n),
+            Benchmark::Keijzer(n) => format!("keijzer-{}", n),
+        }
+    }
+
+    /// The benchmark's training and test data, sampled from `BENCHMARK_SEED`
+    ///
+    /// Every call returns the same data, so results of separate runs can be compared.
+    pub fn problem(self) -> RegressionProblem {
+        self.problem_with_rng(&mut Pcg32::seed_from_u64(BENCHMARK_SEED))
+    }
+
+    /// Samples the benchmark's training and test data from `rng`
+    ///
+    /// Panics if the number of a Nguyen or Keijzer benchmark is out of range.
+    pub fn problem_with_rng<R: Rng + ?Sized>(self, rng: &mut R) -> RegressionProblem {
+        let name = self.name();
+
+        match self {
+            Benchmark::KozaQuartic => koza(rng, &name, |x| x.powi(4) + x.powi(3) + x * x + x),
+            Benchmark::KozaSextic => koza(rng, &name, |x| x.powi(6) - 2. * x.powi(4) + x * x),
+            Benchmark::Nguyen(n) => nguyen(rng, &name, n),
+            Benchmark::Keijzer(n) => keijzer(rng, &name, n),
+        }
+    }
+}
+
+fn koza<R, T>(rng: &mut R, name: &str, target: T) -> RegressionProblem
+where
+    R: Rng + ?Sized,
+    T: Fn(f64) -> f64,
+{
+    let training = Dataset::uniform(rng, &[("x", -1., 1.)], 20, |v| target(v[0]));
+    RegressionProblem::new(name, &KOZA_FUNCTIONS, &[], training, None)
+}
+
+fn nguyen<R: Rng + ?Sized>(rng: &mut R, name: &str, n: usize) -> RegressionProblem {
+    let x = [("x", -1., 1.)];
+    let xy = [("x", -1., 1.), ("y", -1., 1.)];
+
+    let training = match n {
+        1 => Dataset::uniform(rng, &x, 20, |v| v[0].powi(3) + v[0].powi(2) + v[0]),
+        2 => Dataset::uniform(rng, &x, 20, |v| (1..=4).map(|p| v[0].powi(p)).sum()),
+        3 => Dataset::uniform(rng, &x, 20, |v| (1..=5).map(|p| v[0].powi(p)).sum()),
+        4 => Dataset::uniform(rng, &x, 20, |v| (1..=6).map(|p| v[0].powi(p)).sum()),
+        5 => Dataset::uniform(rng, &x, 20, |v| (v[0] * v[0]).sin() * v[0].cos() - 1.),
+        6 => Dataset::uniform(rng, &x, 20, |v| v[0].sin() + (v[0] + v[0] * v[0]).sin()),
+        7 => Dataset::uniform(rng, &[("x", 0., 2.)], 20, |v| {
+            (v[0] + 1.).ln() + (v[0] * v[0] + 1.).ln()
+        }),
+        8 => Dataset::uniform(rng, &[("x", 0., 4.)], 20, |v| v[0].sqrt()),
+        9 => Dataset::uniform(rng, &xy, 100, |v| v[0].sin() + (v[1] * v[1]).sin()),
+        10 => Dataset::uniform(rng, &xy, 100, |v| 2. * v[0].sin() * v[1].cos()),
+        11 => Dataset::uniform(rng, &[("x", 0., 1.), ("y", 0., 1.)], 100, |v| {
+            v[0].powf(v[1])
+        }),
+        12 => Dataset::uniform(rng, &xy, 100, |v| {
+            v[0].powi(4) - v[0].powi(3) + v[1] * v[1] / 2. - v[1]
+        }),
+        _ => panic!("there is no Nguyen-{}", n),
+    };
+
+    RegressionProblem::new(name, &KOZA_FUNCTIONS, &[], training, None)
+}
+
+fn keijzer<R: Rng + ?Sized>(rng: &mut R, name: &str, n: usize) -> RegressionProblem {
+    let sine = |v: &[f64]| 0.3 * v[0] * (2. * PI * v[0]).sin();
+    let bivariate: Option<fn(&[f64]) -> f64> = match n {
+        11 => Some(|v| v[0] * v[1] + ((v[0] - 1.) * (v[1] - 1.)).sin()),
+        12 => Some(|v| v[0].powi(4) - v[0].powi(3) + v[1] * v[1] / 2. - v[1]),
+        13 => Some(|v| 6. * v[0].sin() * v[1].cos()),
+        14 => Some(|v| 8. / (2. + v[0] * v[0] + v[1] * v[1])),
+        15 => Some(|v| v[0].powi(3) / 5. + v[1].powi(3) / 2. - v[1] - v[0]),
+        _ => None,
+    };
+
+    let (training, test) = match n {
+        1..=3 => {
+            let range = n as f64;
+            (
+                Dataset::grid(&[("x", -range, range, 0.1)], sine),
+                Dataset::grid(&[("x", -range, range, 0.001)], sine),
+            )
+        }
+        4 => {
+            let target = |v: &[f64]| {
+                let x = v[0];
+                x.powi(3) * (-x).exp() * x.cos() * x.sin() * (x.sin().powi(2) * x.cos() - 1.)
+            };
+            (
+                Dataset::grid(&[("x", 0., 10., 0.05)], target),
+                Dataset::grid(&[("x", 0.05, 10.05, 0.05)], target),
+            )
+        }
+        5 => {
+            let ranges = [("x", -1., 1.), ("y", 1., 2.), ("z", -1., 1.)];
+            let target = |v: &[f64]| 30. * v[0] * v[2] / ((v[0] - 10.) * v[1] * v[1]);
+            (
+                Dataset::uniform(rng, &ranges, 1000, target),
+                Dataset::uniform(rng, &ranges, 10000, target),
+            )
+        }
+        6 => {
+            let target = |v: &[f64]| (1..=v[0].round() as usize).map(|i| 1. / i as f64).sum();
+            (
+                Dataset::grid(&[("x", 1., 50., 1.)], target),
+                Dataset::grid(&[("x", 1., 120., 1.)], target),
+            )
+        }
+        7..=9 => {
+            let target = match n {
+                7 => |v: &[f64]| v[0].ln(),
+                8 => |v: &[f64]| v[0].sqrt(),
+                _ => |v: &[f64]| v[0].asinh(),
+            };
+            let min = if n == 7 { 1. } else { 0. };
+            (
+                Dataset::grid(&[("x", min, 100., 1.)], target),
+                Dataset::grid(&[("x", min, 100., 0.1)], target),
+            )
+        }
+        10 => {
+            let target = |v: &[f64]| v[0].powf(v[1]);
+            (
+                Dataset::uniform(rng, &[("x", 0., 1.), ("y", 0., 1.)], 100, target),
+                Dataset::grid(&[("x", 0., 1., 0.01), ("y", 0., 1., 0.01)], target),
+            )
+        }
+        11..=15 => {
+            let target = bivariate.unwrap();
+            (
+                Dataset::uniform(rng, &[("x", -3., 3.), ("y", -3., 3.)], 20, target),
+                Dataset::grid(&[("x", -3., 3., 0.01), ("y", -3., 3., 0.01)], target),
+            )
+        }
+        _ => panic!("there is no Keijzer-{}", n),
+    };
+
+    RegressionProblem::with_ephemeral(
+        name,
+        &KEIJZER_FUNCTIONS,
+        &[],
+        Some(KEIJZER_EPHEMERAL),
+        training,
+        Some(test),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::{Binding, ComponentIndex};
+
+    fn successor() -> RegressionProblem {
+        let training = Dataset::grid(&[("x", -1., 1., 0.5)], |v| v[0] + 1.);
+        RegressionProblem::new("successor", &["+", "*"], &["1"], training, None)
+    }
+
+    fn sum(terminal: &str) -> Phenotype {
+        testing::phenotype(
+            &[
+                ("out", ComponentIndex::Output(0)),
+                ("+", ComponentIndex::Regular(0, 0)),
+                ("x", ComponentIndex::Terminal(0, 0)),
+                (terminal, ComponentIndex::Terminal(0, 1)),
+            ],
+            &[
+                (0, 1, Binding::Strong(0)),
+                (1, 2, Binding::Strong(0)),
+                (1, 3, Binding::Strong(1)),
+            ],
+        )
+    }
+
+    #[test]
+    fn grids_include_both_ends() {
+        let dataset = Dataset::grid(&[("x", 0., 1., 0.25), ("y", -1., 1., 1.)], |v| v[0] * v[1]);
+
+        assert_eq!(dataset.variables, vec!["x", "y"]);
+        assert_eq!(dataset.len(), 5 * 3);
+        assert_eq!(dataset.inputs[0], vec![0., -1.]);
+        assert_eq!(dataset.inputs[14], vec![1., 1.]);
+        assert_eq!(dataset.targets[14], 1.);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid step of x")]
+    fn grid_steps_must_be_positive() {
+        Dataset::grid(&[("x", 0., 1., 0.)], |v| v[0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid range of y")]
+    fn grid_ranges_must_not_be_reversed() {
+        Dataset::grid(&[("x", 0., 1., 0.5), ("y", 1., -1., 0.5)], |v| v[0]);
+    }
+
+    #[test]
+    fn csv_has_the_target_last() {
+        let csv = "x, y, f\n1, 2, 3\n\n4, 5, 6\n";
+        let dataset = Dataset::read_csv(csv.as_bytes()).unwrap();
+
+        assert_eq!(dataset.variables, vec!["x", "y"]);
+        assert_eq!(dataset.inputs, vec![vec![1., 2.], vec![4., 5.]]);
+        assert_eq!(dataset.targets, vec![3., 6.]);
+
+        assert!(Dataset::read_csv("f\n1\n".as_bytes()).is_err());
+        assert!(Dataset::read_csv("x,f\n1,2,3\n".as_bytes()).is_err());
+        assert!(Dataset::read_csv("x,f\n1,a\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn fitness_is_the_negated_error() {
+        let problem = successor();
+        let fitness = problem.fitness();
+
+        assert_eq!(fitness(&sum("1")), 0.);
+        assert_eq!(problem.hits(&sum("1"), 1e-9), 5);
+
+        // x + x misses by |x - 1|
+        assert_eq!(
+            problem.error(&sum("x"), &problem.training),
+            Some(1.5f64.sqrt())
+        );
+        assert_eq!(problem.hits(&sum("x"), 1e-9), 1);
+
+        assert_eq!(fitness(&sum("c")), INVALID_FITNESS);
+        assert_eq!(problem.error(&sum("c"), &problem.training), None);
+        assert_eq!(problem.test_error(&sum("1")), None);
+    }
+
+    #[test]
+    fn every_benchmark_has_data() {
+        let mut rng = testing::rng(0);
+
+        for benchmark in Benchmark::all() {
+            let problem = benchmark.problem_with_rng(&mut rng);
+
+            assert_eq!(problem.name, benchmark.name());
+            assert!(!problem.training.is_empty());
+            assert!(problem.training.targets.iter().all(|t| t.is_finite()));
+            assert_eq!(
+                problem.blueprints.terminal[0].len(),
+                problem.training.variables.len()
+            );
+        }
+    }
+
+    #[test]
+    fn benchmark_data_is_fixed() {
+        for benchmark in Benchmark::all() {
+            let (a, b) = (benchmark.problem(), benchmark.problem());
+
+            assert_eq!(a.training, b.training);
+            assert_eq!(a.test, b.test);
+        }
+
+        let seeded = |seed| Benchmark::KozaQuartic.problem_with_rng(&mut testing::rng(seed));
+        assert_eq!(seeded(3).training, seeded(3).training);
+        assert_ne!(seeded(3).training, seeded(4).training);
+    }
+}
@@ -0,0 +1,140 @@
+use petgraph::visit::EdgeRef;
+use std::fmt::Write;
+
+use crate::{Binding, ComponentIndex, Phenotype};
+
+/// Options for rendering phenotypes
+pub struct DotOptions {
+    /// Include the group and chromosome index of each component in node labels
+    pub indices: bool,
+    pub output_color: String,
+    pub regular_color: String,
+    pub terminal_color: String,
+}
+
+impl Default for DotOptions {
+    fn default() -> DotOptions {
+        DotOptions {
+            indices: false,
+            output_color: String::from("lightblue"),
+            regular_color: String::from("white"),
+            terminal_color: String::from("palegreen"),
+        }
+    }
+}
+
+/// Renders a phenotype in the Graphviz DOT format with default options
+pub fn dot(phenotype: &Phenotype) -> String {
+    dot_with(phenotype, &DotOptions::default())
+}
+
+/// Renders a phenotype in the Graphviz DOT format
+///
+/// Strong bindings are solid edges and weak bindings dashed, both labeled with their binding site
+/// index. The output can be passed to `dot -Tsvg` as is.
+pub fn dot_with(phenotype: &Phenotype, options: &DotOptions) -> String {
+    let mut result = String::new();
+
+    writeln!(result, "digraph phenotype {{").unwrap();
+    writeln!(result, "    node [style=filled, fontname=\"Helvetica\"];").unwrap();
+    writeln!(result, "    edge [fontname=\"Helvetica\", fontsize=10];").unwrap();
+
+    for node in phenotype.node_indices() {
+        let expressed = &phenotype[node];
+
+        let (color, shape, index) = match expressed.index {
+            ComponentIndex::Output(index) => (
+                &options.output_color,
+                "doubleoctagon",
+                format!("\\noutput {}", index),
+            ),
+            ComponentIndex::Regular(group, index) => (
+                &options.regular_color,
+                "ellipse",
+                format!("\\ngroup {}, index {}", group, index),
+            ),
+            ComponentIndex::Terminal(group, index) => (
+                &options.terminal_color,
+                "box",
+                format!("\\ngroup {}, terminal {}", group, index),
+            ),
+        };
+
+        let index = if options.indices {
+            index
+        } else {
+            String::new()
+        };
+
+        writeln!(
+            result,
+            "    {} [label=\"{}{}\", shape={}, fillcolor=\"{}\"];",
+            node.index(),
+            escape(&expressed.name()),
+            index,
+            shape,
+            escape(color)
+        )
+        .unwrap();
+    }
+
+    for edge in phenotype.edge_references() {
+        let (index, style) = match *edge.weight() {
+            Binding::Strong(index) => (index, "solid"),
+            Binding::Weak(index) => (index, "dashed"),
+        };
+
+        writeln!(
+            result,
+            "    {} -> {} [label=\"{}\", style={}];",
+            edge.source().index(),
+            edge.target().index(),
+            index,
+            style
+        )
+        .unwrap();
+    }
+
+    writeln!(result, "}}").unwrap();
+
+    result
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn nodes_and_edges_are_rendered() {
+        let dot = dot(&testing::shared_sum());
+
+        assert!(dot.starts_with("digraph phenotype {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(
+            dot.contains("    0 [label=\"out\", shape=doubleoctagon, fillcolor=\"lightblue\"];")
+        );
+        assert!(dot.contains("    2 [label=\"+\", shape=ellipse, fillcolor=\"white\"];"));
+        assert!(dot.contains("    3 [label=\"x\", shape=box, fillcolor=\"palegreen\"];"));
+        assert!(dot.contains("    1 -> 5 [label=\"1\", style=solid];"));
+        assert!(dot.contains("    5 -> 2 [label=\"0\", style=dashed];"));
+        assert_eq!(dot.matches(" -> ").count(), 7);
+    }
+
+    #[test]
+    fn indices_and_escaping() {
+        let phenotype = testing::phenotype(&[("say \"hi\"", ComponentIndex::Regular(1, 4))], &[]);
+        let options = DotOptions {
+            indices: true,
+            ..Default::default()
+        };
+
+        assert!(
+            dot_with(&phenotype, &options).contains("label=\"say \\\"hi\\\"\\ngroup 1, index 4\"")
+        );
+    }
+}
@@ -0,0 +1,495 @@
+use petgraph::graph::NodeIndex;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use crate::pretty::{arguments, output_nodes, shared_name};
+use crate::Phenotype;
+
+/// Label of the root tying together the trees of several outputs, as in `pretty::sexpr`
+pub const VALUES: &str = "values";
+
+/// Expression tree view of a phenotype
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Expr {
+    pub label: String,
+    pub arguments: Vec<Expr>,
+}
+
+impl Expr {
+    pub fn new(label: &str, arguments: Vec<Expr>) -> Expr {
+        Expr {
+            label: String::from(label),
+            arguments,
+        }
+    }
+
+    pub fn leaf(label: &str) -> Expr {
+        Expr::new(label, vec![])
+    }
+
+    /// Builds the tree below the output nodes, duplicating nodes shared through weak bindings
+    ///
+    /// A single output is the root, several outputs are the arguments of a `values` root, in the
+    /// order of the outputs. A weak binding to an ancestor would make the tree infinite, it
+    /// becomes a leaf named like the references in `pretty`.
+    pub fn from_phenotype(phenotype: &Phenotype) -> Option<Expr> {
+        let mut ancestors = HashSet::new();
+        let mut roots: Vec<Expr> = output_nodes(phenotype)
+            .into_iter()
+            .map(|output| Expr::from_node(phenotype, output, &mut ancestors))
+            .collect();
+
+        match roots.len() {
+            0 => None,
+            1 => roots.pop(),
+            _ => Some(Expr::new(VALUES, roots)),
+        }
+    }
+
+    fn from_node(
+        phenotype: &Phenotype,
+        node: NodeIndex,
+        ancestors: &mut HashSet<NodeIndex>,
+    ) -> Expr {
+        if ancestors.contains(&node) {
+            return Expr::leaf(&shared_name(node));
+        }
+
+        ancestors.insert(node);
+        let arguments = arguments(phenotype, node)
+            .into_iter()
+            .map(|argument| Expr::from_node(phenotype, argument, ancestors))
+            .collect();
+        ancestors.remove(&node);
+
+        Expr::new(&phenotype[node].name(), arguments)
+    }
+
+    /// Parses an S-expression such as `(+ x (neg 1))`
+    pub fn parse(source: &str) -> Option<Expr> {
+        let tokens: Vec<String> = source
+            .replace('(', " ( ")
+            .replace(')', " ) ")
+            .split_whitespace()
+            .map(String::from)
+            .collect();
+
+        let mut position = 0;
+        let expr = Expr::parse_tokens(&tokens, &mut position)?;
+
+        if position == tokens.len() {
+            Some(expr)
+        } else {
+            None
+        }
+    }
+
+    fn parse_tokens(tokens: &[String], position: &mut usize) -> Option<Expr> {
+        let token = tokens.get(*position)?;
+        *position += 1;
+
+        match token.as_str() {
+            ")" => None,
+            "(" => {
+                let label = tokens.get(*position)?.clone();
+                if label == "(" || label == ")" {
+                    return None;
+                }
+                *position += 1;
+
+                let mut arguments = vec![];
+                while tokens.get(*position)? != ")" {
+                    arguments.push(Expr::parse_tokens(tokens, position)?);
+                }
+                *position += 1;
+
+                Some(Expr::new(&label, arguments))
+            }
+            label => Some(Expr::leaf(label)),
+        }
+    }
+
+    /// Number of nodes in the tree
+    pub fn size(&self) -> usize {
+        1 + self.arguments.iter().map(Expr::size).sum::<usize>()
+    }
+
+    /// Value of a leaf whose label is a number
+    pub fn constant(&self) -> Option<f64> {
+        if self.arguments.is_empty() {
+            self.label.parse().ok()
+        } else {
+            None
+        }
+    }
+
+    /// Sorts the arguments of commutative labels, so that equal programs compare equal
+    ///
+    /// The outputs under a `values` root keep their order.
+    pub fn canonical(&self, commutative: &HashSet<String>) -> Expr {
+        let mut arguments: Vec<Expr> = self
+            .arguments
+            .iter()
+            .map(|argument| argument.canonical(commutative))
+            .collect();
+
+        if commutative.contains(&self.label) {
+            arguments.sort();
+        }
+
+        Expr::new(&self.label, arguments)
+    }
+
+    /// Hash of the canonical form
+    pub fn structural_hash(&self, commutative: &HashSet<String>) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.canonical(commutative).hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.arguments.is_empty() {
+            write!(f, "{}", self.label)
+        } else {
+            write!(f, "({}", self.label)?;
+            for argument in &self.arguments {
+                write!(f, " {}", argument)?;
+            }
+            write!(f, ")")
+        }
+    }
+}
+
+/// Algebraic rewrite rule, labels starting with `?` in the pattern are variables
+///
+/// A variable used more than once only matches equal subtrees, so `(- ?a ?a)` matches `x - x`.
+pub struct Rule {
+    pub pattern: Expr,
+    pub replacement: Expr,
+}
+
+impl Rule {
+    /// Creates a rule from S-expressions, e.g. `Rule::new("(neg (neg ?a))", "?a")`
+    ///
+    /// # Panics
+    ///
+    /// If either side isn't a valid S-expression, or the replacement uses unbound variables.
+    pub fn new(pattern: &str, replacement: &str) -> Rule {
+        let pattern = Expr::parse(pattern).expect("invalid rule pattern");
+        let replacement = Expr::parse(replacement).expect("invalid rule replacement");
+
+        let mut bound = HashSet::new();
+        variables(&pattern, &mut bound);
+        let mut used = HashSet::new();
+        variables(&replacement, &mut used);
+        assert!(
+            used.is_subset(&bound),
+            "rule replacement uses unbound variables"
+        );
+
+        Rule {
+            pattern,
+            replacement,
+        }
+    }
+
+    /// Rewrites `expr` if it matches the pattern, trying both argument orders of commutative labels
+    pub fn apply(&self, expr: &Expr, commutative: &HashSet<String>) -> Option<Expr> {
+        let mut bindings = HashMap::new();
+
+        if matches(&self.pattern, expr, commutative, &mut bindings) {
+            Some(substitute(&self.replacement, &bindings))
+        } else {
+            None
+        }
+    }
+}
+
+fn is_variable(expr: &Expr) -> bool {
+    expr.arguments.is_empty() && expr.label.starts_with('?')
+}
+
+fn variables(expr: &Expr, result: &mut HashSet<String>) {
+    if is_variable(expr) {
+        result.insert(expr.label.clone());
+    }
+
+    for argument in &expr.arguments {
+        variables(argument, result);
+    }
+}
+
+fn matches(
+    pattern: &Expr,
+    expr: &Expr,
+    commutative: &HashSet<String>,
+    bindings: &mut HashMap<String, Expr>,
+) -> bool {
+    if is_variable(pattern) {
+        return match bindings.get(&pattern.label) {
+            Some(bound) => bound == expr,
+            None => {
+                bindings.insert(pattern.label.clone(), expr.clone());
+                true
+            }
+        };
+    }
+
+    if pattern.label != expr.label || pattern.arguments.len() != expr.arguments.len() {
+        return false;
+    }
+
+    let saved = bindings.clone();
+    if matches_all(
+        &pattern.arguments,
+        expr.arguments.iter(),
+        commutative,
+        bindings,
+    ) {
+        return true;
+    }
+
+    if commutative.contains(&pattern.label) && pattern.arguments.len() == 2 {
+        *bindings = saved;
+        if matches_all(
+            &pattern.arguments,
+            expr.arguments.iter().rev(),
+            commutative,
+            bindings,
+        ) {
+            return true;
+        }
+    }
+
+    false
+}
+
+fn matches_all<'a, I>(
+    patterns: &[Expr],
+    exprs: I,
+    commutative: &HashSet<String>,
+    bindings: &mut HashMap<String, Expr>,
+) -> bool
+where
+    I: Iterator<Item = &'a Expr>,
+{
+    patterns
+        .iter()
+        .zip(exprs)
+        .all(|(pattern, expr)| matches(pattern, expr, commutative, bindings))
+}
+
+fn substitute(replacement: &Expr, bindings: &HashMap<String, Expr>) -> Expr {
+    if is_variable(replacement) {
+        return bindings[&replacement.label].clone();
+    }
+
+    Expr::new(
+        &replacement.label,
+        replacement
+            .arguments
+            .iter()
+            .map(|argument| substitute(argument, bindings))
+            .collect(),
+    )
+}
+
+/// Evaluates a label applied to constant arguments, used for constant folding
+pub type Evaluator = Box<dyn Fn(&str, &[f64]) -> Option<f64>>;
+
+/// Simplifies expressions bottom-up with rewrite rules and constant folding
+pub struct Simplifier {
+    pub rules: Vec<Rule>,
+    pub commutative: HashSet<String>,
+    pub evaluate: Option<Evaluator>,
+    /// Bound on rewrites at a single node, guards against rules that undo each other
+    pub max_rewrites: usize,
+}
+
+impl Simplifier {
+    pub fn new(rules: Vec<Rule>) -> Simplifier {
+        Simplifier {
+            rules,
+            commutative: HashSet::new(),
+            evaluate: None,
+            max_rewrites: 100,
+        }
+    }
+
+    /// Simplifies the expressed program of a phenotype, returning its canonical form
+    ///
+    /// Each output is simplified on its own.
+    pub fn simplify_phenotype(&self, phenotype: &Phenotype) -> Option<Expr> {
+        Expr::from_phenotype(phenotype).map(|expr| self.simplify(&expr))
+    }
+
+    /// Simplifies an expression, returning its canonical form
+    pub fn simplify(&self, expr: &Expr) -> Expr {
+        self.simplify_bounded(expr, self.max_rewrites)
+    }
+
+    fn simplify_bounded(&self, expr: &Expr, budget: usize) -> Expr {
+        let arguments = expr
+            .arguments
+            .iter()
+            .map(|argument| self.simplify_bounded(argument, budget))
+            .collect();
+
+        if expr.label == VALUES {
+            return Expr::new(VALUES, arguments);
+        }
+
+        let mut current = Expr::new(&expr.label, arguments).canonical(&self.commutative);
+
+        for _ in 0..budget {
+            if let Some(folded) = self.fold(&current) {
+                return folded;
+            }
+
+            let rewritten = self
+                .rules
+                .iter()
+                .find_map(|rule| rule.apply(&current, &self.commutative));
+
+            match rewritten {
+                Some(rewritten) if rewritten != current => {
+                    // the replacement may contain new opportunities below its root
+                    current = Expr::new(
+                        &rewritten.label,
+                        rewritten
+                            .arguments
+                            .iter()
+                            .map(|argument| self.simplify_bounded(argument, budget / 2))
+                            .collect(),
+                    )
+                    .canonical(&self.commutative);
+                }
+                _ => break,
+            }
+        }
+
+        current
+    }
+
+    fn fold(&self, expr: &Expr) -> Option<Expr> {
+        let evaluate = self.evaluate.as_ref()?;
+
+        if expr.arguments.is_empty() {
+            return None;
+        }
+
+        let constants = expr
+            .arguments
+            .iter()
+            .map(Expr::constant)
+            .collect::<Option<Vec<f64>>>()?;
+
+        let value = evaluate(&expr.label, &constants)?;
+
+        if value.is_finite() {
+            Some(Expr::leaf(&value.to_string()))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn parse(source: &str) -> Expr {
+        Expr::parse(source).unwrap()
+    }
+
+    fn commutative() -> HashSet<String> {
+        ["+", "*"]
+            .iter()
+            .map(|label| String::from(*label))
+            .collect()
+    }
+
+    #[test]
+    fn parse_and_display_round_trip() {
+        let source = "(+ x (neg 1))";
+
+        assert_eq!(parse(source).to_string(), source);
+        assert_eq!(parse(source).size(), 4);
+        assert_eq!(Expr::parse("(+ x"), None);
+    }
+
+    #[test]
+    fn phenotypes_expand_shared_nodes() {
+        assert_eq!(
+            Expr::from_phenotype(&testing::shared_sum()),
+            Some(parse("(out (* (+ x 1) (use x (+ x 1))))"))
+        );
+    }
+
+    #[test]
+    fn several_outputs_are_values() {
+        let phenotype = testing::two_roots("x");
+        let expr = Expr::from_phenotype(&phenotype).unwrap();
+        assert_eq!(expr, parse("(values (out (+ x 1)) (out x))"));
+
+        let commutative = commutative();
+        let swapped = parse("(values (out x) (out (+ x 1)))");
+        assert_ne!(
+            expr.canonical(&commutative),
+            swapped.canonical(&commutative)
+        );
+        assert_ne!(
+            expr.structural_hash(&commutative),
+            swapped.structural_hash(&commutative)
+        );
+
+        let mut simplifier = Simplifier::new(vec![Rule::new("(+ ?a 1)", "?a")]);
+        simplifier.commutative = commutative;
+        assert_eq!(
+            simplifier.simplify_phenotype(&phenotype),
+            Some(parse("(values (out x) (out x))"))
+        );
+    }
+
+    #[test]
+    fn commutative_arguments_hash_equally() {
+        let commutative = commutative();
+
+        assert_eq!(
+            parse("(+ x (* 2 y))").structural_hash(&commutative),
+            parse("(+ (* y 2) x)").structural_hash(&commutative)
+        );
+        assert_ne!(
+            parse("(- x y)").structural_hash(&commutative),
+            parse("(- y x)").structural_hash(&commutative)
+        );
+    }
+
+    #[test]
+    fn rules_and_folding() {
+        let mut simplifier = Simplifier::new(vec![
+            Rule::new("(* ?a 1)", "?a"),
+            Rule::new("(+ ?a 0)", "?a"),
+            Rule::new("(- ?a ?a)", "0"),
+        ]);
+        simplifier.commutative = commutative();
+
+        assert_eq!(simplifier.simplify(&parse("(* 1 (+ 0 x))")), parse("x"));
+        assert_eq!(simplifier.simplify(&parse("(- (+ x 0) x)")), parse("0"));
+        assert_eq!(simplifier.simplify(&parse("(- x y)")), parse("(- x y)"));
+
+        simplifier.evaluate = Some(Box::new(|label, arguments| match label {
+            "+" => Some(arguments.iter().sum()),
+            _ => None,
+        }));
+        assert_eq!(
+            simplifier.simplify(&parse("(+ x (+ 1 2))")),
+            parse("(+ 3 x)")
+        );
+    }
+}
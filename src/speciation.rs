@@ -0,0 +1,270 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::blueprints::Blueprints;
+use crate::chromosome::EgpChromosome;
+use crate::diversity::genotypic_distance_with;
+use crate::evolution::Individual;
+use crate::ExpressionConfig;
+
+/// Parameters of NEAT-style speciation
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Speciation {
+    /// Largest `genotypic_distance` to a species' representative for membership
+    pub threshold: f64,
+    /// Probability that a recombination donor is chosen from the whole population instead of
+    /// the parent's species
+    pub interbreeding_rate: f64,
+}
+
+impl Default for Speciation {
+    fn default() -> Speciation {
+        Speciation {
+            threshold: 2.,
+            interbreeding_rate: 0.05,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Species {
+    /// Stable over generations, unlike the position in `SpeciesSet::species`
+    pub id: usize,
+    pub representative: EgpChromosome,
+    /// Indices into the population
+    pub members: Vec<usize>,
+}
+
+/// Clustering of a population into species, with fitness shared within each
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SpeciesSet {
+    pub species: Vec<Species>,
+    /// Position in `species` of each member of the population
+    pub membership: Vec<usize>,
+    /// Fitness of each member of the population shared with its species
+    ///
+    /// Fitness may be negative, so it's shifted to start at zero for the worst member before
+    /// being divided by the size of the species.
+    #[serde(with = "crate::checkpoint::float::vec")]
+    pub shared_fitness: Vec<f64>,
+    next_id: usize,
+}
+
+impl SpeciesSet {
+    pub fn new() -> SpeciesSet {
+        SpeciesSet::default()
+    }
+
+    /// Assigns every individual to the first species whose representative is close enough,
+    /// founding new species for the rest
+    ///
+    /// Representatives are kept from the previous clustering, then replaced by random members.
+    /// Species left without members disappear.
+    pub fn speciate<R: Rng + ?Sized>(
+        &mut self,
+        rng: &mut R,
+        blueprints: &Blueprints,
+        population: &[Individual],
+        threshold: f64,
+        expression: &ExpressionConfig,
+    ) {
+        for species in &mut self.species {
+            species.members.clear();
+        }
+        self.membership = vec![0; population.len()];
+
+        for (index, individual) in population.iter().enumerate() {
+            self.place(
+                blueprints,
+                index,
+                &individual.chromosome,
+                threshold,
+                expression,
+            );
+        }
+
+        self.species.retain(|species| !species.members.is_empty());
+        for (position, species) in self.species.iter().enumerate() {
+            for &member in &species.members {
+                self.membership[member] = position;
+            }
+        }
+
+        for species in &mut self.species {
+            let chosen = species.members[rng.gen_range(0, species.members.len())];
+            species.representative = population[chosen].chromosome.clone();
+        }
+
+        self.share(population);
+    }
+
+    /// Moves the individual at `index` into its species, e.g. after it was replaced
+    ///
+    /// Its previous species may be left empty until the next `speciate`.
+    pub fn reassign(
+        &mut self,
+        blueprints: &Blueprints,
+        population: &[Individual],
+        index: usize,
+        threshold: f64,
+        expression: &ExpressionConfig,
+    ) {
+        if let Some(species) = self.species.get_mut(self.membership[index]) {
+            species.members.retain(|&member| member != index);
+        }
+
+        self.place(
+            blueprints,
+            index,
+            &population[index].chromosome,
+            threshold,
+            expression,
+        );
+        self.share(population);
+    }
+
+    fn place(
+        &mut self,
+        blueprints: &Blueprints,
+        index: usize,
+        chromosome: &EgpChromosome,
+        threshold: f64,
+        expression: &ExpressionConfig,
+    ) {
+        let position = self.species.iter().position(|species| {
+            let representative = &species.representative;
+            genotypic_distance_with(blueprints, representative, chromosome, expression) <= threshold
+        });
+
+        let position = match position {
+            Some(position) => position,
+            None => {
+                self.species.push(Species {
+                    id: self.next_id,
+                    representative: chromosome.clone(),
+                    members: vec![],
+                });
+                self.next_id += 1;
+                self.species.len() - 1
+            }
+        };
+
+        self.species[position].members.push(index);
+        self.membership[index] = position;
+    }
+
+    fn share(&mut self, population: &[Individual]) {
+        let worst = population
+            .iter()
+            .map(|individual| individual.fitness)
+            .fold(f64::INFINITY, f64::min);
+
+        self.shared_fitness = population
+            .iter()
+            .enumerate()
+            .map(|(index, individual)| {
+                let size = self.species[self.membership[index]].members.len();
+                (individual.fitness - worst) / size as f64
+            })
+            .collect();
+    }
+
+    /// Members of the species of the individual at `index`
+    pub fn mates(&self, index: usize) -> &[usize] {
+        &self.species[self.membership[index]].members
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evolution::{Config, Evolution};
+    use crate::testing;
+
+    fn population(fitness: &[f64]) -> Vec<Individual> {
+        let blueprints = testing::arithmetic();
+
+        testing::ancestors(&blueprints, 11, fitness.len())
+            .into_iter()
+            .zip(fitness)
+            .enumerate()
+            .map(|(id, (chromosome, &fitness))| Individual {
+                id: id as u64,
+                chromosome,
+                fitness,
+                objectives: vec![],
+                phenotype_size: 0,
+                behaviour: vec![],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn threshold_bounds_the_species() {
+        let blueprints = testing::arithmetic();
+        let expression = ExpressionConfig::default();
+        let population = population(&[1., 3., 5., 7.]);
+        let mut rng = testing::rng(0);
+
+        let mut species = SpeciesSet::new();
+        species.speciate(
+            &mut rng,
+            &blueprints,
+            &population,
+            f64::INFINITY,
+            &expression,
+        );
+        assert_eq!(species.species.len(), 1);
+        assert_eq!(species.mates(2), &[0, 1, 2, 3]);
+        assert_eq!(species.shared_fitness, vec![0., 0.5, 1., 1.5]);
+
+        let mut species = SpeciesSet::new();
+        species.speciate(&mut rng, &blueprints, &population, -1., &expression);
+        assert_eq!(species.species.len(), 4);
+        assert_eq!(species.membership, vec![0, 1, 2, 3]);
+        assert_eq!(species.shared_fitness, vec![0., 2., 4., 6.]);
+    }
+
+    #[test]
+    fn reassigned_members_leave_their_species() {
+        let blueprints = testing::arithmetic();
+        let expression = ExpressionConfig::default();
+        let mut population = population(&[1., 3., 5.]);
+        let mut rng = testing::rng(1);
+
+        let mut species = SpeciesSet::new();
+        species.speciate(&mut rng, &blueprints, &population, -1., &expression);
+
+        population[1].chromosome = population[0].chromosome.clone();
+        species.reassign(&blueprints, &population, 1, 0., &expression);
+
+        assert_eq!(species.mates(1), &[0, 1]);
+        assert!(species.species[1].members.is_empty());
+        assert_eq!(species.shared_fitness, vec![0., 1., 4.]);
+    }
+
+    #[test]
+    fn speciated_runs_cluster_the_whole_population() {
+        let blueprints = testing::arithmetic();
+        let config = Config {
+            speciation: Some(Speciation::default()),
+            recombination_rate: 0.8,
+            ..testing::config(12)
+        };
+
+        let mut evolution = Evolution::new(&blueprints, config, testing::size);
+        // recombination before the first clustering draws donors from the whole population
+        evolution.offspring();
+        evolution.run();
+
+        let species = evolution.species.as_ref().unwrap();
+        assert_eq!(species.membership.len(), 20);
+        assert_eq!(species.shared_fitness.len(), 20);
+        let members: usize = species
+            .species
+            .iter()
+            .map(|species| species.members.len())
+            .sum();
+        assert_eq!(members, 20);
+    }
+}
@@ -0,0 +1,431 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+
+use crate::blueprints::Blueprints;
+use crate::evolution::{FitnessCache, Individual};
+
+/// Offspring produced by each operator in a generation, and how many improved on their parent
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OperatorStats {
+    pub mutations: usize,
+    pub mutation_successes: usize,
+    pub recombinations: usize,
+    pub recombination_successes: usize,
+}
+
+fn rate(successes: usize, attempts: usize) -> f64 {
+    if attempts == 0 {
+        0.
+    } else {
+        successes as f64 / attempts as f64
+    }
+}
+
+impl OperatorStats {
+    pub fn mutation_success_rate(&self) -> f64 {
+        rate(self.mutation_successes, self.mutations)
+    }
+
+    pub fn recombination_success_rate(&self) -> f64 {
+        rate(self.recombination_successes, self.recombinations)
+    }
+}
+
+/// Summary of one generation
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Statistics {
+    pub generation: usize,
+    #[serde(with = "crate::checkpoint::float")]
+    pub best_fitness: f64,
+    #[serde(with = "crate::checkpoint::float")]
+    pub upper_quartile_fitness: f64,
+    #[serde(with = "crate::checkpoint::float")]
+    pub median_fitness: f64,
+    #[serde(with = "crate::checkpoint::float")]
+    pub lower_quartile_fitness: f64,
+    #[serde(with = "crate::checkpoint::float")]
+    pub worst_fitness: f64,
+    #[serde(with = "crate::checkpoint::float")]
+    pub mean_fitness: f64,
+    /// Expressed nodes per phenotype
+    pub mean_phenotype_size: f64,
+    pub max_phenotype_size: usize,
+    /// Mean number of regular components in each group
+    pub mean_chromosome_lengths: Vec<f64>,
+    pub operators: OperatorStats,
+    /// Fraction of this generation's fitness lookups answered from the cache
+    pub cache_hit_rate: f64,
+}
+
+/// Quantile of sorted values, interpolating linearly between neighbours
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.;
+    }
+
+    let position = q * (sorted.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    let weight = position - lower as f64;
+
+    sorted[lower] * (1. - weight) + sorted[upper] * weight
+}
+
+impl Statistics {
+    pub fn compute(
+        generation: usize,
+        blueprints: &Blueprints,
+        population: &[Individual],
+        operators: &OperatorStats,
+        cache: &FitnessCache,
+    ) -> Statistics {
+        let n = population.len().max(1) as f64;
+
+        let mut fitness: Vec<f64> = population
+            .iter()
+            .map(|individual| individual.fitness)
+            .collect();
+        fitness.sort_by(|a, b| a.total_cmp(b));
+
+        let total_size: usize = population
+            .iter()
+            .map(|individual| individual.phenotype_size)
+            .sum();
+
+        let mean_chromosome_lengths = (0..blueprints.regular.len())
+            .map(|group| {
+                let total: usize = population
+                    .iter()
+                    .map(|individual| individual.chromosome.regular[group].len())
+                    .sum();
+                total as f64 / n
+            })
+            .collect();
+
+        Statistics {
+            generation,
+            best_fitness: quantile(&fitness, 1.),
+            upper_quartile_fitness: quantile(&fitness, 0.75),
+            median_fitness: quantile(&fitness, 0.5),
+            lower_quartile_fitness: quantile(&fitness, 0.25),
+            worst_fitness: quantile(&fitness, 0.),
+            mean_fitness: fitness.iter().sum::<f64>() / n,
+            mean_phenotype_size: total_size as f64 / n,
+            max_phenotype_size: population
+                .iter()
+                .map(|individual| individual.phenotype_size)
+                .max()
+                .unwrap_or(0),
+            mean_chromosome_lengths,
+            operators: operators.clone(),
+            cache_hit_rate: cache.generation_hit_rate(),
+        }
+    }
+}
+
+/// What observers are shown after each generation
+pub struct Report<'r> {
+    pub population: &'r [Individual],
+    pub statistics: &'r Statistics,
+}
+
+/// Receives a report after each generation of the evolution loop
+pub trait Observer {
+    fn observe(&mut self, report: &Report);
+}
+
+/// Writes statistics as one JSON object per line
+///
+/// Observers can't fail the evolution loop, the first write error is kept and stops writing.
+pub struct JsonLinesSink<W: Write> {
+    writer: W,
+    pub error: Option<io::Error>,
+}
+
+impl<W: Write> JsonLinesSink<W> {
+    pub fn new(writer: W) -> JsonLinesSink<W> {
+        JsonLinesSink {
+            writer,
+            error: None,
+        }
+    }
+
+    fn write(&mut self, statistics: &Statistics) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, statistics)?;
+        writeln!(self.writer)?;
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> Observer for JsonLinesSink<W> {
+    fn observe(&mut self, report: &Report) {
+        if self.error.is_none() {
+            self.error = self.write(report.statistics).err();
+        }
+    }
+}
+
+/// Writes statistics as CSV, with a header before the first row
+///
+/// Observers can't fail the evolution loop, the first write error is kept and stops writing.
+pub struct CsvSink<W: Write> {
+    writer: W,
+    header_written: bool,
+    pub error: Option<io::Error>,
+}
+
+impl<W: Write> CsvSink<W> {
+    pub fn new(writer: W) -> CsvSink<W> {
+        CsvSink {
+            writer,
+            header_written: false,
+            error: None,
+        }
+    }
+
+    fn write(&mut self, statistics: &Statistics) -> io::Result<()> {
+        if !self.header_written {
+            let mut header = vec![
+                String::from("generation"),
+                String::from("best_fitness"),
+                String::from("upper_quartile_fitness"),
+                String::from("median_fitness"),
+                String::from("lower_quartile_fitness"),
+                String::from("worst_fitness"),
+                String::from("mean_fitness"),
+                String::from("mean_phenotype_size"),
+                String::from("max_phenotype_size"),
+            ];
+            header.extend(
+                (0..statistics.mean_chromosome_lengths.len())
+                    .map(|group| format!("mean_chromosome_length_{}", group)),
+            );
+            header.extend(vec![
+                String::from("mutation_success_rate"),
+                String::from("recombination_success_rate"),
+                String::from("cache_hit_rate"),
+            ]);
+
+            writeln!(self.writer, "{}", header.join(","))?;
+            self.header_written = true;
+        }
+
+        let mut row = vec![
+            statistics.generation.to_string(),
+            statistics.best_fitness.to_string(),
+            statistics.upper_quartile_fitness.to_string(),
+            statistics.median_fitness.to_string(),
+            statistics.lower_quartile_fitness.to_string(),
+            statistics.worst_fitness.to_string(),
+            statistics.mean_fitness.to_string(),
+            statistics.mean_phenotype_size.to_string(),
+            statistics.max_phenotype_size.to_string(),
+        ];
+        row.extend(
+            statistics
+                .mean_chromosome_lengths
+                .iter()
+                .map(|length| length.to_string()),
+        );
+        row.extend(vec![
+            statistics.operators.mutation_success_rate().to_string(),
+            statistics
+                .operators
+                .recombination_success_rate()
+                .to_string(),
+            statistics.cache_hit_rate.to_string(),
+        ]);
+
+        writeln!(self.writer, "{}", row.join(","))?;
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> Observer for CsvSink<W> {
+    fn observe(&mut self, report: &Report) {
+        if self.error.is_none() {
+            self.error = self.write(report.statistics).err();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn quantiles_interpolate_between_neighbours() {
+        let sorted = [1., 2., 4., 8., 16.];
+
+        assert_eq!(quantile(&sorted, 0.), 1.);
+        assert_eq!(quantile(&sorted, 0.5), 4.);
+        assert_eq!(quantile(&sorted, 0.625), 6.);
+        assert_eq!(quantile(&sorted, 1.), 16.);
+        assert_eq!(quantile(&[3.], 0.25), 3.);
+        assert_eq!(quantile(&[], 0.5), 0.);
+    }
+
+    fn population(fitness: &[f64]) -> Vec<Individual> {
+        let blueprints = testing::arithmetic();
+
+        testing::ancestors(&blueprints, 1, fitness.len())
+            .into_iter()
+            .zip(fitness)
+            .enumerate()
+            .map(|(id, (chromosome, &fitness))| Individual {
+                id: id as u64,
+                chromosome,
+                fitness,
+                objectives: vec![],
+                phenotype_size: 2 * id + 1,
+                behaviour: vec![],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn generations_are_summarised() {
+        let blueprints = testing::arithmetic();
+        let population = population(&[3., 1., 5., 2., 4.]);
+        let operators = OperatorStats {
+            mutations: 4,
+            mutation_successes: 1,
+            ..Default::default()
+        };
+        let statistics = Statistics::compute(
+            7,
+            &blueprints,
+            &population,
+            &operators,
+            &FitnessCache::new(),
+        );
+
+        let lengths: usize = population
+            .iter()
+            .map(|individual| individual.chromosome.regular[0].len())
+            .sum();
+        assert_eq!(statistics.generation, 7);
+        assert_eq!(
+            (
+                statistics.worst_fitness,
+                statistics.lower_quartile_fitness,
+                statistics.median_fitness,
+                statistics.upper_quartile_fitness,
+                statistics.best_fitness
+            ),
+            (1., 2., 3., 4., 5.)
+        );
+        assert_eq!(statistics.mean_fitness, 3.);
+        assert_eq!(statistics.mean_phenotype_size, 5.);
+        assert_eq!(statistics.max_phenotype_size, 9);
+        assert_eq!(
+            statistics.mean_chromosome_lengths,
+            vec![lengths as f64 / 5.]
+        );
+        assert_eq!(statistics.operators.mutation_success_rate(), 0.25);
+        assert_eq!(statistics.operators.recombination_success_rate(), 0.);
+        assert_eq!(statistics.cache_hit_rate, 0.);
+    }
+
+    #[test]
+    fn cache_hit_rate_covers_one_generation() {
+        let blueprints = testing::arithmetic();
+        let population = population(&[1.]);
+        let phenotype = testing::shared_sum();
+        let statistics = |cache: &FitnessCache| {
+            Statistics::compute(
+                0,
+                &blueprints,
+                &population,
+                &OperatorStats::default(),
+                cache,
+            )
+        };
+
+        let mut cache = FitnessCache::new();
+        cache.score(&phenotype, testing::size);
+        cache.score(&phenotype, testing::size);
+        assert_eq!(statistics(&cache).cache_hit_rate, 0.5);
+
+        cache.start_generation();
+        cache.score(&phenotype, testing::size);
+        assert_eq!(statistics(&cache).cache_hit_rate, 1.);
+        assert_eq!(cache.hit_rate(), 2. / 3.);
+    }
+
+    fn observe_generations<O: Observer>(observer: &mut O, generations: usize) -> Vec<Statistics> {
+        let population = population(&[1., 2.]);
+
+        (0..generations)
+            .map(|generation| {
+                let statistics = Statistics {
+                    generation,
+                    best_fitness: generation as f64 + 0.5,
+                    worst_fitness: -1.,
+                    mean_chromosome_lengths: vec![1.5, 2.],
+                    ..Default::default()
+                };
+                observer.observe(&Report {
+                    population: &population,
+                    statistics: &statistics,
+                });
+                statistics
+            })
+            .collect()
+    }
+
+    #[test]
+    fn json_lines_has_one_object_per_generation() {
+        let mut sink = JsonLinesSink::new(Vec::new());
+        let written = observe_generations(&mut sink, 3);
+        assert!(sink.error.is_none());
+
+        let output = String::from_utf8(sink.writer).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+
+        for (line, statistics) in lines.iter().zip(&written) {
+            let read: Statistics = serde_json::from_str(line).unwrap();
+            assert_eq!(&read, statistics);
+        }
+    }
+
+    #[test]
+    fn csv_has_one_header_and_a_row_per_generation() {
+        let mut sink = CsvSink::new(Vec::new());
+        observe_generations(&mut sink, 2);
+        assert!(sink.error.is_none());
+
+        let output = String::from_utf8(sink.writer).unwrap();
+        assert_eq!(
+            output,
+            "generation,best_fitness,upper_quartile_fitness,median_fitness,\
+             lower_quartile_fitness,worst_fitness,mean_fitness,mean_phenotype_size,\
+             max_phenotype_size,mean_chromosome_length_0,mean_chromosome_length_1,\
+             mutation_success_rate,recombination_success_rate,cache_hit_rate\n\
+             0,0.5,0,0,0,-1,0,0,0,1.5,2,0,0,0\n\
+             1,1.5,0,0,0,-1,0,0,0,1.5,2,0,0,0\n"
+        );
+    }
+
+    struct Failing;
+
+    impl Write for Failing {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::Other, "full"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_errors_are_kept() {
+        let mut sink = CsvSink::new(Failing);
+        observe_generations(&mut sink, 2);
+
+        assert_eq!(sink.error.unwrap().to_string(), "full");
+    }
+}
@@ -0,0 +1,238 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+/// State of a run as seen by stop conditions
+#[derive(Debug, Clone)]
+pub struct Progress {
+    pub generation: usize,
+    /// Calls of the fitness function, cache hits don't count
+    pub evaluations: usize,
+    /// Time spent in the current call of the evolution loop
+    pub elapsed: Duration,
+    pub best_fitness: f64,
+    pub generations_without_improvement: usize,
+}
+
+/// Why a run stopped
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StopReason {
+    TargetFitness,
+    Generations,
+    Evaluations,
+    WallClock,
+    Stagnation,
+    Custom(String),
+    /// Every condition of an `All` held
+    All(Vec<StopReason>),
+}
+
+/// A stop condition, composed with `Any` and `All`
+pub enum StopCondition {
+    /// Best fitness reached at least this value
+    TargetFitness(f64),
+    Generations(usize),
+    Evaluations(usize),
+    WallClock(Duration),
+    /// Best fitness hasn't improved for this many generations
+    Stagnation(usize),
+    /// User condition, the name is reported as the reason
+    Custom(String, Box<dyn Fn(&Progress) -> bool>),
+    /// The first condition that holds, an empty `Any` never holds
+    Any(Vec<StopCondition>),
+    /// Every condition, an empty `All` always holds
+    All(Vec<StopCondition>),
+}
+
+impl StopCondition {
+    pub fn custom<F>(name: &str, condition: F) -> StopCondition
+    where
+        F: Fn(&Progress) -> bool + 'static,
+    {
+        StopCondition::Custom(String::from(name), Box::new(condition))
+    }
+
+    /// Returns the reason to stop if the condition holds
+    pub fn check(&self, progress: &Progress) -> Option<StopReason> {
+        match self {
+            StopCondition::TargetFitness(target) => {
+                if progress.best_fitness >= *target {
+                    Some(StopReason::TargetFitness)
+                } else {
+                    None
+                }
+            }
+            StopCondition::Generations(generations) => {
+                if progress.generation >= *generations {
+                    Some(StopReason::Generations)
+                } else {
+                    None
+                }
+            }
+            StopCondition::Evaluations(evaluations) => {
+                if progress.evaluations >= *evaluations {
+                    Some(StopReason::Evaluations)
+                } else {
+                    None
+                }
+            }
+            StopCondition::WallClock(budget) => {
+                if progress.elapsed >= *budget {
+                    Some(StopReason::WallClock)
+                } else {
+                    None
+                }
+            }
+            StopCondition::Stagnation(generations) => {
+                if progress.generations_without_improvement >= *generations {
+                    Some(StopReason::Stagnation)
+                } else {
+                    None
+                }
+            }
+            StopCondition::Custom(name, condition) => {
+                if condition(progress) {
+                    Some(StopReason::Custom(name.clone()))
+                } else {
+                    None
+                }
+            }
+            StopCondition::Any(conditions) => conditions
+                .iter()
+                .find_map(|condition| condition.check(progress)),
+            StopCondition::All(conditions) => conditions
+                .iter()
+                .map(|condition| condition.check(progress))
+                .collect::<Option<Vec<StopReason>>>()
+                .map(StopReason::All),
+        }
+    }
+}
+
+impl fmt::Debug for StopCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopCondition::TargetFitness(target) => write!(f, "TargetFitness({})", target),
+            StopCondition::Generations(generations) => write!(f, "Generations({})", generations),
+            StopCondition::Evaluations(evaluations) => write!(f, "Evaluations({})", evaluations),
+            StopCondition::WallClock(budget) => write!(f, "WallClock({:?})", budget),
+            StopCondition::Stagnation(generations) => write!(f, "Stagnation({})", generations),
+            StopCondition::Custom(name, _) => write!(f, "Custom({})", name),
+            StopCondition::Any(conditions) => write!(f, "Any({:?})", conditions),
+            StopCondition::All(conditions) => write!(f, "All({:?})", conditions),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evolution::Evolution;
+    use crate::testing;
+
+    fn progress() -> Progress {
+        Progress {
+            generation: 10,
+            evaluations: 500,
+            elapsed: Duration::from_secs(2),
+            best_fitness: 0.5,
+            generations_without_improvement: 4,
+        }
+    }
+
+    #[test]
+    fn conditions_hold_at_their_thresholds() {
+        let progress = progress();
+        let check = |condition: StopCondition| condition.check(&progress);
+
+        assert_eq!(
+            check(StopCondition::TargetFitness(0.5)),
+            Some(StopReason::TargetFitness)
+        );
+        assert_eq!(check(StopCondition::TargetFitness(0.6)), None);
+        assert_eq!(
+            check(StopCondition::Generations(10)),
+            Some(StopReason::Generations)
+        );
+        assert_eq!(check(StopCondition::Generations(11)), None);
+        assert_eq!(
+            check(StopCondition::Evaluations(500)),
+            Some(StopReason::Evaluations)
+        );
+        assert_eq!(check(StopCondition::Evaluations(501)), None);
+        assert_eq!(
+            check(StopCondition::WallClock(Duration::from_secs(2))),
+            Some(StopReason::WallClock)
+        );
+        assert_eq!(
+            check(StopCondition::WallClock(Duration::from_secs(3))),
+            None
+        );
+        assert_eq!(
+            check(StopCondition::Stagnation(4)),
+            Some(StopReason::Stagnation)
+        );
+        assert_eq!(check(StopCondition::Stagnation(5)), None);
+        assert_eq!(
+            check(StopCondition::custom("even", |progress| progress
+                .generation
+                % 2
+                == 0)),
+            Some(StopReason::Custom(String::from("even")))
+        );
+    }
+
+    #[test]
+    fn combinators() {
+        let progress = progress();
+
+        let any = StopCondition::Any(vec![
+            StopCondition::Generations(20),
+            StopCondition::Evaluations(100),
+            StopCondition::Stagnation(1),
+        ]);
+        assert_eq!(any.check(&progress), Some(StopReason::Evaluations));
+
+        let all = StopCondition::All(vec![
+            StopCondition::Generations(5),
+            StopCondition::Stagnation(1),
+        ]);
+        assert_eq!(
+            all.check(&progress),
+            Some(StopReason::All(vec![
+                StopReason::Generations,
+                StopReason::Stagnation
+            ]))
+        );
+
+        let unmet = StopCondition::All(vec![
+            StopCondition::Generations(5),
+            StopCondition::Stagnation(10),
+        ]);
+        assert_eq!(unmet.check(&progress), None);
+    }
+
+    #[test]
+    fn empty_combinators() {
+        assert_eq!(StopCondition::Any(vec![]).check(&progress()), None);
+        assert_eq!(
+            StopCondition::All(vec![]).check(&progress()),
+            Some(StopReason::All(vec![]))
+        );
+    }
+
+    #[test]
+    fn run_until_checks_before_every_generation() {
+        let blueprints = testing::arithmetic();
+        let mut evolution = Evolution::new(&blueprints, testing::config(8), testing::size);
+
+        let result = evolution.run_until(&StopCondition::Generations(3));
+        assert_eq!(result.reason, StopReason::Generations);
+        assert_eq!(result.generation, 3);
+
+        // already met, so no generation runs
+        let result = evolution.run_until(&StopCondition::All(vec![]));
+        assert_eq!(result.generation, 3);
+        assert_eq!(evolution.statistics.len(), 4);
+    }
+}
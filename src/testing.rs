@@ -0,0 +1,152 @@
+use petgraph::graph::NodeIndex;
+use rand::SeedableRng;
+use rand_pcg::Pcg32;
+use std::collections::HashMap;
+
+use crate::blueprints::{Blueprint, Blueprints};
+use crate::chromosome::EgpChromosome;
+use crate::evolution::Config;
+use crate::{Binding, ComponentIndex, Expressed, ExpressionConfig, OutputSharing, Phenotype};
+
+/// A single group of arithmetic blueprints, `use` weakly binding to an expressed `+`
+pub fn arithmetic() -> Blueprints {
+    let mut using = Blueprint::single_main("use");
+    using.weak_binding_sites = vec![0];
+
+    let mut regular = vec![vec![
+        Blueprint::double_main("+"),
+        Blueprint::double_main("*"),
+        Blueprint::single_main("neg"),
+        using,
+    ]];
+    let mut terminal = vec![Blueprint::terminals(&["x", "1"])];
+    Blueprints::recompute_activities(&mut regular, &mut terminal);
+
+    let mut weak_map = HashMap::new();
+    weak_map.insert(String::from("use"), String::from("+"));
+
+    Blueprints::from_groups(Blueprint::single_main("out"), regular, terminal, weak_map)
+}
+
+/// The arithmetic blueprints with two outputs
+pub fn two_outputs() -> Blueprints {
+    let mut blueprints = arithmetic();
+    blueprints.outputs = vec![
+        Blueprint::single_main("out0"),
+        Blueprint::single_main("out1"),
+    ];
+    blueprints
+}
+
+/// Expression parameters letting outputs share regular components
+pub fn shared_outputs() -> ExpressionConfig {
+    ExpressionConfig {
+        sharing: OutputSharing::Shared,
+        ..Default::default()
+    }
+}
+
+pub fn rng(seed: u64) -> Pcg32 {
+    Pcg32::seed_from_u64(seed)
+}
+
+pub fn ancestors(blueprints: &Blueprints, seed: u64, count: usize) -> Vec<EgpChromosome> {
+    let mut rng = rng(seed);
+
+    (0..count)
+        .map(|_| EgpChromosome::ancestor_from_blueprints_with_rng(&mut rng, blueprints, 30))
+        .collect()
+}
+
+/// A small seeded run
+pub fn config(seed: u64) -> Config {
+    Config {
+        population_size: 20,
+        chromosome_size: 30,
+        generations: 5,
+        seed: Some(seed),
+        ..Default::default()
+    }
+}
+
+/// Expression parameters other than the defaults
+pub fn expression() -> ExpressionConfig {
+    ExpressionConfig {
+        input_bias: 0.2,
+        ..Default::default()
+    }
+}
+
+/// Rewards larger phenotypes, any structure will do for testing the engines
+pub fn size(phenotype: &Phenotype) -> f64 {
+    phenotype.node_count() as f64
+}
+
+/// Whether two phenotypes have the same nodes and edges at the same indices
+pub fn same(a: &Phenotype, b: &Phenotype) -> bool {
+    format!("{:?}", a) == format!("{:?}", b)
+}
+
+/// Builds a phenotype from labeled nodes and (parent, child, binding) edges
+pub fn phenotype(nodes: &[(&str, ComponentIndex)], edges: &[(usize, usize, Binding)]) -> Phenotype {
+    let mut phenotype = Phenotype::new();
+
+    for (label, index) in nodes {
+        phenotype.add_node(Expressed {
+            label: String::from(*label),
+            activity: 0,
+            index: *index,
+            value: None,
+        });
+    }
+
+    for (parent, child, binding) in edges {
+        phenotype.add_edge(NodeIndex::new(*parent), NodeIndex::new(*child), *binding);
+    }
+
+    phenotype
+}
+
+/// `(* (+ x 1) (use x))` where `use` weakly binds the same `+`
+pub fn shared_sum() -> Phenotype {
+    phenotype(
+        &[
+            ("out", ComponentIndex::Output(0)),
+            ("*", ComponentIndex::Regular(0, 1)),
+            ("+", ComponentIndex::Regular(0, 0)),
+            ("x", ComponentIndex::Terminal(0, 0)),
+            ("1", ComponentIndex::Terminal(0, 1)),
+            ("use", ComponentIndex::Regular(0, 2)),
+            ("x", ComponentIndex::Terminal(0, 0)),
+        ],
+        &[
+            (0, 1, Binding::Strong(0)),
+            (1, 2, Binding::Strong(0)),
+            (1, 5, Binding::Strong(1)),
+            (2, 3, Binding::Strong(0)),
+            (2, 4, Binding::Strong(1)),
+            (5, 6, Binding::Strong(0)),
+            (5, 2, Binding::Weak(0)),
+        ],
+    )
+}
+
+/// Two outputs, `(+ x 1)` and a terminal labeled `second`
+pub fn two_roots(second: &str) -> Phenotype {
+    phenotype(
+        &[
+            ("out", ComponentIndex::Output(0)),
+            ("+", ComponentIndex::Regular(0, 0)),
+            ("x", ComponentIndex::Terminal(0, 0)),
+            ("1", ComponentIndex::Terminal(0, 1)),
+            ("out", ComponentIndex::Output(1)),
+            (second, ComponentIndex::Terminal(0, 0)),
+        ],
+        &[
+            (0, 1, Binding::Strong(0)),
+            (1, 2, Binding::Strong(0)),
+            (1, 3, Binding::Strong(1)),
+            (4, 5, Binding::Strong(0)),
+        ],
+    )
+}
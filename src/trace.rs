@@ -0,0 +1,326 @@
+use petgraph::graph::{EdgeIndex, NodeIndex};
+use petgraph::visit::EdgeRef;
+use std::fmt::Write;
+
+use crate::blueprints::Blueprints;
+use crate::chromosome::EgpChromosome;
+use crate::component::Component;
+use crate::vectors;
+use crate::{
+    component_at, expression_walk, group_of, nearest_component, Binding, ComponentIndex,
+    ExpressionConfig, Phenotype,
+};
+
+/// A component considered for a binding site
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub index: ComponentIndex,
+    pub label: String,
+    pub distance: f32,
+    /// Regulars already expressed elsewhere can't be chosen again
+    pub skipped: bool,
+}
+
+/// Explains the choice made for one edge of a phenotype
+#[derive(Debug, Clone)]
+pub struct EdgeTrace {
+    pub edge: EdgeIndex,
+    pub parent: NodeIndex,
+    pub child: NodeIndex,
+    pub binding: Binding,
+    pub binding_site: Vec<f32>,
+    /// All candidates ranked by distance, regulars and terminals together
+    pub candidates: Vec<Candidate>,
+    pub winner: ComponentIndex,
+}
+
+impl EdgeTrace {
+    pub fn is_terminal(&self) -> bool {
+        matches!(self.winner, ComponentIndex::Terminal(_, _))
+    }
+
+    pub fn is_regular(&self) -> bool {
+        matches!(self.winner, ComponentIndex::Regular(_, _))
+    }
+
+    /// Candidates skipped because they were already expressed
+    pub fn skipped(&self) -> impl Iterator<Item = &Candidate> {
+        self.candidates.iter().filter(|candidate| candidate.skipped)
+    }
+}
+
+/// A phenotype along with the reasons behind each of its edges
+pub struct ExpressionTrace {
+    pub phenotype: Phenotype,
+    /// Traces in edge order, so `edges[i].edge.index() == i`
+    pub edges: Vec<EdgeTrace>,
+}
+
+impl ExpressionTrace {
+    /// Human-readable explanation of every choice made during expression
+    pub fn explain(&self) -> String {
+        let mut result = String::new();
+
+        for trace in &self.edges {
+            let parent = &self.phenotype[trace.parent];
+            let child = &self.phenotype[trace.child];
+            let kind = match trace.winner {
+                ComponentIndex::Output(_) => "output",
+                ComponentIndex::Regular(_, _) => "regular",
+                ComponentIndex::Terminal(_, _) => "terminal",
+            };
+
+            writeln!(
+                result,
+                "{} -> {} [{:?}]: {} chosen for {:?}",
+                parent.name(),
+                child.name(),
+                trace.binding,
+                kind,
+                trace.binding_site
+            )
+            .unwrap();
+
+            for candidate in &trace.candidates {
+                let marker = if candidate.index == trace.winner {
+                    "*"
+                } else {
+                    " "
+                };
+                let skipped = if candidate.skipped {
+                    " (already expressed)"
+                } else {
+                    ""
+                };
+
+                writeln!(
+                    result,
+                    "  {} {:.4} {} {:?}{}",
+                    marker, candidate.distance, candidate.label, candidate.index, skipped
+                )
+                .unwrap();
+            }
+        }
+
+        result
+    }
+}
+
+fn candidate(
+    blueprints: &Blueprints,
+    binding: &[f32],
+    component: &Component,
+    index: ComponentIndex,
+    skipped: bool,
+    input_bias: f32,
+) -> Candidate {
+    let profile = component.profile(blueprints.total_activities, input_bias);
+
+    Candidate {
+        index,
+        label: component.label.clone(),
+        distance: vectors::distance(binding, &profile),
+        skipped,
+    }
+}
+
+fn rank(candidates: &mut [Candidate]) {
+    candidates.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+}
+
+/// Expresses a chromosome like `express`, recording why each edge was chosen
+pub fn express_traced(blueprints: &Blueprints, chromosome: &EgpChromosome) -> ExpressionTrace {
+    express_traced_with(blueprints, chromosome, &ExpressionConfig::default())
+}
+
+/// Expresses a chromosome like `express_with`, recording why each edge was chosen
+pub fn express_traced_with(
+    blueprints: &Blueprints,
+    chromosome: &EgpChromosome,
+    config: &ExpressionConfig,
+) -> ExpressionTrace {
+    let mut strong = vec![];
+
+    let walk = expression_walk(
+        blueprints,
+        chromosome,
+        config.sharing,
+        |owner, binding_index, group, expressed_regulars| {
+            let binding = &component_at(blueprints, chromosome, owner).binding_sites[binding_index];
+            let winner = nearest_component(
+                blueprints,
+                chromosome,
+                binding,
+                group,
+                expressed_regulars,
+                config.input_bias,
+            );
+
+            let regulars =
+                chromosome.regular[group]
+                    .iter()
+                    .enumerate()
+                    .map(|(index, component)| {
+                        candidate(
+                            blueprints,
+                            binding,
+                            component,
+                            ComponentIndex::Regular(group, index),
+                            expressed_regulars.contains(&ComponentIndex::Regular(group, index)),
+                            config.input_bias,
+                        )
+                    });
+
+            let terminals =
+                blueprints.terminal[group]
+                    .iter()
+                    .enumerate()
+                    .map(|(index, component)| {
+                        candidate(
+                            blueprints,
+                            binding,
+                            component,
+                            ComponentIndex::Terminal(group, index),
+                            false,
+                            config.input_bias,
+                        )
+                    });
+
+            let mut candidates: Vec<Candidate> = regulars.chain(terminals).collect();
+            rank(&mut candidates);
+
+            strong.push((binding.clone(), candidates, winner));
+
+            winner
+        },
+    );
+
+    let offering = walk.weak_offering.clone();
+    let phenotype = walk.finish(blueprints, chromosome, config.input_bias);
+
+    // strong edges are added in the order of choices, weak edges after them
+    let edges = phenotype
+        .edge_references()
+        .map(|edge| {
+            let (binding_site, candidates, winner) = match *edge.weight() {
+                Binding::Strong(_) => strong[edge.id().index()].clone(),
+                Binding::Weak(index) => {
+                    let looking =
+                        component_at(blueprints, chromosome, phenotype[edge.source()].index);
+                    let binding = &looking.weak_binding_sites[index];
+                    let group = looking.weak_binding_sites_groups[index];
+
+                    let mut candidates: Vec<Candidate> = offering
+                        .iter()
+                        .filter(|node| group_of(phenotype[**node].index) == group)
+                        .map(|node| {
+                            let index = phenotype[*node].index;
+                            let component = component_at(blueprints, chromosome, index);
+                            candidate(
+                                blueprints,
+                                binding,
+                                component,
+                                index,
+                                false,
+                                config.input_bias,
+                            )
+                        })
+                        .collect();
+                    rank(&mut candidates);
+
+                    (binding.clone(), candidates, phenotype[edge.target()].index)
+                }
+            };
+
+            EdgeTrace {
+                edge: edge.id(),
+                parent: edge.source(),
+                child: edge.target(),
+                binding: *edge.weight(),
+                binding_site,
+                candidates,
+                winner,
+            }
+        })
+        .collect();
+
+    ExpressionTrace { phenotype, edges }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::{express, express_with};
+
+    #[test]
+    fn traced_phenotype_matches_express() {
+        let blueprints = testing::arithmetic();
+
+        for chromosome in testing::ancestors(&blueprints, 1, 20) {
+            let trace = express_traced(&blueprints, &chromosome);
+            assert!(testing::same(
+                &trace.phenotype,
+                &express(&blueprints, &chromosome)
+            ));
+            assert_eq!(trace.edges.len(), trace.phenotype.edge_count());
+
+            for (i, edge) in trace.edges.iter().enumerate() {
+                assert_eq!(edge.edge.index(), i);
+                assert_eq!(trace.phenotype[edge.child].index, edge.winner);
+                assert!(edge
+                    .candidates
+                    .windows(2)
+                    .all(|pair| pair[0].distance <= pair[1].distance));
+            }
+        }
+    }
+
+    #[test]
+    fn rank_orders_nan_last() {
+        let candidate = |distance| Candidate {
+            index: ComponentIndex::Terminal(0, 0),
+            label: String::from("x"),
+            distance,
+            skipped: false,
+        };
+        let mut candidates = vec![candidate(f32::NAN), candidate(2.), candidate(1.)];
+
+        rank(&mut candidates);
+
+        assert_eq!(candidates[0].distance, 1.);
+        assert_eq!(candidates[1].distance, 2.);
+        assert!(candidates[2].distance.is_nan());
+    }
+
+    #[test]
+    fn traced_phenotype_matches_express_with() {
+        let blueprints = testing::arithmetic();
+        let config = testing::expression();
+
+        for chromosome in testing::ancestors(&blueprints, 2, 20) {
+            let trace = express_traced_with(&blueprints, &chromosome, &config);
+            assert!(testing::same(
+                &trace.phenotype,
+                &express_with(&blueprints, &chromosome, &config)
+            ));
+        }
+    }
+
+    #[test]
+    fn traced_phenotype_keeps_shared_outputs() {
+        let blueprints = testing::two_outputs();
+        let config = testing::shared_outputs();
+
+        for chromosome in testing::ancestors(&blueprints, 17, 20) {
+            let trace = express_traced_with(&blueprints, &chromosome, &config);
+            assert!(testing::same(
+                &trace.phenotype,
+                &express_with(&blueprints, &chromosome, &config)
+            ));
+            for edge in &trace.edges {
+                assert_eq!(trace.phenotype[edge.child].index, edge.winner);
+            }
+        }
+    }
+}
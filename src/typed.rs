@@ -0,0 +1,499 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::blueprints::{Blueprint, Blueprints};
+use crate::chromosome::EgpChromosome;
+use crate::{component_at, group_of, Binding, ComponentIndex, Phenotype};
+
+/// A type of a strongly typed setup, naming its group
+///
+/// Building blueprints with `TypedBlueprint::constant`, `unary` and `binary` turns misspelled
+/// type names into compile errors.
+pub trait GpType {
+    const NAME: &'static str;
+}
+
+pub struct Bool;
+pub struct Float;
+pub struct Int;
+
+impl GpType for Bool {
+    const NAME: &'static str = "Bool";
+}
+
+impl GpType for Float {
+    const NAME: &'static str = "Float";
+}
+
+impl GpType for Int {
+    const NAME: &'static str = "Int";
+}
+
+/// A blueprint whose return and argument types are given by name instead of group index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypedBlueprint {
+    pub label: String,
+    /// For outputs, the type of the single argument they pass on
+    pub returns: String,
+    pub arguments: Vec<String>,
+    pub weak_arguments: Vec<String>,
+}
+
+impl TypedBlueprint {
+    pub fn new(label: &str, returns: &str, arguments: &[&str]) -> TypedBlueprint {
+        TypedBlueprint {
+            label: String::from(label),
+            returns: String::from(returns),
+            arguments: arguments.iter().map(|a| String::from(*a)).collect(),
+            weak_arguments: vec![],
+        }
+    }
+
+    pub fn terminal(label: &str, returns: &str) -> TypedBlueprint {
+        TypedBlueprint::new(label, returns, &[])
+    }
+
+    /// An output passing on an argument of the given type
+    pub fn output(label: &str, returns: &str) -> TypedBlueprint {
+        TypedBlueprint::new(label, returns, &[returns])
+    }
+
+    pub fn with_weak(mut self, weak_arguments: &[&str]) -> TypedBlueprint {
+        self.weak_arguments = weak_arguments.iter().map(|a| String::from(*a)).collect();
+        self
+    }
+
+    pub fn constant<R: GpType>(label: &str) -> TypedBlueprint {
+        TypedBlueprint::terminal(label, R::NAME)
+    }
+
+    pub fn unary<R: GpType, A: GpType>(label: &str) -> TypedBlueprint {
+        TypedBlueprint::new(label, R::NAME, &[A::NAME])
+    }
+
+    pub fn binary<R: GpType, A: GpType, B: GpType>(label: &str) -> TypedBlueprint {
+        TypedBlueprint::new(label, R::NAME, &[A::NAME, B::NAME])
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TypeError {
+    /// A type was declared twice
+    DuplicateType(String),
+    /// Two blueprints have the same label, so weak maps couldn't tell their types apart
+    DuplicateLabel(String),
+    /// A blueprint refers to a type that wasn't declared
+    UnknownType(String, String), // label, type
+    /// A binding site needs a type without terminals, so expression couldn't always satisfy it
+    NoTerminal(String),
+    /// An output doesn't pass on a single argument of its declared type
+    OutputMismatch(String, String), // label, type
+    /// The weak map sends a weak binding site to a label of another type, or to no blueprint
+    WeakMismatch(String, String), // looking label, offering label
+    /// An expressed node is bound to a node of the wrong type
+    Mismatch {
+        parent: String,
+        binding: Binding,
+        expected: String,
+        found: String,
+    },
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeError::DuplicateType(name) => write!(f, "type {} declared twice", name),
+            TypeError::DuplicateLabel(label) => write!(f, "label {} used twice", label),
+            TypeError::UnknownType(label, name) => {
+                write!(f, "{} refers to unknown type {}", label, name)
+            }
+            TypeError::NoTerminal(name) => write!(f, "type {} has no terminal", name),
+            TypeError::OutputMismatch(label, name) => write!(
+                f,
+                "output {} must pass on a single argument of type {}",
+                label, name
+            ),
+            TypeError::WeakMismatch(looking, offering) => write!(
+                f,
+                "weak binding sites of {} can't bind {}",
+                looking, offering
+            ),
+            TypeError::Mismatch {
+                parent,
+                binding,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} binding {:?} expects {}, found {}",
+                parent, binding, expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TypeError {}
+
+/// Blueprints with one group per declared type
+///
+/// A blueprint goes into the group of its return type and its binding sites into the groups of
+/// its argument types. Expression only searches the group of a binding site, strong and weak, so
+/// every phenotype is well typed.
+#[derive(Debug, Clone)]
+pub struct TypedBlueprints {
+    /// Type of each group, in group order
+    pub types: Vec<String>,
+    pub blueprints: Blueprints,
+}
+
+impl TypedBlueprints {
+    /// Checks the declarations and assigns groups
+    ///
+    /// Labels must be unique among outputs and blueprints. Blueprints without strong or weak
+    /// arguments become terminals. `weak_map` maps labels like
+    /// in `Blueprints`, the offering label must have the type of every weak argument.
+    pub fn new(
+        types: &[&str],
+        outputs: Vec<TypedBlueprint>,
+        blueprints: Vec<TypedBlueprint>,
+        weak_map: HashMap<String, String>,
+    ) -> Result<TypedBlueprints, TypeError> {
+        let mut groups = HashMap::new();
+        for (group, name) in types.iter().enumerate() {
+            if groups.insert(*name, group).is_some() {
+                return Err(TypeError::DuplicateType(String::from(*name)));
+            }
+        }
+
+        let group = |label: &str, name: &str| {
+            groups
+                .get(name)
+                .cloned()
+                .ok_or_else(|| TypeError::UnknownType(String::from(label), String::from(name)))
+        };
+        let groups_of = |typed: &TypedBlueprint, names: &[String]| {
+            names
+                .iter()
+                .map(|name| group(&typed.label, name))
+                .collect::<Result<Vec<usize>, TypeError>>()
+        };
+
+        let mut labels = HashSet::new();
+        for typed in outputs.iter().chain(&blueprints) {
+            if !labels.insert(typed.label.as_str()) {
+                return Err(TypeError::DuplicateLabel(typed.label.clone()));
+            }
+        }
+
+        let mut regular = vec![vec![]; types.len()];
+        let mut terminal = vec![vec![]; types.len()];
+        let mut returns = HashMap::new();
+
+        for typed in &blueprints {
+            let blueprint = Blueprint {
+                activity: 0,
+                label: typed.label.clone(),
+                binding_sites: groups_of(typed, &typed.arguments)?,
+                weak_binding_sites: groups_of(typed, &typed.weak_arguments)?,
+                ephemeral: None,
+            };
+            let returned = group(&typed.label, &typed.returns)?;
+            returns.insert(typed.label.as_str(), returned);
+
+            if blueprint.binding_sites.is_empty() && blueprint.weak_binding_sites.is_empty() {
+                terminal[returned].push(blueprint);
+            } else {
+                regular[returned].push(blueprint);
+            }
+        }
+
+        let outputs = outputs
+            .iter()
+            .map(|typed| {
+                let returned = group(&typed.label, &typed.returns)?;
+                let binding_sites = groups_of(typed, &typed.arguments)?;
+                if binding_sites != [returned] {
+                    return Err(TypeError::OutputMismatch(
+                        typed.label.clone(),
+                        typed.returns.clone(),
+                    ));
+                }
+
+                Ok(Blueprint {
+                    activity: 0,
+                    label: typed.label.clone(),
+                    binding_sites,
+                    weak_binding_sites: groups_of(typed, &typed.weak_arguments)?,
+                    ephemeral: None,
+                })
+            })
+            .collect::<Result<Vec<Blueprint>, TypeError>>()?;
+
+        for blueprint in outputs.iter().chain(regular.iter().flatten()) {
+            if let Some(group) = blueprint
+                .binding_sites
+                .iter()
+                .find(|group| terminal[**group].is_empty())
+            {
+                return Err(TypeError::NoTerminal(types[*group].to_string()));
+            }
+
+            if !blueprint.weak_binding_sites.is_empty() {
+                let mismatch = || {
+                    let offering = weak_map.get(&blueprint.label).cloned().unwrap_or_default();
+                    TypeError::WeakMismatch(blueprint.label.clone(), offering)
+                };
+                let offered = weak_map
+                    .get(&blueprint.label)
+                    .and_then(|offering| returns.get(offering.as_str()))
+                    .ok_or_else(mismatch)?;
+
+                if blueprint.weak_binding_sites.iter().any(|g| g != offered) {
+                    return Err(mismatch());
+                }
+            }
+        }
+
+        Blueprints::recompute_activities(&mut regular, &mut terminal);
+
+        Ok(TypedBlueprints {
+            types: types.iter().map(|name| String::from(*name)).collect(),
+            blueprints: Blueprints::with_outputs(outputs, regular, terminal, weak_map),
+        })
+    }
+
+    /// Group of a type
+    pub fn group(&self, name: &str) -> Option<usize> {
+        self.types.iter().position(|t| t == name)
+    }
+
+    /// Type of an expressed component, `None` for outputs
+    pub fn type_of(&self, index: ComponentIndex) -> Option<&str> {
+        match index {
+            ComponentIndex::Output(_) => None,
+            _ => Some(&self.types[group_of(index)]),
+        }
+    }
+
+    /// Verifies that every binding of a phenotype connects the types its site requires
+    pub fn check(
+        &self,
+        chromosome: &EgpChromosome,
+        phenotype: &Phenotype,
+    ) -> Result<(), TypeError> {
+        for edge in phenotype.raw_edges() {
+            let parent = &phenotype[edge.source()];
+            let child = &phenotype[edge.target()];
+            let component = component_at(&self.blueprints, chromosome, parent.index);
+
+            let expected = match edge.weight {
+                Binding::Strong(site) => component.binding_sites_groups[site],
+                Binding::Weak(site) => component.weak_binding_sites_groups[site],
+            };
+            let found = group_of(child.index);
+
+            if expected != found || matches!(child.index, ComponentIndex::Output(_)) {
+                return Err(TypeError::Mismatch {
+                    parent: parent.label.clone(),
+                    binding: edge.weight,
+                    expected: self.types[expected].clone(),
+                    found: self.type_of(child.index).unwrap_or("output").to_string(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::{express, express_with};
+
+    fn typed(blueprints: Vec<TypedBlueprint>) -> Result<TypedBlueprints, TypeError> {
+        TypedBlueprints::new(
+            &[Float::NAME, Bool::NAME],
+            vec![TypedBlueprint::output("out", Float::NAME)],
+            blueprints,
+            HashMap::new(),
+        )
+    }
+
+    /// Arithmetic and comparisons joined by a conditional
+    fn conditional() -> TypedBlueprints {
+        typed(vec![
+            TypedBlueprint::binary::<Float, Float, Float>("+"),
+            TypedBlueprint::binary::<Float, Float, Float>("*"),
+            TypedBlueprint::new("if", Float::NAME, &[Bool::NAME, Float::NAME, Float::NAME]),
+            TypedBlueprint::constant::<Float>("x"),
+            TypedBlueprint::constant::<Float>("1"),
+            TypedBlueprint::binary::<Bool, Float, Float>("<"),
+            TypedBlueprint::binary::<Bool, Bool, Bool>("and"),
+            TypedBlueprint::unary::<Bool, Bool>("not"),
+            TypedBlueprint::constant::<Bool>("true"),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn declarations_are_checked() {
+        let duplicate = TypedBlueprints::new(&["Float", "Float"], vec![], vec![], HashMap::new());
+        assert_eq!(
+            duplicate.unwrap_err(),
+            TypeError::DuplicateType(String::from("Float"))
+        );
+
+        let unknown = typed(vec![TypedBlueprint::unary::<Int, Float>("round")]);
+        assert_eq!(
+            unknown.unwrap_err(),
+            TypeError::UnknownType(String::from("round"), String::from("Int"))
+        );
+
+        let no_terminal = typed(vec![
+            TypedBlueprint::constant::<Float>("x"),
+            TypedBlueprint::unary::<Float, Bool>("float"),
+        ]);
+        assert_eq!(
+            no_terminal.unwrap_err(),
+            TypeError::NoTerminal(String::from("Bool"))
+        );
+
+        let mut weak_map = HashMap::new();
+        weak_map.insert(String::from("use"), String::from("true"));
+        let weak = TypedBlueprints::new(
+            &[Float::NAME, Bool::NAME],
+            vec![TypedBlueprint::output("out", Float::NAME)],
+            vec![
+                TypedBlueprint::constant::<Float>("x"),
+                TypedBlueprint::constant::<Bool>("true"),
+                TypedBlueprint::unary::<Float, Float>("use").with_weak(&[Float::NAME]),
+            ],
+            weak_map,
+        );
+        assert_eq!(
+            weak.unwrap_err(),
+            TypeError::WeakMismatch(String::from("use"), String::from("true"))
+        );
+    }
+
+    #[test]
+    fn outputs_are_checked() {
+        let output = |output: TypedBlueprint| {
+            TypedBlueprints::new(
+                &[Float::NAME, Bool::NAME],
+                vec![output],
+                vec![
+                    TypedBlueprint::constant::<Float>("x"),
+                    TypedBlueprint::constant::<Bool>("true"),
+                ],
+                HashMap::new(),
+            )
+        };
+
+        assert!(output(TypedBlueprint::output("out", Bool::NAME)).is_ok());
+        assert_eq!(
+            output(TypedBlueprint::output("out", Int::NAME)).unwrap_err(),
+            TypeError::UnknownType(String::from("out"), String::from("Int"))
+        );
+        assert_eq!(
+            output(TypedBlueprint::new("out", Float::NAME, &[Bool::NAME])).unwrap_err(),
+            TypeError::OutputMismatch(String::from("out"), String::from("Float"))
+        );
+        assert_eq!(
+            output(TypedBlueprint::new(
+                "out",
+                Float::NAME,
+                &[Float::NAME, Float::NAME]
+            ))
+            .unwrap_err(),
+            TypeError::OutputMismatch(String::from("out"), String::from("Float"))
+        );
+    }
+
+    #[test]
+    fn labels_are_unique() {
+        let duplicate = typed(vec![
+            TypedBlueprint::constant::<Float>("x"),
+            TypedBlueprint::constant::<Bool>("x"),
+        ]);
+        assert_eq!(
+            duplicate.unwrap_err(),
+            TypeError::DuplicateLabel(String::from("x"))
+        );
+
+        let output = typed(vec![TypedBlueprint::constant::<Float>("out")]);
+        assert_eq!(
+            output.unwrap_err(),
+            TypeError::DuplicateLabel(String::from("out"))
+        );
+    }
+
+    #[test]
+    fn blueprints_go_to_the_group_of_their_type() {
+        let typed = conditional();
+        let blueprints = &typed.blueprints;
+
+        assert_eq!(typed.group("Bool"), Some(1));
+        assert_eq!(blueprints.regular[0].len(), 3);
+        assert_eq!(blueprints.regular[1].len(), 3);
+        assert_eq!(blueprints.terminal[1].len(), 1);
+        assert_eq!(blueprints.regular[0][2].binding_sites, vec![1, 0, 0]);
+        assert_eq!(
+            typed.type_of(ComponentIndex::Terminal(1, 0)),
+            Some(Bool::NAME)
+        );
+    }
+
+    #[test]
+    fn phenotypes_are_well_typed() {
+        let typed = conditional();
+        let mut both_groups = false;
+
+        for chromosome in testing::ancestors(&typed.blueprints, 18, 50) {
+            let phenotype = express(&typed.blueprints, &chromosome);
+            assert_eq!(typed.check(&chromosome, &phenotype), Ok(()));
+            assert_eq!(
+                typed.check(
+                    &chromosome,
+                    &express_with(&typed.blueprints, &chromosome, &testing::expression())
+                ),
+                Ok(())
+            );
+
+            // a regular of one group doesn't block the same position in another
+            both_groups |= phenotype.node_weights().any(|a| {
+                phenotype.node_weights().any(|b| match (a.index, b.index) {
+                    (ComponentIndex::Regular(0, i), ComponentIndex::Regular(1, j)) => i == j,
+                    _ => false,
+                })
+            });
+        }
+
+        assert!(both_groups);
+    }
+
+    #[test]
+    fn check_finds_mismatched_bindings() {
+        let typed = conditional();
+        let chromosome = testing::ancestors(&typed.blueprints, 19, 1).remove(0);
+        let phenotype = testing::phenotype(
+            &[
+                ("out", ComponentIndex::Output(0)),
+                ("true", ComponentIndex::Terminal(1, 0)),
+            ],
+            &[(0, 1, Binding::Strong(0))],
+        );
+
+        assert_eq!(
+            typed.check(&chromosome, &phenotype),
+            Err(TypeError::Mismatch {
+                parent: String::from("out"),
+                binding: Binding::Strong(0),
+                expected: String::from("Float"),
+                found: String::from("Bool"),
+            })
+        );
+    }
+}